@@ -0,0 +1,978 @@
+// @ts-check
+/**
+ * SpacetimeDB TypeScript SDK Bridge for Rust WASM
+ *
+ * This bridge allows Rust WASM code to interact with the SpacetimeDB TypeScript SDK.
+ * `bevy_spacetimedb_wasm` imports it through wasm-bindgen and creates the bridge
+ * itself, so nothing has to be set up in the page.
+ *
+ * The module imports `@clockworklabs/spacetimedb-sdk`, which the page resolves
+ * through a bundler or an import map. No generated bindings are needed: `connect`
+ * fetches the module's schema from the server and builds the SDK's table and
+ * reducer types from it, keeping the names the module gives its columns.
+ *
+ * Without generated bindings, the bridge relies on parts of the SDK that are
+ * exported but not documented: the remote module shape, `SubscriptionBuilderImpl`,
+ * `BinaryWriter`, `clientCache.getOrCreateTable` and `callReducer`. It targets
+ * the SDK version pinned in `package.json` (and `SDK_VERSION` below); check them
+ * again before moving the pin.
+ *
+ * With the crate's `global-bridge` feature, Rust uses the bridge found in
+ * `__SPACETIMEDB_BRIDGE__` instead, which the page creates BEFORE loading WASM:
+ * ```html
+ * <script type="module">
+ *   import { SpacetimeDBBridge } from './js/spacetimedb-bridge.js';
+ *   window.__SPACETIMEDB_BRIDGE__ = new SpacetimeDBBridge();
+ *
+ *   // Then load your WASM module
+ *   import init from './pkg/my_game.js';
+ *   await init();
+ * </script>
+ * ```
+ *
+ * Written as JavaScript with JSDoc types so wasm-bindgen can ship it without a
+ * build step; `npm run check` type-checks it.
+ */
+
+import {
+    AlgebraicType,
+    BinaryWriter,
+    ConnectionId,
+    DbConnectionBuilder,
+    Identity,
+    ProductTypeElement,
+    SubscriptionBuilderImpl,
+    SumTypeVariant,
+    TimeDuration,
+    Timestamp,
+} from '@clockworklabs/spacetimedb-sdk';
+
+/**
+ * The SDK version this bridge targets, as pinned in `package.json`
+ *
+ * Bindings tell the SDK which CLI generated them; the bridge claims the
+ * matching version.
+ */
+const SDK_VERSION = '1.3.3';
+
+/**
+ * @param {object} value
+ * @returns {boolean}
+ */
+function isPlainObject(value) {
+    const proto = Object.getPrototypeOf(value);
+    return proto === Object.prototype || proto === null;
+}
+
+/**
+ * Convert SDK class instances in a row or argument list into the plain objects
+ * the Rust side deserializes (see `types.rs`):
+ *
+ * - `Identity`     -> `{ __identity__: "<hex>" }`
+ * - `ConnectionId` -> `{ __connection_id__: "<decimal>" }`
+ * - `Timestamp`    -> `{ __timestamp_micros_since_unix_epoch__: bigint }`
+ * - `TimeDuration` -> `{ __time_duration_micros__: bigint }`
+ *
+ * Arrays and plain objects are copied structurally; everything else (primitives,
+ * typed arrays, Maps, other class instances) is kept as-is.
+ *
+ * @param {any} value
+ * @returns {any}
+ */
+export function normalizeValue(value) {
+    if (value === null || typeof value !== 'object' || ArrayBuffer.isView(value)) {
+        return value;
+    }
+    if (value instanceof Identity) {
+        return { __identity__: value.toHexString() };
+    }
+    if (value instanceof ConnectionId) {
+        // A string, since connection ids usually don't fit in 64 bits
+        return { __connection_id__: value.__connection_id__.toString() };
+    }
+    if (value instanceof Timestamp) {
+        return { __timestamp_micros_since_unix_epoch__: value.__timestamp_micros_since_unix_epoch__ };
+    }
+    if (value instanceof TimeDuration) {
+        return { __time_duration_micros__: value.__time_duration_micros__ };
+    }
+    if (Array.isArray(value)) {
+        return value.map(normalizeValue);
+    }
+    if (!isPlainObject(value)) {
+        return value;
+    }
+    /** @type {Record<string, any>} */
+    const result = {};
+    for (const key of Object.keys(value)) {
+        result[key] = normalizeValue(value[key]);
+    }
+    return result;
+}
+
+/**
+ * Inverse of `normalizeValue`: turn the plain objects produced by Rust back into
+ * SDK class instances before passing reducer arguments to the SDK.
+ *
+ * @param {any} value
+ * @returns {any}
+ */
+export function denormalizeValue(value) {
+    if (value === null || typeof value !== 'object' || ArrayBuffer.isView(value)) {
+        return value;
+    }
+    if (Array.isArray(value)) {
+        return value.map(denormalizeValue);
+    }
+    if (!isPlainObject(value)) {
+        return value;
+    }
+    const keys = Object.keys(value);
+    if (keys.length === 1) {
+        switch (keys[0]) {
+            case '__identity__':
+                return Identity.fromString(value.__identity__);
+            case '__connection_id__':
+                return new ConnectionId(BigInt(value.__connection_id__));
+            case '__timestamp_micros_since_unix_epoch__':
+                return new Timestamp(BigInt(value.__timestamp_micros_since_unix_epoch__));
+            case '__time_duration_micros__':
+                return new TimeDuration(BigInt(value.__time_duration_micros__));
+        }
+    }
+    /** @type {Record<string, any>} */
+    const result = {};
+    for (const key of keys) {
+        result[key] = denormalizeValue(value[key]);
+    }
+    return result;
+}
+
+/**
+ * Build the SDK type of a SATS `AlgebraicType`, given in the JSON form of
+ * module definitions (e.g. `{ "U32": [] }`, `{ "Ref": 0 }`)
+ *
+ * @param {any} json
+ * @param {any[]} typespace
+ * @returns {AlgebraicType}
+ */
+export function algebraicType(json, typespace) {
+    const [tag, value] = Object.entries(json)[0];
+    const A = /** @type {any} */ (AlgebraicType);
+    switch (tag) {
+        case 'Ref':
+            return algebraicType(typespace[value], typespace);
+        case 'Product':
+            return A.createProductType(value.elements.map((/** @type {any} */ element, /** @type {number} */ i) =>
+                new ProductTypeElement(element.name?.some ?? String(i), algebraicType(element.algebraic_type, typespace))));
+        case 'Sum':
+            return A.createSumType(value.variants.map((/** @type {any} */ variant, /** @type {number} */ i) =>
+                new SumTypeVariant(variant.name?.some ?? String(i), algebraicType(variant.algebraic_type, typespace))));
+        case 'Array':
+            return A.createArrayType(algebraicType(value, typespace));
+        case 'Map':
+            return A.createMapType(algebraicType(value.key_ty, typespace), algebraicType(value.ty, typespace));
+        default: {
+            // Primitives: `createU32Type`, `createStringType`, ...
+            const create = A[`create${tag}Type`];
+            if (typeof create !== 'function') {
+                throw new Error(`Unsupported type in module schema: ${tag}`);
+            }
+            return create.call(A);
+        }
+    }
+}
+
+/**
+ * Build what generated bindings call the remote module, from a module
+ * definition (`RawModuleDefV9`)
+ *
+ * Tables and reducers keep the names the module gives them, so rows and
+ * arguments have the module's (snake_case) field names, as on the Rust side.
+ *
+ * @param {any} def
+ * @returns {any}
+ */
+export function remoteModule(def) {
+    const typespace = def.typespace.types;
+
+    /** @type {Record<string, any>} */
+    const tables = {};
+    for (const table of def.tables) {
+        const rowType = /** @type {any} */ (algebraicType({ Ref: table.product_type_ref }, typespace));
+        /** @type {Record<string, any>} */
+        const info = { tableName: table.name, rowType };
+        const [primaryKey] = table.primary_key ?? [];
+        if (primaryKey !== undefined) {
+            const element = rowType.product.elements[primaryKey];
+            info.primaryKey = element.name;
+            info.primaryKeyInfo = { colName: element.name, colType: element.algebraicType };
+        }
+        tables[table.name] = info;
+    }
+
+    /** @type {Record<string, any>} */
+    const reducers = {};
+    for (const reducer of def.reducers) {
+        reducers[reducer.name] = {
+            reducerName: reducer.name,
+            argsType: algebraicType({ Product: reducer.params }, typespace),
+        };
+    }
+
+    return {
+        tables,
+        reducers,
+        versionInfo: { cliVersion: SDK_VERSION },
+        eventContextConstructor: (/** @type {any} */ imp, /** @type {any} */ event) => ({ ...imp, event }),
+        dbViewConstructor: () => ({}),
+        reducersConstructor: () => ({}),
+        setReducerFlagsConstructor: () => ({}),
+    };
+}
+
+/**
+ * Fetch the definition of a module from the server's HTTP API
+ *
+ * @param {string} uri
+ * @param {string} moduleName
+ * @returns {Promise<any>}
+ */
+async function fetchModuleDef(uri, moduleName) {
+    const base = uri.replace(/^ws(s?):/, 'http$1:').replace(/\/+$/, '');
+    const url = `${base}/v1/database/${encodeURIComponent(moduleName)}/schema?version=9`;
+    const response = await fetch(url);
+    if (!response.ok) {
+        throw new Error(`Failed to fetch the schema of ${moduleName}: ${response.status} ${await response.text()}`);
+    }
+    const def = await response.json();
+    return def.V9 ?? def;
+}
+
+/**
+ * @param {any} error
+ * @returns {string}
+ */
+function errorMessage(error) {
+    return error?.message ?? String(error ?? 'Unknown error');
+}
+
+/**
+ * Callback function type for Rust WASM
+ *
+ * @typedef {(...args: any[]) => void} WasmCallback
+ */
+
+/**
+ * Data structure for table events passed to Rust
+ *
+ * Passed as a plain JS object (not a JSON string) so Rust can deserialize
+ * rows directly into its own types. `BigInt` columns (u64/i64/u128/i128)
+ * are passed through untouched.
+ *
+ * @typedef {object} TableEventData
+ * @property {any} [row]
+ * @property {any} [oldRow]
+ * @property {any} [newRow]
+ * @property {EventContextData} event
+ */
+
+/**
+ * What caused a table event, deserialized as `EventContext` on the Rust side
+ *
+ * - `reducer`: a reducer call (special types normalized with `normalizeValue`)
+ * - `subscription`: part of the initial rows of a subscription being applied
+ * - `unsubscription`: a row leaving the client's scope after an unsubscribe
+ * - `unknown`: anything else
+ *
+ * @typedef {{
+ *     kind: 'reducer',
+ *     reducerName: string,
+ *     callerIdentity: any,
+ *     callerConnectionId?: any,
+ *     timestamp?: any,
+ *     args: any,
+ * } | { kind: 'subscription' } | { kind: 'unsubscription' } | { kind: 'unknown' }} EventContextData
+ */
+
+/**
+ * Data structure for reducer events passed to Rust
+ *
+ * `event` is deserialized as `ReducerEvent` on the Rust side, `args` is the
+ * array of the reducer's arguments (special types normalized with `normalizeValue`).
+ *
+ * @typedef {object} ReducerEventData
+ * @property {{
+ *     reducerName: string,
+ *     callerIdentity: any,
+ *     callerConnectionId?: any,
+ *     timestamp?: any,
+ *     status: ReducerStatusData,
+ * }} event
+ * @property {any[]} args
+ */
+
+/**
+ * The outcome of a reducer run, deserialized as `ReducerStatus` on the Rust side
+ *
+ * @typedef {{ tag: 'Committed' } | { tag: 'Failed', value: string } | { tag: 'OutOfEnergy' }} ReducerStatusData
+ */
+
+/**
+ * Reduce the SDK's reducer status to what Rust needs (dropping committed rows)
+ *
+ * @param {any} status
+ * @returns {ReducerStatusData}
+ */
+function reducerStatus(status) {
+    switch (status?.tag) {
+        case 'Failed':
+            return { tag: 'Failed', value: String(status.value) };
+        case 'OutOfEnergy':
+            return { tag: 'OutOfEnergy' };
+        default:
+            return { tag: 'Committed' };
+    }
+}
+
+/**
+ * A subscription started through the bridge
+ *
+ * @typedef {object} SubscriptionEntry
+ * @property {number} connectionId
+ * @property {string[]} queries
+ * @property {{ unsubscribeThen(onEnd: () => void): void }} handle
+ */
+
+/**
+ * A connection created through the bridge
+ *
+ * The SDK connection is only built by `connect`, once the module's schema is
+ * known, so table and reducer listeners are kept in `setup` until then.
+ *
+ * @typedef {object} ConnectionEntry
+ * @property {string} uri
+ * @property {string} moduleName
+ * @property {string | undefined} token
+ * @property {any} [conn] The SDK connection, once built
+ * @property {any} [module] The remote module built from the schema
+ * @property {Array<(conn: any, module: any) => void>} setup
+ * @property {WasmCallback[]} connectCallbacks
+ * @property {WasmCallback[]} disconnectCallbacks
+ * @property {WasmCallback[]} errorCallbacks
+ * @property {Map<string, Array<{ resolve: () => void, reject: (error: string) => void }>>} pendingCalls
+ *     Reducer calls waiting for their outcome, by reducer name
+ */
+
+/**
+ * Bridge class that connects Rust WASM to the SpacetimeDB TypeScript SDK
+ */
+export class SpacetimeDBBridge {
+    constructor() {
+        /** @type {Map<number, ConnectionEntry>} */
+        this.connections = new Map();
+        this.nextConnectionId = 0;
+        /** @type {Map<number, WasmCallback>} */
+        this.callbacks = new Map();
+        this.nextCallbackId = 0;
+        /**
+         * Active subscriptions by subscription ID
+         * @type {Map<number, SubscriptionEntry>}
+         */
+        this.subscriptions = new Map();
+        this.nextSubscriptionId = 0;
+        /**
+         * Callbacks notified when a subscription has been applied, per connection
+         * @type {Map<number, WasmCallback[]>}
+         */
+        this.subscriptionAppliedCallbacks = new Map();
+        /**
+         * Callbacks notified when a subscription fails, per connection
+         * @type {Map<number, WasmCallback[]>}
+         */
+        this.subscriptionErrorCallbacks = new Map();
+        /**
+         * Callbacks notified when a subscription has ended after an unsubscribe, per connection
+         * @type {Map<number, WasmCallback[]>}
+         */
+        this.subscriptionEndedCallbacks = new Map();
+
+        console.log('[SpacetimeDB Bridge] Initialized');
+    }
+
+    /**
+     * Create a new connection to SpacetimeDB
+     *
+     * Nothing is sent until `connect`.
+     *
+     * @param {string} uri
+     * @param {string} moduleName
+     * @param {string | null} authToken
+     * @returns {number}
+     */
+    createConnection(uri, moduleName, authToken) {
+        const id = this.nextConnectionId++;
+        this.connections.set(id, {
+            uri,
+            moduleName,
+            token: authToken || undefined,
+            setup: [],
+            connectCallbacks: [],
+            disconnectCallbacks: [],
+            errorCallbacks: [],
+            pendingCalls: new Map(),
+        });
+        console.log(`[SpacetimeDB Bridge] Created connection ${id} to ${uri}/${moduleName}`);
+        return id;
+    }
+
+    /**
+     * @private
+     * @param {number} connectionId
+     * @returns {ConnectionEntry}
+     */
+    entry(connectionId) {
+        const entry = this.connections.get(connectionId);
+        if (!entry) {
+            throw new Error(`Invalid connection ID: ${connectionId}`);
+        }
+        return entry;
+    }
+
+    /**
+     * Run `setup` on the SDK connection now if there is one, and on every one built later
+     *
+     * @private
+     * @param {ConnectionEntry} entry
+     * @param {(conn: any, module: any) => void} setup
+     */
+    addSetup(entry, setup) {
+        entry.setup.push(setup);
+        if (entry.conn) {
+            setup(entry.conn, entry.module);
+        }
+    }
+
+    /**
+     * Connect to the SpacetimeDB server
+     *
+     * Fetches the module's schema, then opens the SDK connection. Resolves once
+     * the server has sent the identity, rejects with the error message otherwise.
+     *
+     * @param {number} connectionId
+     * @returns {Promise<void>}
+     */
+    async connect(connectionId) {
+        const entry = this.entry(connectionId);
+        console.log(`[SpacetimeDB Bridge] Connecting ${connectionId}...`);
+
+        const fail = (/** @type {any} */ error) => {
+            const message = errorMessage(error);
+            console.error(`[SpacetimeDB Bridge] Connection ${connectionId} error:`, message);
+            for (const callback of entry.errorCallbacks) {
+                callback(message);
+            }
+            return message;
+        };
+
+        let module;
+        try {
+            module = remoteModule(await fetchModuleDef(entry.uri, entry.moduleName));
+        } catch (error) {
+            throw fail(error);
+        }
+
+        // Calls made on an earlier connection listen to that one
+        entry.pendingCalls.clear();
+        await new Promise((resolve, reject) => {
+            const conn = new DbConnectionBuilder(module, (/** @type {any} */ imp) => imp)
+                .withUri(entry.uri)
+                .withModuleName(entry.moduleName)
+                .withToken(entry.token)
+                .onConnect((/** @type {any} */ _conn, /** @type {Identity} */ identity) => {
+                    console.log(`[SpacetimeDB Bridge] Connection ${connectionId} connected event`);
+                    for (const callback of entry.connectCallbacks) {
+                        callback(identity ? normalizeValue(identity) : null);
+                    }
+                    resolve(undefined);
+                })
+                .onConnectError((/** @type {any} */ _ctx, /** @type {Error} */ error) => reject(fail(error)))
+                .onDisconnect((/** @type {any} */ _ctx, /** @type {Error | undefined} */ error) => {
+                    console.log(`[SpacetimeDB Bridge] Connection ${connectionId} disconnected event`, error);
+                    for (const calls of entry.pendingCalls.values()) {
+                        calls.splice(0).forEach(call => call.reject('Disconnected'));
+                    }
+                    // Subscriptions end with the connection
+                    for (const [id, subscription] of this.subscriptions) {
+                        if (subscription.connectionId === connectionId) {
+                            this.subscriptions.delete(id);
+                        }
+                    }
+                    for (const callback of entry.disconnectCallbacks) {
+                        callback(error?.message || null);
+                    }
+                })
+                .build();
+            entry.conn = conn;
+            entry.module = module;
+            for (const setup of entry.setup) {
+                setup(conn, module);
+            }
+        });
+        console.log(`[SpacetimeDB Bridge] Connected ${connectionId}`);
+    }
+
+    /**
+     * Disconnect from the SpacetimeDB server
+     *
+     * The connection keeps its listeners and can `connect` again.
+     *
+     * @param {number} connectionId
+     * @returns {Promise<void>}
+     */
+    async disconnect(connectionId) {
+        const entry = this.entry(connectionId);
+        console.log(`[SpacetimeDB Bridge] Disconnecting ${connectionId}...`);
+        entry.conn?.disconnect();
+        entry.conn = undefined;
+        console.log(`[SpacetimeDB Bridge] Disconnected ${connectionId}`);
+    }
+
+    /**
+     * Register a callback for connection events
+     *
+     * @param {number} connectionId
+     * @param {number} callbackId
+     */
+    onConnect(connectionId, callbackId) {
+        this.addConnectionListener('connectCallbacks', connectionId, callbackId, 'onConnect');
+    }
+
+    /**
+     * Register a callback for disconnection events
+     *
+     * @param {number} connectionId
+     * @param {number} callbackId
+     */
+    onDisconnect(connectionId, callbackId) {
+        this.addConnectionListener('disconnectCallbacks', connectionId, callbackId, 'onDisconnect');
+    }
+
+    /**
+     * Register a callback for connection error events
+     *
+     * @param {number} connectionId
+     * @param {number} callbackId
+     */
+    onConnectionError(connectionId, callbackId) {
+        this.addConnectionListener('errorCallbacks', connectionId, callbackId, 'onConnectionError');
+    }
+
+    /**
+     * @private
+     * @param {'connectCallbacks' | 'disconnectCallbacks' | 'errorCallbacks'} listeners
+     * @param {number} connectionId
+     * @param {number} callbackId
+     * @param {string} name
+     */
+    addConnectionListener(listeners, connectionId, callbackId, name) {
+        const entry = this.connections.get(connectionId);
+        const callback = this.callbacks.get(callbackId);
+        if (!entry || !callback) {
+            console.error(`[SpacetimeDB Bridge] ${name}: Invalid connection or callback ID`);
+            return;
+        }
+        entry[listeners].push(callback);
+    }
+
+    /**
+     * Register a callback for subscription applied events
+     *
+     * The callback receives `{ id, queries }` once the initial rows of a
+     * subscription have all been delivered.
+     *
+     * @param {number} connectionId
+     * @param {number} callbackId
+     */
+    onSubscriptionApplied(connectionId, callbackId) {
+        this.addSubscriptionListener(this.subscriptionAppliedCallbacks, connectionId, callbackId, 'onSubscriptionApplied');
+    }
+
+    /**
+     * Register a callback for subscription error events
+     *
+     * The callback receives `{ id, queries, error }`. The subscription has ended.
+     *
+     * @param {number} connectionId
+     * @param {number} callbackId
+     */
+    onSubscriptionError(connectionId, callbackId) {
+        this.addSubscriptionListener(this.subscriptionErrorCallbacks, connectionId, callbackId, 'onSubscriptionError');
+    }
+
+    /**
+     * Register a callback for subscription ended events
+     *
+     * The callback receives `{ id, queries }` once an unsubscribe has completed.
+     *
+     * @param {number} connectionId
+     * @param {number} callbackId
+     */
+    onSubscriptionEnded(connectionId, callbackId) {
+        this.addSubscriptionListener(this.subscriptionEndedCallbacks, connectionId, callbackId, 'onSubscriptionEnded');
+    }
+
+    /**
+     * @private
+     * @param {Map<number, WasmCallback[]>} listeners
+     * @param {number} connectionId
+     * @param {number} callbackId
+     * @param {string} name
+     */
+    addSubscriptionListener(listeners, connectionId, callbackId, name) {
+        const callback = this.callbacks.get(callbackId);
+        if (!this.connections.has(connectionId) || !callback) {
+            console.error(`[SpacetimeDB Bridge] ${name}: Invalid connection or callback ID`);
+            return;
+        }
+
+        const callbacks = listeners.get(connectionId) ?? [];
+        callbacks.push(callback);
+        listeners.set(connectionId, callbacks);
+    }
+
+    /**
+     * @private
+     * @param {Map<number, WasmCallback[]>} listeners
+     * @param {number} connectionId
+     * @param {any} data
+     */
+    notifySubscriptionListeners(listeners, connectionId, data) {
+        for (const callback of listeners.get(connectionId) ?? []) {
+            callback(data);
+        }
+    }
+
+    /**
+     * Call a reducer on the SpacetimeDB server
+     *
+     * Resolves once the reducer has committed, rejects with its error message
+     * if it failed.
+     *
+     * @param {number} connectionId
+     * @param {string} reducerName
+     * @param {any} args
+     * @returns {Promise<void>}
+     */
+    async callReducer(connectionId, reducerName, args) {
+        const entry = this.entry(connectionId);
+        const { conn, module } = entry;
+        if (!conn) {
+            throw `Connection ${connectionId} is not connected`;
+        }
+        const reducer = module.reducers[reducerName];
+        if (!reducer) {
+            throw `Reducer not found: ${reducerName}`;
+        }
+
+        console.log(`[SpacetimeDB Bridge] Calling reducer ${reducerName} on connection ${connectionId}`, args);
+
+        // Args are an array in parameter order; the SDK serializes them as a product
+        const argsArray = denormalizeValue(Array.isArray(args) ? args : [args]);
+        /** @type {Record<string, any>} */
+        const value = {};
+        reducer.argsType.product.elements.forEach((/** @type {any} */ element, /** @type {number} */ i) => {
+            value[element.name] = argsArray[i];
+        });
+        const writer = new BinaryWriter(1024);
+        reducer.argsType.serialize(writer, value);
+
+        const pending = this.pendingCalls(entry, reducerName);
+        return new Promise((resolve, reject) => {
+            pending.push({ resolve, reject });
+            conn.callReducer(reducerName, writer.getBuffer(), 'FullUpdate');
+        });
+    }
+
+    /**
+     * The calls of a reducer waiting for their outcome, settled in order
+     *
+     * @private
+     * @param {ConnectionEntry} entry
+     * @param {string} reducerName
+     */
+    pendingCalls(entry, reducerName) {
+        const existing = entry.pendingCalls.get(reducerName);
+        if (existing) {
+            return existing;
+        }
+        /** @type {Array<{ resolve: () => void, reject: (error: string) => void }>} */
+        const calls = [];
+        entry.pendingCalls.set(reducerName, calls);
+        entry.conn.onReducer(reducerName, (/** @type {any} */ ctx) => {
+            // Other clients' calls are reported too; only ours settle a call
+            const event = ctx?.event ?? {};
+            const caller = normalizeValue(event.callerConnectionId)?.__connection_id__;
+            if (caller !== normalizeValue(entry.conn?.connectionId)?.__connection_id__) {
+                return;
+            }
+            const call = calls.shift();
+            const status = reducerStatus(event.status);
+            if (status.tag === 'Committed') {
+                call?.resolve();
+            } else {
+                call?.reject(status.tag === 'Failed' ? status.value : 'Out of energy');
+            }
+        });
+        return calls;
+    }
+
+    /**
+     * Subscribe to a set of SQL queries
+     *
+     * Returns a subscription ID immediately. The outcome is reported through the
+     * `onSubscriptionApplied` / `onSubscriptionError` callbacks.
+     *
+     * @param {number} connectionId
+     * @param {string[]} queries
+     * @returns {number}
+     */
+    subscribe(connectionId, queries) {
+        const id = this.nextSubscriptionId++;
+        console.log(`[SpacetimeDB Bridge] Subscription ${id} on connection ${connectionId}:`, queries);
+
+        const fail = (/** @type {any} */ error) => {
+            this.subscriptions.delete(id);
+            const message = error?.message ?? String(error ?? 'Unknown error');
+            console.error(`[SpacetimeDB Bridge] Subscription ${id} failed:`, message);
+            this.notifySubscriptionListeners(this.subscriptionErrorCallbacks, connectionId, { id, queries, error: message });
+        };
+
+        try {
+            const conn = this.entry(connectionId).conn;
+            if (!conn) {
+                throw new Error(`Connection ${connectionId} is not connected`);
+            }
+
+            const handle = new SubscriptionBuilderImpl(conn)
+                .onApplied(() => {
+                    console.log(`[SpacetimeDB Bridge] Subscription ${id} applied`);
+                    this.notifySubscriptionListeners(this.subscriptionAppliedCallbacks, connectionId, { id, queries });
+                })
+                .onError((/** @type {any} */ ctx) => fail(ctx?.event ?? ctx))
+                .subscribe(queries);
+            this.subscriptions.set(id, { connectionId, queries, handle });
+        } catch (error) {
+            fail(error);
+        }
+
+        return id;
+    }
+
+    /**
+     * End a subscription started with `subscribe`
+     *
+     * @param {number} connectionId
+     * @param {number} subscriptionId
+     */
+    unsubscribe(connectionId, subscriptionId) {
+        const subscription = this.subscriptions.get(subscriptionId);
+        if (!subscription || subscription.connectionId !== connectionId) {
+            console.warn(`[SpacetimeDB Bridge] unsubscribe: Unknown subscription ${subscriptionId}`);
+            return;
+        }
+
+        console.log(`[SpacetimeDB Bridge] Unsubscribing ${subscriptionId} on connection ${connectionId}`);
+        this.subscriptions.delete(subscriptionId);
+        const queries = subscription.queries;
+
+        subscription.handle.unsubscribeThen(() => {
+            this.notifySubscriptionListeners(this.subscriptionEndedCallbacks, connectionId, { id: subscriptionId, queries });
+        });
+    }
+
+    /**
+     * Run a SQL query once, without subscribing
+     *
+     * Resolves with the matching rows (normalized like table event rows) or
+     * rejects with the server's error message.
+     *
+     * @param {number} connectionId
+     * @param {string} query
+     * @returns {Promise<any[]>}
+     */
+    async oneOffQuery(connectionId, query) {
+        const conn = this.entry(connectionId).conn;
+        if (!conn) {
+            throw `Connection ${connectionId} is not connected`;
+        }
+
+        console.log(`[SpacetimeDB Bridge] One-off query on connection ${connectionId}:`, query);
+
+        try {
+            const rows = await /** @type {any} */ (conn).oneOffQuery(query);
+            return normalizeValue(Array.from(rows ?? []));
+        } catch (/** @type {any} */ error) {
+            throw error?.message ?? String(error);
+        }
+    }
+
+    /**
+     * Describe what caused a table event, from the event context the SDK passes to row callbacks
+     *
+     * @private
+     * @param {any} ctx
+     * @returns {EventContextData}
+     */
+    eventContext(ctx) {
+        const event = ctx?.event;
+        switch (event?.tag) {
+            case 'Reducer': {
+                const reducerEvent = event.value;
+                const args = reducerEvent.reducer?.args;
+                return {
+                    kind: 'reducer',
+                    reducerName: reducerEvent.reducer?.name,
+                    callerIdentity: normalizeValue(reducerEvent.callerIdentity),
+                    callerConnectionId: normalizeValue(reducerEvent.callerConnectionId),
+                    timestamp: normalizeValue(reducerEvent.timestamp),
+                    // Generated bindings give the arguments as an object in declaration order
+                    args: normalizeValue(Array.isArray(args) ? args : Object.values(args ?? {})),
+                };
+            }
+            case 'SubscribeApplied':
+                return { kind: 'subscription' };
+            case 'UnsubscribeApplied':
+                return { kind: 'unsubscription' };
+            default:
+                return { kind: 'unknown' };
+        }
+    }
+
+    /**
+     * Subscribe to table events
+     *
+     * Listeners are attached once connected; an unknown table is reported then.
+     *
+     * @param {number} connectionId
+     * @param {string} tableName
+     * @param {number | null} onInsertId
+     * @param {number | null} onUpdateId
+     * @param {number | null} onDeleteId
+     */
+    subscribeTable(connectionId, tableName, onInsertId, onUpdateId, onDeleteId) {
+        const entry = this.entry(connectionId);
+        const callback = (/** @type {number | null} */ id) => (id === null ? undefined : this.callbacks.get(id));
+        const onInsert = callback(onInsertId);
+        const onUpdate = callback(onUpdateId);
+        const onDelete = callback(onDeleteId);
+
+        console.log(`[SpacetimeDB Bridge] Subscribing to table ${tableName} on connection ${connectionId}`);
+
+        this.addSetup(entry, (conn, module) => {
+            const info = module.tables[tableName];
+            if (!info) {
+                console.error(`[SpacetimeDB Bridge] Table not found: ${tableName}`);
+                return;
+            }
+            const table = conn.clientCache.getOrCreateTable(info);
+
+            if (onInsert) {
+                table.onInsert((/** @type {any} */ ctx, /** @type {any} */ row) => {
+                    /** @type {TableEventData} */
+                    const data = {
+                        row: normalizeValue(row),
+                        event: this.eventContext(ctx),
+                    };
+                    onInsert(data);
+                });
+            }
+
+            if (onUpdate) {
+                table.onUpdate((/** @type {any} */ ctx, /** @type {any} */ oldRow, /** @type {any} */ newRow) => {
+                    /** @type {TableEventData} */
+                    const data = {
+                        oldRow: normalizeValue(oldRow),
+                        newRow: normalizeValue(newRow),
+                        event: this.eventContext(ctx),
+                    };
+                    onUpdate(data);
+                });
+            }
+
+            if (onDelete) {
+                table.onDelete((/** @type {any} */ ctx, /** @type {any} */ row) => {
+                    /** @type {TableEventData} */
+                    const data = {
+                        row: normalizeValue(row),
+                        event: this.eventContext(ctx),
+                    };
+                    onDelete(data);
+                });
+            }
+        });
+    }
+
+    /**
+     * Register a callback for the runs of a reducer
+     *
+     * The callback receives `{ event, args }` each time the reducer runs.
+     *
+     * @param {number} connectionId
+     * @param {string} reducerName
+     * @param {number} callbackId
+     */
+    onReducer(connectionId, reducerName, callbackId) {
+        const entry = this.connections.get(connectionId);
+        const callback = this.callbacks.get(callbackId);
+        if (!entry || !callback) {
+            console.error(`[SpacetimeDB Bridge] onReducer: Invalid connection or callback ID`);
+            return;
+        }
+
+        console.log(`[SpacetimeDB Bridge] Listening to reducer ${reducerName} on connection ${connectionId}`);
+
+        this.addSetup(entry, (conn, module) => {
+            if (!module.reducers[reducerName]) {
+                console.error(`[SpacetimeDB Bridge] Reducer not found: ${reducerName}`);
+                return;
+            }
+            conn.onReducer(reducerName, (/** @type {any} */ ctx, /** @type {any[]} */ ...args) => {
+                const event = ctx?.event ?? {};
+                /** @type {ReducerEventData} */
+                const data = {
+                    event: {
+                        reducerName,
+                        callerIdentity: normalizeValue(event.callerIdentity),
+                        callerConnectionId: normalizeValue(event.callerConnectionId),
+                        timestamp: normalizeValue(event.timestamp),
+                        status: reducerStatus(event.status),
+                    },
+                    args: normalizeValue(args),
+                };
+                callback(data);
+            });
+        });
+    }
+
+    /**
+     * Register a JavaScript callback that can be called from Rust
+     *
+     * @param {WasmCallback} callback
+     * @returns {number}
+     */
+    registerCallback(callback) {
+        const id = this.nextCallbackId++;
+        this.callbacks.set(id, callback);
+        return id;
+    }
+
+    /**
+     * Unregister a callback
+     *
+     * @param {number} callbackId
+     */
+    unregisterCallback(callbackId) {
+        this.callbacks.delete(callbackId);
+    }
+}
@@ -1,22 +1,42 @@
 #!/usr/bin/env bash
 set -e
 
+# Usage: ./run_tests.sh [--fake-server]
+#
+# With --fake-server, the tests run against the in-memory stand-in server
+# instead of a `spacetime start` server on localhost:3000.
+
 echo "Setting up test environment..."
 
-# Install Node.js dependencies for tests
-cd tests
-if [ ! -d "node_modules" ]; then
-    echo "Installing test dependencies..."
-    npm install
+# Install Node.js dependencies: the bridge's SDK, and the tests' WebSocket
+for dir in js tests; do
+    if [ ! -d "$dir/node_modules" ]; then
+        echo "Installing $dir dependencies..."
+        (cd "$dir" && npm install)
+    fi
+done
+
+if [ "$1" = "--fake-server" ]; then
+    echo "Starting fake SpacetimeDB server..."
+    cargo build -p bevy_spacetimedb_fake_server
+    cargo run -q -p bevy_spacetimedb_fake_server -- \
+        --listen 127.0.0.1:3000 --schema tests/fake_server_schema.json &
+    FAKE_SERVER_PID=$!
+    trap 'kill $FAKE_SERVER_PID' EXIT
+    sleep 1
 fi
-cd ..
+
+# The tests use the global bridge: build the default, bundled-bridge configuration too
+echo "Building for wasm32 with default features..."
+cargo build --target wasm32-unknown-unknown -p bevy_spacetimedb_wasm
 
 echo "Running wasm-pack tests..."
 
 # Set up the bridge before running tests
-export NODE_OPTIONS="--require $(pwd)/tests/node_setup.js"
+export NODE_OPTIONS="--import $(pwd)/tests/node_setup.mjs"
 
-# Run the tests
-wasm-pack test --node --release -- --test integration_test
+# Run the tests: the wasm-only unit tests in src/ (e.g. the codec), then the integration tests
+wasm-pack test --node --release -- --features global-bridge --lib
+wasm-pack test --node --release -- --features global-bridge --test integration_test
 
 echo "Tests completed!"
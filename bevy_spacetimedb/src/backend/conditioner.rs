@@ -0,0 +1,504 @@
+//! Backend simulating a bad network in front of another backend
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy::time::TimeSystems;
+
+use super::{BackendCallback, BackendValue, ResultCallback, StdbBackend, TableCallbacks};
+use crate::ValueEncoding;
+
+/// The error of a disconnect forced by a [`NetworkConditioner`]
+pub const SIMULATED_DISCONNECT: &str = "Connection lost (simulated network conditions)";
+
+/// How bad the network simulated by a [`NetworkConditioner`] is
+///
+/// The default is a perfect network.
+///
+/// # Example
+/// ```ignore
+/// NetworkConditions::default()
+///     .with_latency(Duration::from_millis(120))
+///     .with_jitter(Duration::from_millis(40))
+///     .with_reordering(2)
+///     .with_reducer_drop_rate(0.05)
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NetworkConditions {
+    latency: Duration,
+    jitter: Duration,
+    reordering: usize,
+    reducer_drop_rate: f64,
+    disconnect_rate: f64,
+    seed: u64,
+}
+
+impl NetworkConditions {
+    /// Delay every event and request outcome by `latency` before the app sees it
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// Add a random delay of up to `jitter` to each event
+    pub fn with_jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Let each event overtake up to `events` that arrived before it
+    ///
+    /// With no reordering (the default), an event waits for those before it,
+    /// however short its own delay.
+    pub fn with_reordering(mut self, events: usize) -> Self {
+        self.reordering = events;
+        self
+    }
+
+    /// Lose this fraction (0 to 1) of reducer calls
+    ///
+    /// A lost call never reaches the server and its outcome is never reported.
+    pub fn with_reducer_drop_rate(mut self, rate: f64) -> Self {
+        self.reducer_drop_rate = rate;
+        self
+    }
+
+    /// Drop the connection this many times per second on average
+    pub fn with_disconnect_rate(mut self, per_second: f64) -> Self {
+        self.disconnect_rate = per_second;
+        self
+    }
+
+    /// Seed the random choices, so a run can be reproduced
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+}
+
+/// SplitMix64, enough to make the simulation reproducible
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..1`
+    fn unit(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// A number in `0..n`, or 0 if `n` is 0
+    fn below(&mut self, n: u64) -> u64 {
+        if n == 0 {
+            0
+        } else {
+            self.next_u64() % n
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+type Delivery = Box<dyn FnOnce() + Send>;
+#[cfg(target_arch = "wasm32")]
+type Delivery = Box<dyn FnOnce()>;
+
+/// Values that can be held in flight: anything `Send`, and JS values on WASM
+#[cfg(not(target_arch = "wasm32"))]
+trait Deliverable: Send + 'static {}
+#[cfg(not(target_arch = "wasm32"))]
+impl<T: Send + 'static> Deliverable for T {}
+#[cfg(target_arch = "wasm32")]
+trait Deliverable: 'static {}
+#[cfg(target_arch = "wasm32")]
+impl<T: 'static> Deliverable for T {}
+
+/// Something the app will see once `due`
+struct InFlight {
+    due: Duration,
+    deliver: Delivery,
+}
+
+// JS values only exist on the one thread of a WASM app
+#[cfg(target_arch = "wasm32")]
+unsafe impl Send for InFlight {}
+
+type Callback = Arc<dyn Fn(BackendValue) + Send + Sync>;
+
+struct ConditionerState {
+    conditions: NetworkConditions,
+    rng: Rng,
+    now: Duration,
+    in_flight: VecDeque<InFlight>,
+    connected: bool,
+    /// Whether the disconnect reported next by the backend was forced
+    swallow_disconnect: bool,
+    on_disconnect: Vec<Callback>,
+}
+
+impl ConditionerState {
+    fn send(&mut self, deliver: Delivery) {
+        let jitter = self.rng.below(self.conditions.jitter.as_nanos() as u64 + 1);
+        let due = self.now + self.conditions.latency + Duration::from_nanos(jitter);
+        self.in_flight.push_back(InFlight { due, deliver });
+    }
+
+    /// Take the next event to deliver, if one is due
+    fn next_due(&mut self) -> Option<Delivery> {
+        let window = self.conditions.reordering + 1;
+        let due = self
+            .in_flight
+            .iter()
+            .take(window)
+            .enumerate()
+            .filter(|(_, in_flight)| in_flight.due <= self.now)
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+        let index = *due.get(self.rng.below(due.len() as u64) as usize)?;
+        self.in_flight
+            .remove(index)
+            .map(|in_flight| in_flight.deliver)
+    }
+}
+
+/// A backend delaying, reordering and losing the traffic of another backend
+///
+/// Events and request outcomes are held back until [`advance`](Self::advance)
+/// reaches their delivery time.
+/// [`StdbPlugin::with_network_conditions`](crate::StdbPlugin::with_network_conditions)
+/// does this every frame from the real time and inserts the conditioner as a
+/// resource, so tests and debug UIs can change the conditions or force a
+/// disconnect.
+#[derive(Resource, Clone)]
+pub struct NetworkConditioner {
+    inner: Arc<dyn StdbBackend>,
+    state: Arc<Mutex<ConditionerState>>,
+}
+
+impl NetworkConditioner {
+    /// Simulate `conditions` in front of `inner`
+    pub fn new(inner: impl StdbBackend, conditions: NetworkConditions) -> Self {
+        Self::wrap(Arc::new(inner), conditions)
+    }
+
+    pub(crate) fn wrap(inner: Arc<dyn StdbBackend>, conditions: NetworkConditions) -> Self {
+        Self {
+            inner,
+            state: Arc::new(Mutex::new(ConditionerState {
+                rng: Rng(conditions.seed),
+                conditions,
+                now: Duration::ZERO,
+                in_flight: VecDeque::new(),
+                connected: false,
+                swallow_disconnect: false,
+                on_disconnect: Vec::new(),
+            })),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, ConditionerState> {
+        lock(&self.state)
+    }
+
+    /// The conditions simulated
+    pub fn conditions(&self) -> NetworkConditions {
+        self.state().conditions.clone()
+    }
+
+    /// Simulate other conditions from now on
+    pub fn set_conditions(&self, conditions: NetworkConditions) {
+        let mut state = self.state();
+        state.rng = Rng(conditions.seed);
+        state.conditions = conditions;
+    }
+
+    /// The number of events and outcomes not delivered yet
+    pub fn in_flight(&self) -> usize {
+        self.state().in_flight.len()
+    }
+
+    /// Drop the connection, losing what is in flight
+    ///
+    /// The app sees a disconnect with the error [`SIMULATED_DISCONNECT`].
+    pub fn force_disconnect(&self) {
+        {
+            let mut state = self.state();
+            if !state.connected {
+                return;
+            }
+            state.connected = false;
+            state.swallow_disconnect = true;
+            state.in_flight.clear();
+            let callbacks = state.on_disconnect.clone();
+            state.send(Box::new(move || {
+                for callback in callbacks {
+                    callback(BackendValue::from(serde_json::json!(SIMULATED_DISCONNECT)));
+                }
+            }));
+        }
+        self.inner.disconnect();
+    }
+
+    /// Deliver what is due at `now`, the time since the simulation started
+    pub fn advance(&self, now: Duration) {
+        let disconnect = {
+            let mut state = self.state();
+            let elapsed = now.saturating_sub(state.now).as_secs_f64();
+            state.now = state.now.max(now);
+            let chance = state.conditions.disconnect_rate * elapsed;
+            state.connected && chance > 0.0 && state.rng.unit() < chance
+        };
+        if disconnect {
+            self.force_disconnect();
+        }
+
+        // Delivering may make requests that put more in flight
+        while let Some(deliver) = self.state().next_due() {
+            deliver();
+        }
+    }
+
+    /// Wrap `callback` to receive values through the simulated network
+    fn conditioned(&self, callback: BackendCallback) -> BackendCallback {
+        let state = self.state.clone();
+        let callback: Callback = Arc::from(callback);
+        Box::new(move |data| {
+            let callback = callback.clone();
+            lock(&state).send(Box::new(move || callback(data)));
+        })
+    }
+
+    fn conditioned_result<T: Deliverable>(
+        &self,
+        on_result: ResultCallback<T>,
+    ) -> ResultCallback<T> {
+        let state = self.state.clone();
+        Box::new(move |result| {
+            lock(&state).send(Box::new(move || on_result(result)));
+        })
+    }
+}
+
+fn lock(state: &Mutex<ConditionerState>) -> MutexGuard<'_, ConditionerState> {
+    state.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl StdbBackend for NetworkConditioner {
+    fn encoding(&self) -> ValueEncoding {
+        self.inner.encoding()
+    }
+
+    fn connection_id(&self) -> u32 {
+        self.inner.connection_id()
+    }
+
+    fn connect(&self) {
+        self.state().swallow_disconnect = false;
+        self.inner.connect();
+    }
+
+    fn disconnect(&self) {
+        self.inner.disconnect();
+    }
+
+    fn on_connect(&self, callback: BackendCallback) {
+        let state = self.state.clone();
+        let callback = self.conditioned(callback);
+        self.inner.on_connect(Box::new(move |identity| {
+            lock(&state).connected = true;
+            callback(identity);
+        }));
+    }
+
+    fn on_disconnect(&self, callback: BackendCallback) {
+        let callback: Callback = Arc::from(callback);
+        self.state().on_disconnect.push(callback.clone());
+
+        let state = self.state.clone();
+        let callback = self.conditioned(Box::new(move |err| callback(err)));
+        self.inner.on_disconnect(Box::new(move |err| {
+            let forced = {
+                let mut state = lock(&state);
+                state.connected = false;
+                std::mem::take(&mut state.swallow_disconnect)
+            };
+            if !forced {
+                callback(err);
+            }
+        }));
+    }
+
+    fn on_connection_error(&self, callback: BackendCallback) {
+        self.inner.on_connection_error(self.conditioned(callback));
+    }
+
+    fn call_reducer(&self, reducer: &str, args: BackendValue, on_result: ResultCallback<()>) {
+        let dropped = {
+            let mut state = self.state();
+            let rate = state.conditions.reducer_drop_rate;
+            rate > 0.0 && state.rng.unit() < rate
+        };
+        if !dropped {
+            self.inner
+                .call_reducer(reducer, args, self.conditioned_result(on_result));
+        }
+    }
+
+    fn subscribe(&self, queries: &[String]) -> u32 {
+        self.inner.subscribe(queries)
+    }
+
+    fn unsubscribe(&self, subscription_id: u32) {
+        self.inner.unsubscribe(subscription_id);
+    }
+
+    fn on_subscription_applied(&self, callback: BackendCallback) {
+        self.inner
+            .on_subscription_applied(self.conditioned(callback));
+    }
+
+    fn on_subscription_error(&self, callback: BackendCallback) {
+        self.inner.on_subscription_error(self.conditioned(callback));
+    }
+
+    fn on_subscription_ended(&self, callback: BackendCallback) {
+        self.inner.on_subscription_ended(self.conditioned(callback));
+    }
+
+    fn subscribe_table(&self, table: &str, callbacks: TableCallbacks) {
+        self.inner.subscribe_table(
+            table,
+            TableCallbacks {
+                on_insert: callbacks.on_insert.map(|cb| self.conditioned(cb)),
+                on_update: callbacks.on_update.map(|cb| self.conditioned(cb)),
+                on_delete: callbacks.on_delete.map(|cb| self.conditioned(cb)),
+            },
+        );
+    }
+
+    fn on_reducer(&self, reducer: &str, callback: BackendCallback) {
+        self.inner.on_reducer(reducer, self.conditioned(callback));
+    }
+
+    fn one_off_query(&self, query: &str, on_result: ResultCallback<BackendValue>) {
+        self.inner
+            .one_off_query(query, self.conditioned_result(on_result));
+    }
+}
+
+/// Deliver what the network conditioner has in flight, before the frame reads it
+pub(crate) fn advance_network_conditioner(
+    conditioner: Res<NetworkConditioner>,
+    time: Option<Res<Time<Real>>>,
+) {
+    if let Some(time) = time {
+        conditioner.advance(time.elapsed());
+    }
+}
+
+/// Schedule [`advance_network_conditioner`] after the clock is updated
+pub(crate) fn setup_network_conditioner(app: &mut App, conditioner: NetworkConditioner) {
+    app.insert_resource(conditioner)
+        .add_systems(First, advance_network_conditioner.after(TimeSystems));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        InsertEvent, StdbConnectedEvent, StdbConnection, StdbDisconnectedEvent, StdbPlugin,
+        StdbTestApp,
+    };
+    use bevy::time::TimeUpdateStrategy;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, crate::TableRow)]
+    struct Player {
+        #[stdb(primary_key)]
+        id: u64,
+    }
+
+    crate::define_reducer!(Greet(player_id: u64));
+
+    /// An app whose clock starts at the first update and advances 10ms per update
+    fn test_app(conditions: NetworkConditions) -> StdbTestApp {
+        let mut test = StdbTestApp::new(
+            StdbPlugin::default()
+                .add_table::<Player>()
+                .with_network_conditions(conditions),
+        );
+        test.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+            10,
+        )));
+        test
+    }
+
+    #[test]
+    fn test_latency_delays_events() {
+        let mut test =
+            test_app(NetworkConditions::default().with_latency(Duration::from_millis(30)));
+        test.server().connect(None);
+
+        test.run_frames(3);
+        test.assert_no_message::<StdbConnectedEvent>();
+        test.update();
+        test.assert_message::<StdbConnectedEvent>(|_| true);
+    }
+
+    #[test]
+    fn test_reordering_is_bounded() {
+        let mut reordered = false;
+        for seed in 0..8 {
+            let mut test = test_app(
+                NetworkConditions::default()
+                    .with_reordering(1)
+                    .with_seed(seed),
+            );
+            for id in 0..6 {
+                test.server().insert(Player { id });
+            }
+            test.update();
+
+            let order = test
+                .read_messages::<InsertEvent<Player>>()
+                .iter()
+                .map(|event| event.row.id)
+                .collect::<Vec<_>>();
+            assert_eq!(order.len(), 6);
+            for (position, id) in order.iter().enumerate() {
+                let overtaken = order[position..].iter().filter(|other| *other < id).count();
+                assert!(overtaken <= 1, "{:?} overtakes more than one event", order);
+            }
+            reordered |= order != [0, 1, 2, 3, 4, 5];
+        }
+        assert!(reordered);
+    }
+
+    #[test]
+    fn test_dropped_calls_and_forced_disconnect() {
+        let mut test = test_app(NetworkConditions::default().with_reducer_drop_rate(1.0));
+        test.server().connect(None);
+        test.update();
+
+        let stdb = test.world().resource::<StdbConnection>();
+        stdb.reducers().call::<Greet>((1,)).unwrap();
+        assert!(test.server().reducer_calls().is_empty());
+
+        test.world()
+            .resource::<NetworkConditioner>()
+            .force_disconnect();
+        assert!(!test.server().backend().is_connected());
+        test.update();
+
+        let disconnects = test.read_messages::<StdbDisconnectedEvent>();
+        assert_eq!(disconnects.len(), 1);
+        assert_eq!(disconnects[0].err.as_deref(), Some(SIMULATED_DISCONNECT));
+    }
+}
@@ -0,0 +1,198 @@
+//! Backend using the SpacetimeDB TypeScript SDK bridge
+
+use bevy::log::{error, info};
+use wasm_bindgen::prelude::*;
+
+use super::{BackendCallback, BackendValue, ResultCallback, StdbBackend, TableCallbacks, ValueEncoding};
+use crate::bridge::{get_bridge, SpacetimeDBBridge};
+
+/// Wrapper to make JS types Send + Sync in WASM single-threaded context
+/// SAFETY: WASM is single-threaded, so Send + Sync are safe.
+/// This assumes single-threaded WASM without atomics. If WASM gains threading support,
+/// this wrapper will need to be reevaluated for soundness.
+#[cfg(target_feature = "atomics")]
+compile_error!("SendSyncWrapper assumes single-threaded WASM. Review safety with atomics enabled.");
+
+#[derive(Clone)]
+struct SendSyncWrapper<T>(T);
+
+unsafe impl<T> Send for SendSyncWrapper<T> {}
+unsafe impl<T> Sync for SendSyncWrapper<T> {}
+
+impl<T> std::ops::Deref for SendSyncWrapper<T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// A connection made through the JavaScript bridge
+///
+/// This is the backend used by `StdbPlugin` unless another one is given.
+pub struct JsBackend {
+    bridge: SendSyncWrapper<SpacetimeDBBridge>,
+    connection_id: u32,
+}
+
+impl JsBackend {
+    /// Create a connection on the bridge
+    ///
+    /// # Panics
+    ///
+    /// Panics if the bridge is not initialized, see [`get_bridge`].
+    pub fn new(uri: &str, module_name: &str, auth_token: Option<String>) -> Self {
+        let bridge = get_bridge();
+        let connection_id = bridge.create_connection(uri, module_name, auth_token);
+        info!(
+            "Created SpacetimeDB connection {} to {}/{}",
+            connection_id, uri, module_name
+        );
+
+        Self {
+            bridge: SendSyncWrapper(bridge),
+            connection_id,
+        }
+    }
+
+    /// Register a callback with the bridge, returning its id
+    fn register(&self, callback: BackendCallback) -> u32 {
+        let closure = Closure::wrap(Box::new(move |data: JsValue| {
+            callback(BackendValue::Js(data))
+        }) as Box<dyn Fn(JsValue)>);
+
+        let id = self
+            .bridge
+            .register_callback(closure.as_ref().unchecked_ref());
+        // Keep the closure alive for the app lifetime. This is intentional - these callbacks
+        // are registered with the JS SDK and must live as long as the connection exists.
+        closure.forget();
+        id
+    }
+}
+
+/// Await a bridge promise and pass its outcome to `on_result`
+fn resolve_with<T: 'static>(
+    promise: js_sys::Promise,
+    on_result: ResultCallback<T>,
+    convert: impl FnOnce(JsValue) -> T + 'static,
+) {
+    wasm_bindgen_futures::spawn_local(async move {
+        let result = wasm_bindgen_futures::JsFuture::from(promise)
+            .await
+            .map(convert)
+            .map_err(|e| e.as_string().unwrap_or_else(|| format!("{:?}", e)));
+        on_result(result);
+    });
+}
+
+impl StdbBackend for JsBackend {
+    fn encoding(&self) -> ValueEncoding {
+        ValueEncoding::Js
+    }
+
+    fn connection_id(&self) -> u32 {
+        self.connection_id
+    }
+
+    fn connect(&self) {
+        let connection_id = self.connection_id;
+        info!("Connecting to SpacetimeDB connection {}...", connection_id);
+        resolve_with(
+            self.bridge.connect(connection_id),
+            Box::new(move |result| match result {
+                Ok(()) => info!(
+                    "Successfully connected to SpacetimeDB (connection {})",
+                    connection_id
+                ),
+                Err(e) => error!("Failed to connect to SpacetimeDB: {}", e),
+            }),
+            |_| (),
+        );
+    }
+
+    fn disconnect(&self) {
+        resolve_with(
+            self.bridge.disconnect(self.connection_id),
+            Box::new(|result| match result {
+                Ok(()) => info!("Disconnected from SpacetimeDB"),
+                Err(e) => error!("Failed to disconnect: {}", e),
+            }),
+            |_| (),
+        );
+    }
+
+    fn on_connect(&self, callback: BackendCallback) {
+        let id = self.register(callback);
+        self.bridge.on_connect(self.connection_id, id);
+    }
+
+    fn on_disconnect(&self, callback: BackendCallback) {
+        let id = self.register(callback);
+        self.bridge.on_disconnect(self.connection_id, id);
+    }
+
+    fn on_connection_error(&self, callback: BackendCallback) {
+        let id = self.register(callback);
+        self.bridge.on_connection_error(self.connection_id, id);
+    }
+
+    fn call_reducer(&self, reducer: &str, args: BackendValue, on_result: ResultCallback<()>) {
+        let args = match args {
+            BackendValue::Js(args) => args,
+            BackendValue::Json(args) => match crate::codec::encode_value(&args) {
+                Ok(args) => args,
+                Err(e) => return on_result(Err(e.to_string())),
+            },
+        };
+        resolve_with(
+            self.bridge.call_reducer(self.connection_id, reducer, args),
+            on_result,
+            |_| (),
+        );
+    }
+
+    fn subscribe(&self, queries: &[String]) -> u32 {
+        let array = queries.iter().map(JsValue::from).collect::<js_sys::Array>();
+        self.bridge.subscribe(self.connection_id, array)
+    }
+
+    fn unsubscribe(&self, subscription_id: u32) {
+        self.bridge.unsubscribe(self.connection_id, subscription_id);
+    }
+
+    fn on_subscription_applied(&self, callback: BackendCallback) {
+        let id = self.register(callback);
+        self.bridge.on_subscription_applied(self.connection_id, id);
+    }
+
+    fn on_subscription_error(&self, callback: BackendCallback) {
+        let id = self.register(callback);
+        self.bridge.on_subscription_error(self.connection_id, id);
+    }
+
+    fn on_subscription_ended(&self, callback: BackendCallback) {
+        let id = self.register(callback);
+        self.bridge.on_subscription_ended(self.connection_id, id);
+    }
+
+    fn subscribe_table(&self, table: &str, callbacks: TableCallbacks) {
+        let on_insert = callbacks.on_insert.map(|callback| self.register(callback));
+        let on_update = callbacks.on_update.map(|callback| self.register(callback));
+        let on_delete = callbacks.on_delete.map(|callback| self.register(callback));
+        self.bridge
+            .subscribe_table(self.connection_id, table, on_insert, on_update, on_delete);
+    }
+
+    fn on_reducer(&self, reducer: &str, callback: BackendCallback) {
+        let id = self.register(callback);
+        self.bridge.on_reducer(self.connection_id, reducer, id);
+    }
+
+    fn one_off_query(&self, query: &str, on_result: ResultCallback<BackendValue>) {
+        resolve_with(
+            self.bridge.one_off_query(self.connection_id, query),
+            on_result,
+            BackendValue::Js,
+        );
+    }
+}
@@ -0,0 +1,705 @@
+//! In-memory backend for tests
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Value};
+
+use super::{BackendCallback, BackendValue, ResultCallback, StdbBackend, TableCallbacks, ValueEncoding};
+use crate::{Identity, ReducerStatus, TableEventKind, TableRow};
+
+type Callback = Arc<dyn Fn(BackendValue) + Send + Sync>;
+
+/// A reducer call captured by a [`MockBackend`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct MockReducerCall {
+    /// The name of the reducer
+    pub reducer: String,
+    /// The arguments, as JSON
+    pub args: Value,
+}
+
+impl MockReducerCall {
+    /// Deserialize the arguments, e.g. into the reducer's argument tuple
+    pub fn args_as<A: DeserializeOwned>(&self) -> Option<A> {
+        A::deserialize(&self.args).ok()
+    }
+}
+
+/// A subscription made on a [`MockBackend`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MockSubscription {
+    /// The id of the subscription
+    pub id: u32,
+    /// The queries of the subscription
+    pub queries: Vec<String>,
+    /// Whether the subscription has been applied
+    pub applied: bool,
+}
+
+#[derive(Default)]
+struct TableListeners {
+    on_insert: Vec<Callback>,
+    on_update: Vec<Callback>,
+    on_delete: Vec<Callback>,
+}
+
+#[derive(Default)]
+struct MockState {
+    connect_requested: bool,
+    connected: bool,
+    on_connect: Vec<Callback>,
+    on_disconnect: Vec<Callback>,
+    on_connection_error: Vec<Callback>,
+    on_subscription_applied: Vec<Callback>,
+    on_subscription_error: Vec<Callback>,
+    on_subscription_ended: Vec<Callback>,
+    tables: HashMap<String, TableListeners>,
+    reducers: HashMap<String, Vec<Callback>>,
+    subscriptions: BTreeMap<u32, MockSubscription>,
+    next_subscription_id: u32,
+    reducer_calls: Vec<MockReducerCall>,
+    reducer_results: HashMap<String, VecDeque<Result<(), String>>>,
+    one_off_queries: Vec<(String, ResultCallback<BackendValue>)>,
+}
+
+/// A backend that keeps everything in memory, for testing game systems
+///
+/// Nothing happens on its own: tests drive the connection, subscriptions and
+/// table events, and inspect the reducers called. Clones share the same state,
+/// so keep one to drive the backend given to `StdbPlugin::with_backend`.
+///
+/// # Example
+/// ```ignore
+/// let backend = MockBackend::new();
+/// app.add_plugins(
+///     StdbPlugin::default()
+///         .with_backend(backend.clone())
+///         .add_table::<Player>(),
+/// );
+///
+/// backend.accept_connection(None);
+/// backend.insert(&Player { id: 1, name: "Alice".into() });
+/// app.update();
+///
+/// assert_eq!(backend.reducer_calls()[0].reducer, "spawn_player");
+/// ```
+#[derive(Clone, Default)]
+pub struct MockBackend {
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockBackend {
+    /// Create a backend with no connection
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn state(&self) -> MutexGuard<'_, MockState> {
+        self.state.lock().expect("mock backend poisoned")
+    }
+
+    /// Call `callbacks` with `value`, without holding the lock
+    fn emit(callbacks: Vec<Callback>, value: Value) {
+        for callback in callbacks {
+            callback(BackendValue::Json(value.clone()));
+        }
+    }
+
+    /// Whether the plugin has asked to connect
+    pub fn connect_requested(&self) -> bool {
+        self.state().connect_requested
+    }
+
+    /// Whether the connection is open
+    pub fn is_connected(&self) -> bool {
+        self.state().connected
+    }
+
+    /// Report the connection as established, with the given identity
+    pub fn accept_connection(&self, identity: Option<Identity>) {
+        let callbacks = {
+            let mut state = self.state();
+            state.connected = true;
+            state.on_connect.clone()
+        };
+        Self::emit(callbacks, json!(identity));
+    }
+
+    /// Report the connection as failed
+    pub fn fail_connection(&self, error: impl Into<String>) {
+        let callbacks = self.state().on_connection_error.clone();
+        Self::emit(callbacks, json!(error.into()));
+    }
+
+    /// Report the connection as closed, optionally because of an error
+    ///
+    /// Its subscriptions end with it, without a `SubscriptionEndedEvent`.
+    pub fn close_connection(&self, error: Option<String>) {
+        let callbacks = {
+            let mut state = self.state();
+            state.connected = false;
+            state.subscriptions.clear();
+            state.on_disconnect.clone()
+        };
+        Self::emit(callbacks, json!(error));
+    }
+
+    /// The subscriptions that have not ended
+    pub fn subscriptions(&self) -> Vec<MockSubscription> {
+        self.state().subscriptions.values().cloned().collect()
+    }
+
+    /// Report a subscription as applied
+    pub fn apply_subscription(&self, id: u32) {
+        let (callbacks, queries) = {
+            let mut state = self.state();
+            let Some(subscription) = state.subscriptions.get_mut(&id) else {
+                return;
+            };
+            subscription.applied = true;
+            let queries = subscription.queries.clone();
+            (state.on_subscription_applied.clone(), queries)
+        };
+        Self::emit(callbacks, json!({ "id": id, "queries": queries }));
+    }
+
+    /// Report a subscription as applied, with `rows` as its initial rows
+    ///
+    /// As with the SDK, the rows are sent first, as insert events whose context
+    /// is `EventContext::Subscription`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a row cannot be serialized.
+    pub fn apply_subscription_with_rows<T: TableRow + Serialize>(&self, id: u32, rows: &[T]) {
+        if !self.state().subscriptions.contains_key(&id) {
+            return;
+        }
+        for row in rows {
+            let data = json!({ "row": to_json(row), "event": { "kind": "subscription" } });
+            self.emit_table_event(T::TABLE_NAME, TableEventKind::Insert, data);
+        }
+        self.apply_subscription(id);
+    }
+
+    /// Report every subscription not yet applied as applied
+    pub fn apply_subscriptions(&self) {
+        let pending = self
+            .subscriptions()
+            .into_iter()
+            .filter(|subscription| !subscription.applied);
+        for subscription in pending {
+            self.apply_subscription(subscription.id);
+        }
+    }
+
+    /// Report a subscription as rejected by the server
+    pub fn fail_subscription(&self, id: u32, error: impl Into<String>) {
+        let (callbacks, queries) = {
+            let mut state = self.state();
+            let Some(subscription) = state.subscriptions.remove(&id) else {
+                return;
+            };
+            (state.on_subscription_error.clone(), subscription.queries)
+        };
+        Self::emit(
+            callbacks,
+            json!({ "id": id, "queries": queries, "error": error.into() }),
+        );
+    }
+
+    /// Report a subscription as ended, returning whether it was active
+    pub(crate) fn end_subscription(&self, id: u32) -> bool {
+        let (callbacks, queries) = {
+            let mut state = self.state();
+            let Some(subscription) = state.subscriptions.remove(&id) else {
+                return false;
+            };
+            (state.on_subscription_ended.clone(), subscription.queries)
+        };
+        Self::emit(callbacks, json!({ "id": id, "queries": queries }));
+        true
+    }
+
+    /// Send a table event, with the payload described in [`TableCallbacks`]
+    pub(crate) fn emit_table_event(&self, table: &str, kind: TableEventKind, data: Value) {
+        let callbacks = self
+            .state()
+            .tables
+            .get(table)
+            .map(|listeners| match kind {
+                TableEventKind::Insert => listeners.on_insert.clone(),
+                TableEventKind::Update => listeners.on_update.clone(),
+                TableEventKind::Delete => listeners.on_delete.clone(),
+            })
+            .unwrap_or_default();
+        Self::emit(callbacks, data);
+    }
+
+    /// Send an insert event for `row`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `row` cannot be serialized.
+    pub fn insert<T: TableRow + Serialize>(&self, row: &T) {
+        let data = json!({ "row": to_json(row) });
+        self.emit_table_event(T::TABLE_NAME, TableEventKind::Insert, data);
+    }
+
+    /// Send an update event from `old` to `new`
+    ///
+    /// # Panics
+    ///
+    /// Panics if a row cannot be serialized.
+    pub fn update<T: TableRow + Serialize>(&self, old: &T, new: &T) {
+        let data = json!({ "oldRow": to_json(old), "newRow": to_json(new) });
+        self.emit_table_event(T::TABLE_NAME, TableEventKind::Update, data);
+    }
+
+    /// Send a delete event for `row`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `row` cannot be serialized.
+    pub fn delete<T: TableRow + Serialize>(&self, row: &T) {
+        let data = json!({ "row": to_json(row) });
+        self.emit_table_event(T::TABLE_NAME, TableEventKind::Delete, data);
+    }
+
+    /// Report a run of `reducer` with `args`, the tuple of its arguments
+    ///
+    /// # Panics
+    ///
+    /// Panics if `args` cannot be serialized.
+    pub fn run_reducer<A: Serialize>(
+        &self,
+        reducer: &str,
+        args: &A,
+        caller: Identity,
+        status: ReducerStatus,
+    ) {
+        // The bridge always passes the arguments as an array, even when there are none
+        let args = match to_json(args) {
+            Value::Null => json!([]),
+            args => args,
+        };
+        let data = json!({
+            "event": { "reducerName": reducer, "callerIdentity": caller, "status": status },
+            "args": args,
+        });
+        self.emit_reducer_event(reducer, data);
+    }
+
+    /// Send a reducer event, with the payload described in [`StdbBackend::on_reducer`]
+    pub(crate) fn emit_reducer_event(&self, reducer: &str, data: Value) {
+        let callbacks = self
+            .state()
+            .reducers
+            .get(reducer)
+            .cloned()
+            .unwrap_or_default();
+        Self::emit(callbacks, data);
+    }
+
+    /// The reducer calls made so far
+    pub fn reducer_calls(&self) -> Vec<MockReducerCall> {
+        self.state().reducer_calls.clone()
+    }
+
+    /// Take the reducer calls made so far, clearing the list
+    pub fn take_reducer_calls(&self) -> Vec<MockReducerCall> {
+        std::mem::take(&mut self.state().reducer_calls)
+    }
+
+    /// Make the next call to `reducer` fail with `error`
+    ///
+    /// Calling this again fails the call after that, and so on.
+    pub fn fail_next_reducer_call(&self, reducer: impl Into<String>, error: impl Into<String>) {
+        self.push_reducer_result(reducer.into(), Err(error.into()));
+    }
+
+    /// Queue the outcome of the next call to `reducer` without a queued outcome
+    pub(crate) fn push_reducer_result(&self, reducer: String, result: Result<(), String>) {
+        self.state()
+            .reducer_results
+            .entry(reducer)
+            .or_default()
+            .push_back(result);
+    }
+
+    /// The one-off queries waiting for a result
+    pub fn one_off_queries(&self) -> Vec<String> {
+        self.state()
+            .one_off_queries
+            .iter()
+            .map(|(query, _)| query.clone())
+            .collect()
+    }
+
+    /// Resolve the oldest pending one-off query equal to `query`
+    ///
+    /// Returns whether such a query was pending.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a row cannot be serialized.
+    pub fn resolve_one_off_query<T: Serialize>(&self, query: &str, rows: &[T]) -> bool {
+        let rows = rows.iter().map(to_json).collect::<Vec<_>>();
+        self.finish_one_off_query(query, Ok(BackendValue::Json(Value::Array(rows))))
+    }
+
+    /// Fail the oldest pending one-off query equal to `query`
+    ///
+    /// Returns whether such a query was pending.
+    pub fn reject_one_off_query(&self, query: &str, error: impl Into<String>) -> bool {
+        self.finish_one_off_query(query, Err(error.into()))
+    }
+
+    pub(crate) fn finish_one_off_query(
+        &self,
+        query: &str,
+        result: Result<BackendValue, String>,
+    ) -> bool {
+        let on_result = {
+            let mut state = self.state();
+            let Some(index) = state
+                .one_off_queries
+                .iter()
+                .position(|(pending, _)| pending == query)
+            else {
+                return false;
+            };
+            state.one_off_queries.remove(index).1
+        };
+        on_result(result);
+        true
+    }
+}
+
+fn to_json<T: Serialize>(row: &T) -> Value {
+    serde_json::to_value(row).expect("failed to serialize mock row")
+}
+
+impl StdbBackend for MockBackend {
+    fn encoding(&self) -> ValueEncoding {
+        ValueEncoding::Json
+    }
+
+    fn connect(&self) {
+        self.state().connect_requested = true;
+    }
+
+    fn disconnect(&self) {
+        if self.is_connected() {
+            self.close_connection(None);
+        }
+    }
+
+    fn on_connect(&self, callback: BackendCallback) {
+        self.state().on_connect.push(callback.into());
+    }
+
+    fn on_disconnect(&self, callback: BackendCallback) {
+        self.state().on_disconnect.push(callback.into());
+    }
+
+    fn on_connection_error(&self, callback: BackendCallback) {
+        self.state().on_connection_error.push(callback.into());
+    }
+
+    fn call_reducer(&self, reducer: &str, args: BackendValue, on_result: ResultCallback<()>) {
+        let result = {
+            let mut state = self.state();
+            state.reducer_calls.push(MockReducerCall {
+                reducer: reducer.to_string(),
+                args: args.to_json(),
+            });
+            state
+                .reducer_results
+                .get_mut(reducer)
+                .and_then(VecDeque::pop_front)
+        };
+        on_result(result.unwrap_or(Ok(())));
+    }
+
+    fn subscribe(&self, queries: &[String]) -> u32 {
+        let (id, rejected) = {
+            let mut state = self.state();
+            let id = state.next_subscription_id;
+            state.next_subscription_id += 1;
+            if state.connected {
+                state.subscriptions.insert(
+                    id,
+                    MockSubscription {
+                        id,
+                        queries: queries.to_vec(),
+                        applied: false,
+                    },
+                );
+                (id, None)
+            } else {
+                (id, Some(state.on_subscription_error.clone()))
+            }
+        };
+        // Like the bridge, fail at once when there is no connection
+        if let Some(callbacks) = rejected {
+            Self::emit(
+                callbacks,
+                json!({ "id": id, "queries": queries, "error": "not connected" }),
+            );
+        }
+        id
+    }
+
+    fn unsubscribe(&self, subscription_id: u32) {
+        self.end_subscription(subscription_id);
+    }
+
+    fn on_subscription_applied(&self, callback: BackendCallback) {
+        self.state().on_subscription_applied.push(callback.into());
+    }
+
+    fn on_subscription_error(&self, callback: BackendCallback) {
+        self.state().on_subscription_error.push(callback.into());
+    }
+
+    fn on_subscription_ended(&self, callback: BackendCallback) {
+        self.state().on_subscription_ended.push(callback.into());
+    }
+
+    fn subscribe_table(&self, table: &str, callbacks: TableCallbacks) {
+        let mut state = self.state();
+        let listeners = state.tables.entry(table.to_string()).or_default();
+        listeners.on_insert.extend(callbacks.on_insert.map(Arc::from));
+        listeners.on_update.extend(callbacks.on_update.map(Arc::from));
+        listeners.on_delete.extend(callbacks.on_delete.map(Arc::from));
+    }
+
+    fn on_reducer(&self, reducer: &str, callback: BackendCallback) {
+        self.state()
+            .reducers
+            .entry(reducer.to_string())
+            .or_default()
+            .push(callback.into());
+    }
+
+    fn one_off_query(&self, query: &str, on_result: ResultCallback<BackendValue>) {
+        self.state()
+            .one_off_queries
+            .push((query.to_string(), on_result));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        EventContext, InsertEvent, Reducer, StdbConnectedEvent, StdbConnection, StdbPlugin,
+        SubscriptionHandle, UpdateEvent,
+    };
+    use bevy::prelude::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, crate::TableRow)]
+    struct Player {
+        #[stdb(primary_key)]
+        id: u64,
+        name: String,
+    }
+
+    struct Rename;
+
+    impl Reducer for Rename {
+        const NAME: &'static str = "rename";
+        type Args = (u64, String);
+    }
+
+    fn app(backend: &MockBackend) -> App {
+        let mut app = App::new();
+        app.add_plugins(
+            StdbPlugin::default()
+                .with_backend(backend.clone())
+                .add_table::<Player>(),
+        );
+        app
+    }
+
+    fn messages<M: Message + Clone>(app: &App) -> Vec<M> {
+        app.world()
+            .resource::<Messages<M>>()
+            .iter_current_update_messages()
+            .cloned()
+            .collect()
+    }
+
+    #[test]
+    fn test_rows_are_delivered_as_events() {
+        let backend = MockBackend::new();
+        let mut app = app(&backend);
+        assert!(backend.connect_requested());
+
+        let identity = Identity::from_be_byte_array([1; 32]);
+        backend.accept_connection(Some(identity));
+        let alice = Player {
+            id: 1,
+            name: "Alice".to_string(),
+        };
+        let renamed = Player {
+            id: 1,
+            name: "Alicia".to_string(),
+        };
+        backend.insert(&alice);
+        backend.update(&alice, &renamed);
+        app.update();
+
+        let connected = messages::<StdbConnectedEvent>(&app);
+        assert_eq!(connected[0].identity, Some(identity));
+        assert_eq!(messages::<InsertEvent<Player>>(&app)[0].row, alice);
+        let updates = messages::<UpdateEvent<Player>>(&app);
+        assert_eq!((&updates[0].old, &updates[0].new), (&alice, &renamed));
+    }
+
+    #[test]
+    fn test_reducer_calls_are_captured() {
+        let backend = MockBackend::new();
+        let app = app(&backend);
+        let stdb = app.world().resource::<StdbConnection>();
+
+        stdb.reducers()
+            .call::<Rename>((1, "Bob".to_string()))
+            .unwrap();
+        backend.fail_next_reducer_call("rename", "no such player");
+        stdb.reducers()
+            .call::<Rename>((2, "Carol".to_string()))
+            .unwrap();
+
+        let calls = backend.take_reducer_calls();
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].reducer, "rename");
+        assert_eq!(
+            calls[1].args_as::<(u64, String)>(),
+            Some((2, "Carol".to_string()))
+        );
+        assert!(backend.reducer_calls().is_empty());
+    }
+
+    #[test]
+    fn test_subscription_lifecycle() {
+        let backend = MockBackend::new();
+        let mut app = app(&backend);
+        backend.accept_connection(None);
+
+        let handle: SubscriptionHandle = app
+            .world()
+            .resource::<StdbConnection>()
+            .subscription_builder()
+            .subscribe("SELECT * FROM player");
+        assert_eq!(backend.subscriptions()[0].queries, ["SELECT * FROM player"]);
+        assert!(!handle.is_active());
+
+        backend.apply_subscriptions();
+        assert!(handle.is_active());
+
+        handle.unsubscribe();
+        assert!(handle.is_ended());
+        assert!(backend.subscriptions().is_empty());
+        app.update();
+    }
+
+    #[test]
+    fn test_subscriptions_fail_without_connection() {
+        let backend = MockBackend::new();
+        let mut app = app(&backend);
+
+        let handle: SubscriptionHandle = app
+            .world()
+            .resource::<StdbConnection>()
+            .subscription_builder()
+            .subscribe("SELECT * FROM player");
+        assert!(handle.is_ended());
+        assert!(backend.subscriptions().is_empty());
+        app.update();
+
+        let errors = messages::<crate::SubscriptionErrorEvent>(&app);
+        assert_eq!(errors[0].id, handle.id());
+        assert_eq!(errors[0].error, "not connected");
+    }
+
+    #[test]
+    fn test_subscriptions_end_with_the_connection() {
+        let backend = MockBackend::new();
+        let app = app(&backend);
+        backend.accept_connection(None);
+
+        let handle: SubscriptionHandle = app
+            .world()
+            .resource::<StdbConnection>()
+            .subscription_builder()
+            .subscribe("SELECT * FROM player");
+        backend.apply_subscriptions();
+        assert!(handle.is_active());
+
+        backend.close_connection(None);
+        assert!(handle.is_ended());
+        assert!(backend.subscriptions().is_empty());
+    }
+
+    #[test]
+    fn test_one_off_queries_resolve_on_demand() {
+        let backend = MockBackend::new();
+        let mut app = app(&backend);
+        let query = "SELECT * FROM player WHERE id = 1";
+
+        let id = app
+            .world()
+            .resource::<StdbConnection>()
+            .one_off_query::<Player>(query);
+        assert_eq!(backend.one_off_queries(), [query]);
+
+        let alice = Player {
+            id: 1,
+            name: "Alice".to_string(),
+        };
+        assert!(backend.resolve_one_off_query(query, std::slice::from_ref(&alice)));
+        assert!(!backend.resolve_one_off_query(query, std::slice::from_ref(&alice)));
+        app.update();
+
+        let results = messages::<crate::OneOffQueryResultEvent<Player>>(&app);
+        assert_eq!(results[0].id, id);
+        assert_eq!(results[0].result, Ok(vec![alice]));
+    }
+
+    #[test]
+    fn test_initial_rows_are_marked() {
+        let backend = MockBackend::new();
+        let mut app = app(&backend);
+        backend.accept_connection(None);
+
+        let handle: SubscriptionHandle = app
+            .world()
+            .resource::<StdbConnection>()
+            .subscription_builder()
+            .subscribe("SELECT * FROM player");
+        let alice = Player {
+            id: 1,
+            name: "Alice".to_string(),
+        };
+        let bob = Player {
+            id: 2,
+            name: "Bob".to_string(),
+        };
+        backend.apply_subscription_with_rows(handle.id().0, std::slice::from_ref(&alice));
+        backend.insert(&bob);
+        assert!(handle.is_active());
+        app.update();
+
+        let inserts = messages::<InsertEvent<Player>>(&app);
+        assert_eq!(inserts.len(), 2);
+        assert_eq!(inserts[0].row, alice);
+        assert!(inserts[0].ctx.is_subscription());
+        assert_eq!(inserts[1].row, bob);
+        assert_eq!(inserts[1].ctx, EventContext::Unknown);
+    }
+}
@@ -0,0 +1,101 @@
+//! Backends the plugin talks to the server through
+//!
+//! The plugin, tables, reducers and subscriptions only use the [`StdbBackend`]
+//! trait. `JsBackend` implements it on top of the TypeScript SDK bridge (WASM
+//! only), and [`MockBackend`] in memory so game systems can be tested without a
+//! browser or a server. [`RecordingBackend`] wraps either to record its traffic,
+//! and [`NetworkConditioner`] to simulate a bad network.
+
+mod conditioner;
+#[cfg(target_arch = "wasm32")]
+mod js;
+mod mock;
+mod recording;
+mod value;
+
+pub(crate) use conditioner::setup_network_conditioner;
+pub use conditioner::{NetworkConditioner, NetworkConditions, SIMULATED_DISCONNECT};
+#[cfg(target_arch = "wasm32")]
+pub use js::JsBackend;
+pub use mock::{MockBackend, MockReducerCall, MockSubscription};
+pub use recording::RecordingBackend;
+pub use value::{BackendValue, CodecError, ValueEncoding};
+
+/// Callback receiving the values a backend reports for one kind of event
+pub type BackendCallback = Box<dyn Fn(BackendValue) + Send + Sync>;
+
+/// Callback receiving the outcome of an asynchronous request
+pub type ResultCallback<T> = Box<dyn FnOnce(Result<T, String>) + Send>;
+
+/// Callbacks receiving the row events of one table
+///
+/// Insert and delete events carry a `row` field, update events `oldRow` and
+/// `newRow` fields. All carry an optional `event` field describing their cause.
+#[derive(Default)]
+pub struct TableCallbacks {
+    /// Called for each inserted row
+    pub on_insert: Option<BackendCallback>,
+    /// Called for each updated row
+    pub on_update: Option<BackendCallback>,
+    /// Called for each deleted row
+    pub on_delete: Option<BackendCallback>,
+}
+
+/// A connection to a SpacetimeDB module
+///
+/// Requests return immediately; their outcome is reported to the registered
+/// callbacks, which may be called from within the request.
+pub trait StdbBackend: Send + Sync + 'static {
+    /// How values sent to this backend must be encoded
+    fn encoding(&self) -> ValueEncoding;
+
+    /// An identifier of the connection, for diagnostics
+    fn connection_id(&self) -> u32 {
+        0
+    }
+
+    /// Start connecting to the server
+    fn connect(&self);
+
+    /// Start disconnecting from the server
+    fn disconnect(&self);
+
+    /// Register a callback receiving the identity (or null) once connected
+    fn on_connect(&self, callback: BackendCallback);
+
+    /// Register a callback receiving the error message (or null) once disconnected
+    fn on_disconnect(&self, callback: BackendCallback);
+
+    /// Register a callback receiving the error message when connecting fails
+    fn on_connection_error(&self, callback: BackendCallback);
+
+    /// Call a reducer with encoded arguments
+    fn call_reducer(&self, reducer: &str, args: BackendValue, on_result: ResultCallback<()>);
+
+    /// Subscribe to a set of SQL queries, returning the subscription id
+    fn subscribe(&self, queries: &[String]) -> u32;
+
+    /// End a subscription
+    fn unsubscribe(&self, subscription_id: u32);
+
+    /// Register a callback receiving `{ id, queries }` when a subscription is applied
+    fn on_subscription_applied(&self, callback: BackendCallback);
+
+    /// Register a callback receiving `{ id, queries, error }` when a subscription fails
+    fn on_subscription_error(&self, callback: BackendCallback);
+
+    /// Register a callback receiving `{ id, queries }` when a subscription has ended
+    fn on_subscription_ended(&self, callback: BackendCallback);
+
+    /// Register the row event callbacks of a table
+    fn subscribe_table(&self, table: &str, callbacks: TableCallbacks);
+
+    /// Register a callback receiving `{ event, args }` each time `reducer` runs
+    ///
+    /// `event` describes the call as a [`ReducerEvent`](crate::ReducerEvent) and
+    /// `args` is the array of the reducer's arguments.
+    fn on_reducer(&self, reducer: &str, callback: BackendCallback);
+
+    /// Run a SQL query once, resolving with an array of rows
+    fn one_off_query(&self, query: &str, on_result: ResultCallback<BackendValue>);
+}
@@ -0,0 +1,225 @@
+//! Backend recording the traffic of another backend
+
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+use super::{BackendCallback, BackendValue, ResultCallback, StdbBackend, TableCallbacks};
+use crate::{RecordedEvent, SessionRecorder, ValueEncoding};
+
+/// A backend passing everything through to another one and recording it
+///
+/// Connection, subscription and table events are recorded once, however many
+/// callbacks are registered for them.
+pub struct RecordingBackend {
+    inner: Arc<dyn StdbBackend>,
+    recorder: SessionRecorder,
+    /// The kinds of events a recording callback is registered for
+    recorded: Mutex<HashSet<String>>,
+}
+
+impl RecordingBackend {
+    /// Record the traffic of `inner` into `recorder`
+    pub fn new(inner: impl StdbBackend, recorder: SessionRecorder) -> Self {
+        Self::wrap(Arc::new(inner), recorder)
+    }
+
+    pub(crate) fn wrap(inner: Arc<dyn StdbBackend>, recorder: SessionRecorder) -> Self {
+        Self {
+            inner,
+            recorder,
+            recorded: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Wrap `callback` to record what it receives, unless a callback registered
+    /// earlier for the same kind of event already does
+    fn record_with(
+        &self,
+        kind: String,
+        callback: BackendCallback,
+        event: impl Fn(serde_json::Value) -> RecordedEvent + Send + Sync + 'static,
+    ) -> BackendCallback {
+        let first = self
+            .recorded
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(kind);
+        if !first {
+            return callback;
+        }
+
+        let recorder = self.recorder.clone();
+        Box::new(move |data: BackendValue| {
+            recorder.record(event(data.to_json()));
+            callback(data);
+        })
+    }
+
+    fn record_table(
+        &self,
+        table: &str,
+        kind: &str,
+        callback: Option<BackendCallback>,
+        event: fn(String, serde_json::Value) -> RecordedEvent,
+    ) -> Option<BackendCallback> {
+        let table = table.to_string();
+        callback.map(|callback| {
+            self.record_with(format!("{}:{}", kind, table), callback, move |data| {
+                event(table.clone(), data)
+            })
+        })
+    }
+}
+
+impl StdbBackend for RecordingBackend {
+    fn encoding(&self) -> ValueEncoding {
+        self.inner.encoding()
+    }
+
+    fn connection_id(&self) -> u32 {
+        self.inner.connection_id()
+    }
+
+    fn connect(&self) {
+        self.recorder.record(RecordedEvent::Connect);
+        self.inner.connect();
+    }
+
+    fn disconnect(&self) {
+        self.recorder.record(RecordedEvent::Disconnect);
+        self.inner.disconnect();
+    }
+
+    fn on_connect(&self, callback: BackendCallback) {
+        self.inner
+            .on_connect(self.record_with("connected".into(), callback, |data| {
+                RecordedEvent::Connected { data }
+            }));
+    }
+
+    fn on_disconnect(&self, callback: BackendCallback) {
+        self.inner
+            .on_disconnect(self.record_with("disconnected".into(), callback, |data| {
+                RecordedEvent::Disconnected { data }
+            }));
+    }
+
+    fn on_connection_error(&self, callback: BackendCallback) {
+        self.inner.on_connection_error(self.record_with(
+            "connection_error".into(),
+            callback,
+            |data| RecordedEvent::ConnectionError { data },
+        ));
+    }
+
+    fn call_reducer(&self, reducer: &str, args: BackendValue, on_result: ResultCallback<()>) {
+        self.recorder.record(RecordedEvent::ReducerCall {
+            reducer: reducer.to_string(),
+            args: args.to_json(),
+        });
+        let recorder = self.recorder.clone();
+        let name = reducer.to_string();
+        self.inner.call_reducer(
+            reducer,
+            args,
+            Box::new(move |result: Result<(), String>| {
+                recorder.record(RecordedEvent::ReducerResult {
+                    reducer: name,
+                    error: result.as_ref().err().cloned(),
+                });
+                on_result(result);
+            }),
+        );
+    }
+
+    fn subscribe(&self, queries: &[String]) -> u32 {
+        // Recorded before subscribing, as the backend may apply it right away
+        let index = self.recorder.record(RecordedEvent::Subscribe {
+            id: 0,
+            queries: queries.to_vec(),
+        });
+        let id = self.inner.subscribe(queries);
+        self.recorder.set_subscription_id(index, id);
+        id
+    }
+
+    fn unsubscribe(&self, subscription_id: u32) {
+        self.recorder.record(RecordedEvent::Unsubscribe {
+            id: subscription_id,
+        });
+        self.inner.unsubscribe(subscription_id);
+    }
+
+    fn on_subscription_applied(&self, callback: BackendCallback) {
+        self.inner.on_subscription_applied(self.record_with(
+            "subscription_applied".into(),
+            callback,
+            |data| RecordedEvent::SubscriptionApplied { data },
+        ));
+    }
+
+    fn on_subscription_error(&self, callback: BackendCallback) {
+        self.inner.on_subscription_error(self.record_with(
+            "subscription_error".into(),
+            callback,
+            |data| RecordedEvent::SubscriptionError { data },
+        ));
+    }
+
+    fn on_subscription_ended(&self, callback: BackendCallback) {
+        self.inner.on_subscription_ended(self.record_with(
+            "subscription_ended".into(),
+            callback,
+            |data| RecordedEvent::SubscriptionEnded { data },
+        ));
+    }
+
+    fn subscribe_table(&self, table: &str, callbacks: TableCallbacks) {
+        let callbacks = TableCallbacks {
+            on_insert: self.record_table(table, "insert", callbacks.on_insert, |table, data| {
+                RecordedEvent::Insert { table, data }
+            }),
+            on_update: self.record_table(table, "update", callbacks.on_update, |table, data| {
+                RecordedEvent::Update { table, data }
+            }),
+            on_delete: self.record_table(table, "delete", callbacks.on_delete, |table, data| {
+                RecordedEvent::Delete { table, data }
+            }),
+        };
+        self.inner.subscribe_table(table, callbacks);
+    }
+
+    fn on_reducer(&self, reducer: &str, callback: BackendCallback) {
+        let name = reducer.to_string();
+        let callback = self.record_with(format!("reducer:{}", reducer), callback, move |data| {
+            RecordedEvent::Reducer {
+                reducer: name.clone(),
+                data,
+            }
+        });
+        self.inner.on_reducer(reducer, callback);
+    }
+
+    fn one_off_query(&self, query: &str, on_result: ResultCallback<BackendValue>) {
+        self.recorder.record(RecordedEvent::OneOffQuery {
+            query: query.to_string(),
+        });
+        let recorder = self.recorder.clone();
+        let name = query.to_string();
+        self.inner.one_off_query(
+            query,
+            Box::new(move |result: Result<BackendValue, String>| {
+                let (rows, error) = match &result {
+                    Ok(rows) => (Some(rows.to_json()), None),
+                    Err(error) => (None, Some(error.clone())),
+                };
+                recorder.record(RecordedEvent::OneOffQueryResult {
+                    query: name,
+                    rows,
+                    error,
+                });
+                on_result(result);
+            }),
+        );
+    }
+}
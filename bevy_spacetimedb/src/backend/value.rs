@@ -0,0 +1,198 @@
+//! Values exchanged with a backend
+
+use std::fmt;
+
+use serde::{de::DeserializeOwned, Serialize};
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::JsValue;
+
+#[cfg(target_arch = "wasm32")]
+use crate::codec;
+
+/// How a backend expects the values it is sent (e.g. reducer arguments) to be encoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueEncoding {
+    /// JS values, with 64-bit and wider integers as `BigInt`
+    #[cfg(target_arch = "wasm32")]
+    Js,
+    /// JSON values
+    Json,
+}
+
+/// A value received from or sent to a backend
+///
+/// Rows, events and reducer arguments are kept in the representation of the
+/// backend they come from and only deserialized once their type is known.
+#[derive(Debug, Clone)]
+pub enum BackendValue {
+    /// A value from the TypeScript bridge
+    #[cfg(target_arch = "wasm32")]
+    Js(JsValue),
+    /// A value from an in-memory backend
+    Json(serde_json::Value),
+}
+
+/// An error converting a [`BackendValue`] from or to a Rust type
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodecError(String);
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for CodecError {}
+
+impl From<serde_json::Error> for CodecError {
+    fn from(error: serde_json::Error) -> Self {
+        Self(error.to_string())
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl From<serde_wasm_bindgen::Error> for CodecError {
+    fn from(error: serde_wasm_bindgen::Error) -> Self {
+        Self(error.to_string())
+    }
+}
+
+impl From<serde_json::Value> for BackendValue {
+    fn from(value: serde_json::Value) -> Self {
+        BackendValue::Json(value)
+    }
+}
+
+impl BackendValue {
+    /// The null value
+    pub fn null() -> Self {
+        BackendValue::Json(serde_json::Value::Null)
+    }
+
+    /// Serialize `value` with the given encoding
+    pub fn encode<T: Serialize + ?Sized>(
+        value: &T,
+        encoding: ValueEncoding,
+    ) -> Result<Self, CodecError> {
+        match encoding {
+            #[cfg(target_arch = "wasm32")]
+            ValueEncoding::Js => Ok(BackendValue::Js(codec::encode_value(value)?)),
+            ValueEncoding::Json => Ok(BackendValue::Json(serde_json::to_value(value)?)),
+        }
+    }
+
+    /// Deserialize this value
+    pub fn decode<T: DeserializeOwned>(&self) -> Result<T, CodecError> {
+        match self {
+            #[cfg(target_arch = "wasm32")]
+            BackendValue::Js(value) => Ok(codec::decode_value(value.clone())?),
+            BackendValue::Json(value) => Ok(T::deserialize(value)?),
+        }
+    }
+
+    /// Get a field of an object, or null if there is no such field
+    pub fn field(&self, name: &str) -> BackendValue {
+        match self {
+            #[cfg(target_arch = "wasm32")]
+            BackendValue::Js(value) => BackendValue::Js(codec::get_field(value, name)),
+            BackendValue::Json(value) => value
+                .get(name)
+                .cloned()
+                .map_or_else(BackendValue::null, BackendValue::Json),
+        }
+    }
+
+    /// Deserialize a field of an object (e.g. `row` or `newRow` of a table event)
+    pub fn decode_field<T: DeserializeOwned>(&self, name: &str) -> Result<T, CodecError> {
+        self.field(name).decode()
+    }
+
+    /// Whether this is null or undefined
+    pub fn is_null(&self) -> bool {
+        match self {
+            #[cfg(target_arch = "wasm32")]
+            BackendValue::Js(value) => value.is_null() || value.is_undefined(),
+            BackendValue::Json(value) => value.is_null(),
+        }
+    }
+
+    /// Get the elements of an array, or nothing if this is not an array
+    pub fn elements(&self) -> Vec<BackendValue> {
+        match self {
+            #[cfg(target_arch = "wasm32")]
+            BackendValue::Js(value) => codec::array_elements(value)
+                .into_iter()
+                .map(BackendValue::Js)
+                .collect(),
+            BackendValue::Json(serde_json::Value::Array(values)) => {
+                values.iter().cloned().map(BackendValue::Json).collect()
+            }
+            BackendValue::Json(_) => Vec::new(),
+        }
+    }
+
+    /// Convert to a JSON value
+    ///
+    /// `BigInt`s within the 64-bit range become numbers and wider ones decimal
+    /// strings; typed arrays become arrays of numbers.
+    pub fn to_json(&self) -> serde_json::Value {
+        match self {
+            #[cfg(target_arch = "wasm32")]
+            BackendValue::Js(value) => codec::to_json_value(value),
+            BackendValue::Json(value) => value.clone(),
+        }
+    }
+
+    /// Render as JSON for diagnostics
+    pub fn to_json_string(&self) -> String {
+        match self {
+            #[cfg(target_arch = "wasm32")]
+            BackendValue::Js(value) => codec::to_json_string(value),
+            BackendValue::Json(value) => value.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Identity;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Row {
+        owner: Identity,
+        #[serde(with = "crate::bytes")]
+        data: Vec<u8>,
+        score: u64,
+    }
+
+    #[test]
+    fn test_json_values_roundtrip() {
+        let row = Row {
+            owner: Identity::from_be_byte_array([7; 32]),
+            data: vec![0, 255],
+            score: u64::MAX,
+        };
+        let event = BackendValue::from(serde_json::json!({
+            "row": BackendValue::encode(&row, ValueEncoding::Json).unwrap().to_json(),
+        }));
+
+        assert_eq!(event.decode_field::<Row>("row").unwrap(), row);
+        assert!(event.field("oldRow").is_null());
+        assert_eq!(event.decode_field::<Option<Row>>("oldRow").unwrap(), None);
+        assert!(event.decode_field::<Row>("oldRow").is_err());
+    }
+
+    #[test]
+    fn test_json_elements() {
+        let rows = BackendValue::from(serde_json::json!([1, 2, 3]));
+        let decoded = rows
+            .elements()
+            .iter()
+            .map(|value| value.decode::<u32>().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(decoded, vec![1, 2, 3]);
+        assert!(BackendValue::null().elements().is_empty());
+    }
+}
@@ -1,8 +1,10 @@
 //! Bridge to the SpacetimeDB TypeScript SDK via wasm-bindgen
 //!
 //! This module provides Rust bindings to the JavaScript bridge layer that wraps
-//! the SpacetimeDB TypeScript SDK. The bridge must be initialized in JavaScript
-//! before the WASM module is loaded.
+//! the SpacetimeDB TypeScript SDK. wasm-bindgen ships `js/spacetimedb-bridge.js`
+//! with the WASM module and the bridge is created on first use. With the
+//! `global-bridge` feature, the page creates the bridge instead, before the WASM
+//! module is loaded.
 
 use wasm_bindgen::prelude::*;
 
@@ -45,6 +47,18 @@ extern "C" {
     #[wasm_bindgen(method, js_name = onConnectionError)]
     pub fn on_connection_error(this: &SpacetimeDBBridge, connection_id: u32, callback_id: u32);
 
+    /// Register a callback for subscription applied events
+    #[wasm_bindgen(method, js_name = onSubscriptionApplied)]
+    pub fn on_subscription_applied(this: &SpacetimeDBBridge, connection_id: u32, callback_id: u32);
+
+    /// Register a callback for subscription error events
+    #[wasm_bindgen(method, js_name = onSubscriptionError)]
+    pub fn on_subscription_error(this: &SpacetimeDBBridge, connection_id: u32, callback_id: u32);
+
+    /// Register a callback for subscription ended events
+    #[wasm_bindgen(method, js_name = onSubscriptionEnded)]
+    pub fn on_subscription_ended(this: &SpacetimeDBBridge, connection_id: u32, callback_id: u32);
+
     /// Call a reducer on the SpacetimeDB server
     #[wasm_bindgen(method, js_name = callReducer)]
     pub fn call_reducer(
@@ -54,13 +68,17 @@ extern "C" {
         args: JsValue,
     ) -> js_sys::Promise;
 
-    /// Subscribe to a SQL query
+    /// Subscribe to a set of SQL queries, returning the subscription ID
     #[wasm_bindgen(method)]
-    pub fn subscribe(
-        this: &SpacetimeDBBridge,
-        connection_id: u32,
-        query: &str,
-    ) -> js_sys::Promise;
+    pub fn subscribe(this: &SpacetimeDBBridge, connection_id: u32, queries: js_sys::Array) -> u32;
+
+    /// End a subscription
+    #[wasm_bindgen(method)]
+    pub fn unsubscribe(this: &SpacetimeDBBridge, connection_id: u32, subscription_id: u32);
+
+    /// Run a SQL query once, resolving with the matching rows
+    #[wasm_bindgen(method, js_name = oneOffQuery)]
+    pub fn one_off_query(this: &SpacetimeDBBridge, connection_id: u32, query: &str) -> js_sys::Promise;
 
     /// Subscribe to table events
     #[wasm_bindgen(method, js_name = subscribeTable)]
@@ -73,6 +91,15 @@ extern "C" {
         on_delete_id: Option<u32>,
     );
 
+    /// Register a callback for the runs of a reducer
+    #[wasm_bindgen(method, js_name = onReducer)]
+    pub fn on_reducer(
+        this: &SpacetimeDBBridge,
+        connection_id: u32,
+        reducer_name: &str,
+        callback_id: u32,
+    );
+
     /// Register a JavaScript callback
     #[wasm_bindgen(method, js_name = registerCallback)]
     pub fn register_callback(this: &SpacetimeDBBridge, callback: &js_sys::Function) -> u32;
@@ -82,7 +109,32 @@ extern "C" {
     pub fn unregister_callback(this: &SpacetimeDBBridge, callback_id: u32);
 }
 
-/// Get the global SpacetimeDB bridge instance
+#[cfg(not(feature = "global-bridge"))]
+#[wasm_bindgen(module = "/js/spacetimedb-bridge.js")]
+extern "C" {
+    /// Create a bridge from the copy of `js/spacetimedb-bridge.js` shipped with the crate
+    #[wasm_bindgen(constructor, js_class = "SpacetimeDBBridge")]
+    fn new_bundled_bridge() -> SpacetimeDBBridge;
+}
+
+/// Get the SpacetimeDB bridge instance
+///
+/// The bridge is created from the copy of `js/spacetimedb-bridge.js` wasm-bindgen
+/// ships with the WASM module, once, and shared by every connection. It imports
+/// `@clockworklabs/spacetimedb-sdk`, which the page resolves through a bundler or
+/// an import map.
+#[cfg(not(feature = "global-bridge"))]
+pub fn get_bridge() -> SpacetimeDBBridge {
+    thread_local! {
+        static BRIDGE: SpacetimeDBBridge = SpacetimeDBBridge::new_bundled_bridge();
+    }
+    BRIDGE.with(Clone::clone)
+}
+
+/// Get the SpacetimeDB bridge instance
+///
+/// With the `global-bridge` feature, this is the bridge the page put in
+/// `__SPACETIMEDB_BRIDGE__`, e.g. a custom bridge or the Node.js test bridge.
 ///
 /// # Panics
 ///
@@ -99,6 +151,7 @@ extern "C" {
 ///   await init();
 /// </script>
 /// ```
+#[cfg(feature = "global-bridge")]
 pub fn get_bridge() -> SpacetimeDBBridge {
     #[wasm_bindgen]
     extern "C" {
@@ -126,8 +179,8 @@ pub fn get_bridge() -> SpacetimeDBBridge {
         ║ SpacetimeDB TypeScript SDK Bridge Not Found!                                ║\n\
         ╠══════════════════════════════════════════════════════════════════════════════╣\n\
         ║                                                                              ║\n\
-        ║ The bevy_spacetimedb_wasm plugin requires the SpacetimeDB TypeScript SDK    ║\n\
-        ║ bridge to be loaded and initialized BEFORE the WASM module loads.           ║\n\
+        ║ With the `global-bridge` feature, bevy_spacetimedb_wasm requires the        ║\n\
+        ║ SpacetimeDB bridge to be initialized BEFORE the WASM module loads.          ║\n\
         ║                                                                              ║\n\
         ║ For browser environments, add this to your HTML file:                       ║\n\
         ║                                                                              ║\n\
@@ -143,7 +196,8 @@ pub fn get_bridge() -> SpacetimeDBBridge {
         ║ For Node.js/test environments:                                              ║\n\
         ║   global.__SPACETIMEDB_BRIDGE__ = new SpacetimeDBBridge();                 ║\n\
         ║                                                                              ║\n\
-        ║ The bridge file should be at: bevy_spacetimedb/js/spacetimedb-bridge.js    ║\n\
+        ║ The bridge file is at: bevy_spacetimedb/js/spacetimedb-bridge.js            ║\n\
+        ║ Without the feature, the crate creates the bridge itself.                   ║\n\
         ║                                                                              ║\n\
         ╚══════════════════════════════════════════════════════════════════════════════╝\n\n"
     )
@@ -154,8 +208,6 @@ mod tests {
     use super::*;
     use wasm_bindgen_test::*;
 
-    wasm_bindgen_test_configure!(run_in_browser);
-
     #[wasm_bindgen_test]
     fn test_bridge_available() {
         // This test will fail if run without the bridge loaded
@@ -0,0 +1,148 @@
+//! Efficient transport of binary columns and reducer arguments
+//!
+//! The TypeScript SDK represents `Vec<u8>` columns as `Uint8Array`. Serde treats a
+//! plain `Vec<u8>` as a sequence of numbers, which crosses the bridge one element at
+//! a time. Using this module (or the [`Bytes`] wrapper) makes serde treat the data
+//! as a byte buffer, so it is copied to and from a `Uint8Array` in one go.
+//!
+//! # Example
+//! ```ignore
+//! #[derive(Debug, Clone, Deserialize, TableRow)]
+//! pub struct Avatar {
+//!     #[stdb(primary_key)]
+//!     pub id: u64,
+//!     #[serde(with = "bevy_spacetimedb_wasm::bytes")]
+//!     pub image: Vec<u8>,
+//! }
+//!
+//! // Reducer arguments are tuples, so use the `Bytes` wrapper there
+//! pub struct UploadAvatar;
+//!
+//! impl Reducer for UploadAvatar {
+//!     const NAME: &'static str = "upload_avatar";
+//!     type Args = (u64, Bytes);
+//! }
+//! ```
+
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+
+use serde::de::{self, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Serialize bytes as a byte buffer (`Uint8Array` on the JS side)
+pub fn serialize<T, S>(bytes: &T, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: AsRef<[u8]> + ?Sized,
+    S: Serializer,
+{
+    serializer.serialize_bytes(bytes.as_ref())
+}
+
+/// Deserialize bytes from a byte buffer (`Uint8Array` on the JS side) or an array of numbers
+pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<u8>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserializer.deserialize_byte_buf(BytesVisitor)
+}
+
+struct BytesVisitor;
+
+impl<'de> Visitor<'de> for BytesVisitor {
+    type Value = Vec<u8>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a byte array")
+    }
+
+    fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<Self::Value, E> {
+        Ok(bytes.to_vec())
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, bytes: Vec<u8>) -> Result<Self::Value, E> {
+        Ok(bytes)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(byte) = seq.next_element()? {
+            bytes.push(byte);
+        }
+        Ok(bytes)
+    }
+}
+
+/// A `Vec<u8>` that crosses the bridge as a `Uint8Array`
+///
+/// Use this where a `#[serde(with = "bevy_spacetimedb_wasm::bytes")]` attribute
+/// cannot be placed, e.g. in reducer argument tuples or inside `Option`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Bytes(pub Vec<u8>);
+
+impl Serialize for Bytes {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize(&self.0, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Bytes {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize(deserializer).map(Bytes)
+    }
+}
+
+impl Deref for Bytes {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for Bytes {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl From<Vec<u8>> for Bytes {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+}
+
+impl From<Bytes> for Vec<u8> {
+    fn from(bytes: Bytes) -> Self {
+        bytes.0
+    }
+}
+
+impl AsRef<[u8]> for Bytes {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Blob {
+        #[serde(with = "crate::bytes")]
+        data: Vec<u8>,
+        extra: Option<Bytes>,
+    }
+
+    #[test]
+    fn test_bytes_roundtrip_through_serde_json() {
+        let blob = Blob {
+            data: vec![0, 1, 254, 255],
+            extra: Some(Bytes(vec![7; 3])),
+        };
+        let json = serde_json::to_string(&blob).unwrap();
+        assert_eq!(json, r#"{"data":[0,1,254,255],"extra":[7,7,7]}"#);
+        assert_eq!(serde_json::from_str::<Blob>(&json).unwrap(), blob);
+    }
+}
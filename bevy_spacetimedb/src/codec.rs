@@ -0,0 +1,243 @@
+//! Conversion of rows between JavaScript values and Rust types
+//!
+//! Rows cross the bridge as plain JS objects and are deserialized directly into
+//! the row type with `serde_wasm_bindgen`, without going through a JSON string.
+//!
+//! The TypeScript SDK represents 64-bit and wider integers as `BigInt`. Decoding
+//! accepts both `BigInt` and plain numbers for `u64`/`i64`/`u128`/`i128`, and
+//! encoding always produces `BigInt` for them so no precision is lost.
+//!
+//! Binary data marked with [`crate::bytes`] or wrapped in [`crate::Bytes`] is
+//! copied to and from a `Uint8Array` in one go.
+
+use serde::{de::DeserializeOwned, Serialize};
+use wasm_bindgen::{prelude::Closure, JsCast, JsValue};
+
+/// Serializer producing `BigInt` for 64-bit and 128-bit integers
+const SERIALIZER: serde_wasm_bindgen::Serializer =
+    serde_wasm_bindgen::Serializer::new().serialize_large_number_types_as_bigints(true);
+
+/// Serialize a value (e.g. reducer arguments) into a JS value for the bridge
+pub(crate) fn encode_value<T: Serialize + ?Sized>(
+    value: &T,
+) -> Result<JsValue, serde_wasm_bindgen::Error> {
+    value.serialize(&SERIALIZER)
+}
+
+/// Get a field of a JS object, or `undefined` if there is no such field
+pub(crate) fn get_field(data: &JsValue, field: &str) -> JsValue {
+    js_sys::Reflect::get(data, &JsValue::from_str(field)).unwrap_or(JsValue::UNDEFINED)
+}
+
+/// Deserialize a JS value (e.g. a row of a table event)
+pub(crate) fn decode_value<T: DeserializeOwned>(
+    value: JsValue,
+) -> Result<T, serde_wasm_bindgen::Error> {
+    serde_wasm_bindgen::from_value(value)
+}
+
+/// Get the elements of a JS array (e.g. the rows returned by a one-off query)
+pub(crate) fn array_elements(value: &JsValue) -> Vec<JsValue> {
+    if js_sys::Array::is_array(value) {
+        js_sys::Array::from(value).iter().collect()
+    } else {
+        Vec::new()
+    }
+}
+
+/// Render a JS value as JSON for diagnostics
+///
+/// Unlike a plain `JSON.stringify`, this does not throw on `BigInt` (rendered as a
+/// decimal string) and renders typed arrays as arrays of numbers.
+pub(crate) fn to_json_string(value: &JsValue) -> String {
+    let replacer = Closure::<dyn FnMut(JsValue, JsValue) -> JsValue>::new(
+        |_key: JsValue, value: JsValue| {
+            if let Some(bigint) = value.dyn_ref::<js_sys::BigInt>() {
+                bigint
+                    .to_string(10)
+                    .map(JsValue::from)
+                    .unwrap_or(JsValue::UNDEFINED)
+            } else if let Some(bytes) = value.dyn_ref::<js_sys::Uint8Array>() {
+                js_sys::Array::from(bytes).into()
+            } else {
+                value
+            }
+        },
+    );
+
+    js_sys::JSON::stringify_with_replacer(value, replacer.as_ref().unchecked_ref())
+        .ok()
+        .and_then(|json| json.as_string())
+        .unwrap_or_else(|| format!("{:?}", value))
+}
+
+/// Convert a JS value to JSON, e.g. to record it
+///
+/// `BigInt`s within the 64-bit range become numbers and wider ones decimal
+/// strings, so the values of `u64`/`i64` columns and connection ids decode
+/// from the result.
+/// Typed arrays become arrays of numbers.
+pub(crate) fn to_json_value(value: &JsValue) -> serde_json::Value {
+    use serde_json::Value;
+
+    if value.is_null() || value.is_undefined() {
+        Value::Null
+    } else if let Some(boolean) = value.as_bool() {
+        Value::Bool(boolean)
+    } else if let Some(bigint) = value.dyn_ref::<js_sys::BigInt>() {
+        if let Ok(signed) = i64::try_from(value.clone()) {
+            signed.into()
+        } else if let Ok(unsigned) = u64::try_from(value.clone()) {
+            unsigned.into()
+        } else {
+            bigint
+                .to_string(10)
+                .ok()
+                .and_then(|digits| digits.as_string())
+                .map_or(Value::Null, Value::String)
+        }
+    } else if let Some(number) = value.as_f64() {
+        if number.fract() == 0.0 && number.abs() <= 9_007_199_254_740_991.0 {
+            (number as i64).into()
+        } else {
+            serde_json::Number::from_f64(number).map_or(Value::Null, Value::Number)
+        }
+    } else if let Some(string) = value.as_string() {
+        Value::String(string)
+    } else if let Some(bytes) = value.dyn_ref::<js_sys::Uint8Array>() {
+        bytes.to_vec().into()
+    } else if js_sys::Array::is_array(value) {
+        array_elements(value).iter().map(to_json_value).collect()
+    } else if value.is_object() {
+        js_sys::Object::entries(value.unchecked_ref())
+            .iter()
+            .filter_map(|entry| {
+                let entry = js_sys::Array::from(&entry);
+                Some((entry.get(0).as_string()?, to_json_value(&entry.get(1))))
+            })
+            .collect::<serde_json::Map<_, _>>()
+            .into()
+    } else {
+        Value::Null
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use wasm_bindgen_test::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct WideRow {
+        a: u64,
+        b: i64,
+        c: i64,
+        d: u128,
+        e: i128,
+        f: i128,
+    }
+
+    fn decode_field<T: DeserializeOwned>(
+        data: &JsValue,
+        field: &str,
+    ) -> Result<T, serde_wasm_bindgen::Error> {
+        decode_value(get_field(data, field))
+    }
+
+    fn roundtrip(row: &WideRow) -> WideRow {
+        let data = js_sys::Object::new();
+        js_sys::Reflect::set(&data, &"row".into(), &encode_value(row).unwrap()).unwrap();
+        decode_field(&data, "row").unwrap()
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wide_integers_roundtrip_at_boundaries() {
+        let max = WideRow {
+            a: u64::MAX,
+            b: i64::MAX,
+            c: i64::MIN,
+            d: u128::MAX,
+            e: i128::MAX,
+            f: i128::MIN,
+        };
+        assert_eq!(roundtrip(&max), max);
+
+        let min = WideRow {
+            a: 0,
+            b: 0,
+            c: -1,
+            d: 0,
+            e: 0,
+            f: -1,
+        };
+        assert_eq!(roundtrip(&min), min);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wide_integers_encode_as_bigint() {
+        let encoded = encode_value(&(u64::MAX, i64::MIN, u128::MAX)).unwrap();
+        let array = js_sys::Array::from(&encoded);
+        assert!(array.iter().all(|value| value.is_bigint()));
+        assert_eq!(
+            String::from(
+                array
+                    .get(0)
+                    .unchecked_into::<js_sys::BigInt>()
+                    .to_string(10)
+                    .unwrap()
+            ),
+            "18446744073709551615"
+        );
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct BlobRow {
+        #[serde(with = "crate::bytes")]
+        data: Vec<u8>,
+    }
+
+    #[wasm_bindgen_test]
+    fn test_bytes_cross_as_uint8_array() {
+        let row = BlobRow {
+            data: vec![0, 1, 128, 255],
+        };
+        let encoded = encode_value(&row).unwrap();
+        let data = js_sys::Reflect::get(&encoded, &"data".into()).unwrap();
+        assert!(data.is_instance_of::<js_sys::Uint8Array>());
+
+        let event = js_sys::Object::new();
+        js_sys::Reflect::set(&event, &"row".into(), &encoded).unwrap();
+        assert_eq!(decode_field::<BlobRow>(&event, "row").unwrap(), row);
+
+        let (bytes,): (crate::Bytes,) =
+            serde_wasm_bindgen::from_value(encode_value(&(crate::Bytes(vec![9, 8]),)).unwrap())
+                .unwrap();
+        assert_eq!(bytes.0, vec![9, 8]);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_to_json_string_handles_bigint_and_bytes() {
+        let value = encode_value(&(u64::MAX, crate::Bytes(vec![1, 2]))).unwrap();
+        assert_eq!(to_json_string(&value), r#"["18446744073709551615",[1,2]]"#);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_to_json_value_keeps_64_bit_integers() {
+        let value = encode_value(&(u64::MAX, -1i64, u128::MAX, crate::Bytes(vec![1, 2]))).unwrap();
+        assert_eq!(
+            to_json_value(&value),
+            serde_json::json!([u64::MAX, -1, u128::MAX.to_string(), [1, 2]])
+        );
+    }
+
+    #[wasm_bindgen_test]
+    fn test_u64_decodes_from_bigint_and_number() {
+        let data = js_sys::JSON::parse(r#"{"small":42}"#).unwrap();
+        js_sys::Reflect::set(&data, &"big".into(), &JsValue::from(u64::MAX)).unwrap();
+
+        assert_eq!(decode_field::<u64>(&data, "small").unwrap(), 42);
+        assert_eq!(decode_field::<u64>(&data, "big").unwrap(), u64::MAX);
+        assert!(decode_field::<u32>(&data, "big").is_err());
+    }
+}
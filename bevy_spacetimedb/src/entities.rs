@@ -0,0 +1,246 @@
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+use bevy::prelude::*;
+
+use crate::{
+    tables::{setup_table_events_with_changes, RowChange, TableConfig},
+    AddEventChannelAppExtensions, HasPrimaryKey, StdbPlugin, TableEvents,
+};
+
+/// Marker component for entities that mirror a row of table `T`
+///
+/// Spawned alongside the row component by [`StdbPlugin::add_table_as_entities`].
+#[derive(Component, Debug, Clone)]
+pub struct StdbRow<T: HasPrimaryKey> {
+    key: T::PrimaryKey,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T: HasPrimaryKey> StdbRow<T> {
+    /// Create a new marker for the row with the given primary key
+    pub fn new(key: T::PrimaryKey) -> Self {
+        Self {
+            key,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Get the primary key of the mirrored row
+    pub fn key(&self) -> &T::PrimaryKey {
+        &self.key
+    }
+}
+
+/// Lookup from primary key to the entity mirroring that row
+///
+/// Kept up to date by [`StdbPlugin::add_table_as_entities`].
+///
+/// # Example
+/// ```ignore
+/// fn highlight_player(players: Res<StdbEntityMap<Player>>, mut commands: Commands) {
+///     if let Some(entity) = players.get(&42) {
+///         commands.entity(entity).insert(Highlighted);
+///     }
+/// }
+/// ```
+#[derive(Resource)]
+pub struct StdbEntityMap<T: HasPrimaryKey> {
+    entities: HashMap<T::PrimaryKey, Entity>,
+}
+
+impl<T: HasPrimaryKey> Default for StdbEntityMap<T> {
+    fn default() -> Self {
+        Self {
+            entities: HashMap::new(),
+        }
+    }
+}
+
+impl<T: HasPrimaryKey> StdbEntityMap<T> {
+    /// Get the entity mirroring the row with the given primary key
+    pub fn get(&self, key: &T::PrimaryKey) -> Option<Entity> {
+        self.entities.get(key).copied()
+    }
+
+    /// Iterate over all primary keys and their entities
+    pub fn iter(&self) -> impl Iterator<Item = (&T::PrimaryKey, Entity)> {
+        self.entities.iter().map(|(key, entity)| (key, *entity))
+    }
+
+    /// Number of mirrored rows
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    /// Whether no rows are currently mirrored
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+}
+
+impl StdbPlugin {
+    /// Register a table and mirror each of its rows as a Bevy entity
+    ///
+    /// Every inserted row spawns an entity with the row as a component and a
+    /// [`StdbRow<T>`] marker. Updates replace the component in place and deletes
+    /// despawn the entity, in the order the changes arrived. The
+    /// [`StdbEntityMap<T>`] resource maps primary keys to entities.
+    ///
+    /// The regular `InsertEvent<T>`, `UpdateEvent<T>` and `DeleteEvent<T>` events
+    /// are still sent.
+    ///
+    /// # Example
+    /// ```ignore
+    /// StdbPlugin::default()
+    ///     .add_table_as_entities::<Player>()
+    /// ```
+    pub fn add_table_as_entities<T: HasPrimaryKey + Component>(mut self) -> Self {
+        self.table_configs.push(TableConfig {
+            table_name: T::TABLE_NAME.to_string(),
+            events: TableEvents::all(),
+            setup_fn: Box::new(|context, events, app| {
+                let (send, recv) = std::sync::mpsc::channel::<RowChange<T>>();
+                app.add_event_channel(recv);
+                setup_table_events_with_changes::<T>(context, events, app, Some(send));
+                setup_entity_mirroring::<T>(app);
+            }),
+        });
+        self
+    }
+}
+
+/// Setup the entity map and the system keeping entities in sync with table events
+pub(crate) fn setup_entity_mirroring<T: HasPrimaryKey + Component>(app: &mut App) {
+    app.init_resource::<StdbEntityMap<T>>()
+        .add_systems(Update, mirror_rows_as_entities::<T>);
+}
+
+/// Apply the changes of table `T` to the mirroring entities, in arrival order
+fn mirror_rows_as_entities<T: HasPrimaryKey + Component>(
+    mut commands: Commands,
+    mut map: ResMut<StdbEntityMap<T>>,
+    mut changes: MessageReader<RowChange<T>>,
+) {
+    for change in changes.read() {
+        match change {
+            RowChange::Insert(row) => {
+                let key = row.primary_key();
+                match map.entities.get(&key) {
+                    Some(&entity) => {
+                        commands.entity(entity).try_insert(row.clone());
+                    }
+                    None => {
+                        let entity = commands
+                            .spawn((row.clone(), StdbRow::<T>::new(key.clone())))
+                            .id();
+                        map.entities.insert(key, entity);
+                    }
+                }
+            }
+            RowChange::Update { old, new } => {
+                let new_key = new.primary_key();
+                match map.entities.remove(&old.primary_key()) {
+                    Some(entity) => {
+                        commands
+                            .entity(entity)
+                            .try_insert((new.clone(), StdbRow::<T>::new(new_key.clone())));
+                        map.entities.insert(new_key, entity);
+                    }
+                    None => {
+                        let entity = commands
+                            .spawn((new.clone(), StdbRow::<T>::new(new_key.clone())))
+                            .id();
+                        map.entities.insert(new_key, entity);
+                    }
+                }
+            }
+            RowChange::Delete(row) => {
+                if let Some(entity) = map.entities.remove(&row.primary_key()) {
+                    commands.entity(entity).try_despawn();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{InsertEvent, StdbTestApp, TableRow};
+
+    #[derive(Component, Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct Ship {
+        id: u32,
+        hull: u32,
+    }
+
+    impl TableRow for Ship {
+        const TABLE_NAME: &'static str = "ship";
+    }
+
+    impl HasPrimaryKey for Ship {
+        type PrimaryKey = u32;
+
+        fn primary_key(&self) -> u32 {
+            self.id
+        }
+    }
+
+    fn test_app() -> StdbTestApp {
+        let test = StdbTestApp::new(StdbPlugin::default().add_table_as_entities::<Ship>());
+        test.server().connect(None);
+        test
+    }
+
+    #[test]
+    fn test_rows_are_mirrored_as_entities() {
+        let mut test = test_app();
+
+        test.server().insert(Ship { id: 1, hull: 100 });
+        test.update();
+
+        let entity = test
+            .world()
+            .resource::<StdbEntityMap<Ship>>()
+            .get(&1)
+            .expect("inserted row should have an entity");
+        assert_eq!(test.world().get::<Ship>(entity).unwrap().hull, 100);
+        assert_eq!(test.world().get::<StdbRow<Ship>>(entity).unwrap().key(), &1);
+        test.assert_message::<InsertEvent<Ship>>(|event| event.row.id == 1);
+
+        test.server()
+            .update(Ship { id: 1, hull: 100 }, Ship { id: 1, hull: 40 });
+        test.update();
+
+        assert_eq!(test.world().get::<Ship>(entity).unwrap().hull, 40);
+
+        test.server().delete(Ship { id: 1, hull: 40 });
+        test.update();
+
+        assert!(test.world().get_entity(entity).is_err());
+        assert!(test.world().resource::<StdbEntityMap<Ship>>().is_empty());
+    }
+
+    #[test]
+    fn test_changes_in_one_frame_keep_their_order() {
+        let mut test = test_app();
+        test.server().insert(Ship { id: 1, hull: 100 });
+        test.update();
+
+        // Deleted then inserted again, e.g. as a subscription is replaced
+        test.server().delete(Ship { id: 1, hull: 100 });
+        test.server().insert(Ship { id: 1, hull: 80 });
+        // Inserted then deleted
+        test.server().insert(Ship { id: 2, hull: 100 });
+        test.server().delete(Ship { id: 2, hull: 100 });
+        test.update();
+
+        let map = test.world().resource::<StdbEntityMap<Ship>>();
+        assert_eq!(map.len(), 1);
+        let entity = map.get(&1).expect("re-inserted row should have an entity");
+        assert_eq!(test.world().get::<Ship>(entity).unwrap().hull, 80);
+        let ships = test.world_mut().query::<&Ship>().iter(test.world()).count();
+        assert_eq!(ships, 1);
+    }
+}
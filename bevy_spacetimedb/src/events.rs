@@ -1,10 +1,13 @@
 use bevy::prelude::Message;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{ConnectionId, Identity, SubscriptionId, Timestamp};
 
 /// An event that is triggered when a connection to SpacetimeDB is established.
 #[derive(Message, Debug, Clone)]
 pub struct StdbConnectedEvent {
-    // Note: In WASM mode, we don't have direct access to Identity/tokens
-    // These would need to be queried from the JS side if needed
+    /// The identity of this client, if the SDK reported one.
+    pub identity: Option<Identity>,
 }
 
 /// An event that is triggered when a connection to SpacetimeDB is lost.
@@ -21,11 +24,229 @@ pub struct StdbConnectionErrorEvent {
     pub err: String,
 }
 
+/// An event that is triggered when a reducer call fails.
+#[derive(Message, Debug, Clone)]
+pub struct StdbReducerErrorEvent {
+    /// The name of the reducer that was called.
+    pub reducer: String,
+    /// The error message returned for the call.
+    pub err: String,
+}
+
+/// An event that is triggered when a subscription has been applied.
+///
+/// All rows matching the subscription have been delivered by the time this is
+/// sent; they arrived as `InsertEvent`s with [`EventContext::Subscription`].
+/// Inserts after this point are live changes.
+#[derive(Message, Debug, Clone, Deserialize)]
+pub struct SubscriptionAppliedEvent {
+    /// The id of the subscription, see `SubscriptionHandle::id`.
+    pub id: SubscriptionId,
+    /// The queries of the applied subscription.
+    pub queries: Vec<String>,
+}
+
+/// An event that is triggered when a subscription fails.
+///
+/// The subscription has ended and will not deliver any more rows.
+#[derive(Message, Debug, Clone, Deserialize)]
+pub struct SubscriptionErrorEvent {
+    /// The id of the subscription, see `SubscriptionHandle::id`.
+    pub id: SubscriptionId,
+    /// The queries of the failed subscription.
+    pub queries: Vec<String>,
+    /// The error message that occurred.
+    pub error: String,
+}
+
+/// An event that is triggered when a subscription has ended after `SubscriptionHandle::unsubscribe`.
+#[derive(Message, Debug, Clone, Deserialize)]
+pub struct SubscriptionEndedEvent {
+    /// The id of the subscription, see `SubscriptionHandle::id`.
+    pub id: SubscriptionId,
+    /// The queries of the ended subscription.
+    pub queries: Vec<String>,
+}
+
+/// An event that is triggered when a subscription managed by this crate has been replaced.
+///
+/// Sent for subscriptions re-issued by `add_stdb_subscription_for_resource` once the
+/// new subscription has been applied and the previous one unsubscribed. The rows
+/// entering and leaving scope follow in a [`SubscriptionDiffEvent`] for the tables
+/// registered with `add_stdb_subscription_diff`.
+#[derive(Message, Debug, Clone)]
+pub struct SubscriptionReplacedEvent {
+    /// The id of the replaced subscription, or `None` for the first one.
+    pub previous: Option<SubscriptionId>,
+    /// The id of the subscription now in effect.
+    pub current: SubscriptionId,
+}
+
+/// An event listing the rows of table `T` that entered and left scope when a subscription was replaced.
+///
+/// Sent by `add_stdb_subscription_diff` once the previous subscription has ended.
+#[derive(Message, Debug, Clone)]
+pub struct SubscriptionDiffEvent<T> {
+    /// The id of the replaced subscription, or `None` for the first one.
+    pub previous: Option<SubscriptionId>,
+    /// The id of the subscription now in effect.
+    pub current: SubscriptionId,
+    /// The rows covered by the new subscription only.
+    pub entering: Vec<T>,
+    /// The rows covered by the previous subscription only.
+    pub leaving: Vec<T>,
+}
+
+/// What caused a table event.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum EventContext {
+    /// The change was made by a reducer call.
+    Reducer(ReducerEventContext),
+    /// The row was part of the initial rows of a subscription being applied.
+    Subscription,
+    /// The row was removed because a subscription covering it ended.
+    Unsubscription,
+    /// The cause of the change is not known.
+    #[default]
+    Unknown,
+}
+
+impl EventContext {
+    /// The reducer call that caused the change, if any.
+    pub fn reducer(&self) -> Option<&ReducerEventContext> {
+        match self {
+            EventContext::Reducer(reducer) => Some(reducer),
+            _ => None,
+        }
+    }
+
+    /// Whether the row was part of the initial rows of a subscription.
+    ///
+    /// Use this to bulk-load existing rows quietly and only react to live inserts.
+    pub fn is_subscription(&self) -> bool {
+        matches!(self, EventContext::Subscription)
+    }
+
+    /// Whether the row was removed because a subscription covering it ended.
+    ///
+    /// Such deletes mean the row left the client's scope, not that it was deleted
+    /// on the server.
+    pub fn is_unsubscription(&self) -> bool {
+        matches!(self, EventContext::Unsubscription)
+    }
+
+    /// Whether the change was made by a reducer called by `identity`.
+    ///
+    /// Compare against the identity from `StdbConnectedEvent` to detect changes
+    /// caused by the local player.
+    pub fn is_caused_by(&self, identity: &Identity) -> bool {
+        self.reducer()
+            .is_some_and(|reducer| reducer.caller_identity == *identity)
+    }
+}
+
+/// Details of the reducer call that caused a table event.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReducerEventContext {
+    /// The name of the reducer.
+    pub reducer_name: String,
+    /// The identity of the client that called the reducer.
+    pub caller_identity: Identity,
+    /// The connection of the client that called the reducer, if any.
+    #[serde(default)]
+    pub caller_connection_id: Option<ConnectionId>,
+    /// When the reducer ran, if the SDK reported it.
+    #[serde(default)]
+    pub timestamp: Option<Timestamp>,
+    /// The reducer arguments, or `None` if they could not be represented as JSON.
+    #[serde(skip)]
+    pub args: Option<serde_json::Value>,
+}
+
+impl ReducerEventContext {
+    /// Deserialize the reducer arguments, e.g. into the `Args` tuple of a `Reducer`.
+    pub fn args_as<A: DeserializeOwned>(&self) -> Option<A> {
+        serde_json::from_value(self.args.clone()?).ok()
+    }
+}
+
+/// The outcome of a reducer call that ran on the server.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "tag", content = "value")]
+pub enum ReducerStatus {
+    /// The reducer succeeded and its changes were committed.
+    Committed,
+    /// The reducer failed with the given error; its changes were rolled back.
+    Failed(String),
+    /// The reducer ran out of energy; its changes were rolled back.
+    OutOfEnergy,
+}
+
+/// A reducer call that ran on the server, as carried by `RegisterReducerEvent` structs.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReducerEvent {
+    /// The name of the reducer.
+    pub reducer_name: String,
+    /// The identity of the client that called the reducer.
+    pub caller_identity: Identity,
+    /// The connection of the client that called the reducer, if any.
+    #[serde(default)]
+    pub caller_connection_id: Option<ConnectionId>,
+    /// When the reducer ran, if the SDK reported it.
+    #[serde(default)]
+    pub timestamp: Option<Timestamp>,
+    /// Whether the reducer succeeded.
+    pub status: ReducerStatus,
+}
+
+impl ReducerEvent {
+    /// Whether the reducer succeeded and its changes were committed.
+    pub fn is_committed(&self) -> bool {
+        self.status == ReducerStatus::Committed
+    }
+
+    /// Whether the reducer was called by `identity`.
+    pub fn is_caused_by(&self, identity: &Identity) -> bool {
+        self.caller_identity == *identity
+    }
+}
+
+/// The kind of table event a row was received for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableEventKind {
+    /// A row insertion.
+    Insert,
+    /// A row update.
+    Update,
+    /// A row deletion.
+    Delete,
+}
+
+/// An event that is triggered when a row received from SpacetimeDB cannot be deserialized.
+///
+/// This usually means the Rust row type no longer matches the server schema.
+#[derive(Message, Debug, Clone)]
+pub struct StdbDecodeErrorEvent {
+    /// The name of the table the row belongs to.
+    pub table: String,
+    /// The kind of table event that carried the row.
+    pub event_kind: TableEventKind,
+    /// The raw event payload as received from the bridge, as JSON.
+    pub raw_json: String,
+    /// The deserialization error.
+    pub error: String,
+}
+
 /// An event that is triggered when a row is inserted into a table.
 #[derive(Message, Debug, Clone)]
 pub struct InsertEvent<T> {
     /// The row that was inserted.
     pub row: T,
+    /// What caused the insertion.
+    pub ctx: EventContext,
 }
 
 /// An event that is triggered when a row is deleted from a table.
@@ -33,6 +254,8 @@ pub struct InsertEvent<T> {
 pub struct DeleteEvent<T> {
     /// The row that was deleted.
     pub row: T,
+    /// What caused the deletion.
+    pub ctx: EventContext,
 }
 
 /// An event that is triggered when a row is updated in a table.
@@ -42,6 +265,8 @@ pub struct UpdateEvent<T> {
     pub old: T,
     /// The new row.
     pub new: T,
+    /// What caused the update.
+    pub ctx: EventContext,
 }
 
 /// An event that is triggered when a row is inserted or updated in a table.
@@ -51,9 +276,11 @@ pub struct InsertUpdateEvent<T> {
     pub old: Option<T>,
     /// The new value of the row or the inserted value.
     pub new: T,
+    /// What caused the insertion or update.
+    pub ctx: EventContext,
 }
 
-/// An event that is triggered when a reducer is called and returns a result.
+/// An event that is triggered when a reducer registered with `StdbPlugin::add_reducer` runs.
 #[derive(Message, Debug, Clone)]
 pub struct ReducerResultEvent<T> {
     /// The reducer result data.
@@ -66,3 +293,37 @@ impl<T> ReducerResultEvent<T> {
         Self { result }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_context_decodes_from_bridge_payload() {
+        let ctx: EventContext = serde_json::from_str(
+            r#"{
+                "kind": "reducer",
+                "reducerName": "move_player",
+                "callerIdentity": { "__identity__": "0x0000000000000000000000000000000000000000000000000000000000000001" },
+                "callerConnectionId": null,
+                "timestamp": { "__timestamp_micros_since_unix_epoch__": 5 }
+            }"#,
+        )
+        .unwrap();
+
+        let reducer = ctx.reducer().expect("reducer context");
+        assert_eq!(reducer.reducer_name, "move_player");
+        assert_eq!(
+            reducer.timestamp,
+            Some(Timestamp::from_micros_since_unix_epoch(5))
+        );
+        let mut caller = [0; 32];
+        caller[31] = 1;
+        assert!(ctx.is_caused_by(&Identity::from_be_byte_array(caller)));
+        assert!(!ctx.is_caused_by(&Identity::ZERO));
+
+        let ctx: EventContext = serde_json::from_str(r#"{"kind":"subscription"}"#).unwrap();
+        assert!(ctx.is_subscription());
+        assert!(ctx.reducer().is_none());
+    }
+}
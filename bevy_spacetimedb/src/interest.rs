@@ -0,0 +1,423 @@
+//! Spatial interest management: subscriptions that follow a focus entity
+//!
+//! Space is divided into a grid of cubic cells. The focus entity subscribes to
+//! the rows within `radius` of the center of the cell it is in, snapped outward
+//! to cell boundaries. When the focus leaves its cell by more than the
+//! hysteresis margin the region is re-centered and a new subscription replaces
+//! the old one without a gap.
+
+use std::marker::PhantomData;
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::{
+    subscriptions::ReplaceableSubscription, Column, Condition, SqlLiteral, StdbConnection,
+    StdbQuery, SubscriptionHandle, SubscriptionReplacedEvent, TableRow,
+};
+
+/// Column types usable as spatial coordinates
+pub trait Coordinate: SqlLiteral + Send + Sync + 'static {
+    /// Convert a region bound to the column type
+    fn from_f64(value: f64) -> Self;
+}
+
+impl Coordinate for f32 {
+    fn from_f64(value: f64) -> Self {
+        value as f32
+    }
+}
+
+impl Coordinate for f64 {
+    fn from_f64(value: f64) -> Self {
+        value
+    }
+}
+
+/// Builds the `min <= column <= max` condition of one axis
+type AxisBounds<T> = Box<dyn Fn(f64, f64) -> Condition<T> + Send + Sync>;
+
+/// Keeps a subscription to the rows of table `T` around the entity it is added to
+///
+/// Requires [`SpatialInterestAppExtensions::add_spatial_interest`] to be called
+/// for `T`. The entity's `GlobalTransform` translation is matched against the
+/// columns given for each axis; axes without a column are not filtered. A
+/// [`SubscriptionReplacedEvent`] is sent each time the region moves. The
+/// subscription ends when the component is removed.
+///
+/// # Example
+/// ```ignore
+/// commands.spawn((
+///     Camera3d::default(),
+///     SpatialInterest::<StarSystem>::new()
+///         .x(col!(StarSystem::x))
+///         .y(col!(StarSystem::y))
+///         .z(col!(StarSystem::z))
+///         .with_cell_size(100.0)
+///         .with_radius(500.0),
+/// ));
+/// ```
+#[derive(Component)]
+pub struct SpatialInterest<T: TableRow> {
+    axes: [Option<AxisBounds<T>>; 3],
+    filter: Option<Condition<T>>,
+    cell_size: f64,
+    radius: f64,
+    hysteresis: Option<f64>,
+    timer: Timer,
+    /// Whether to check the position without waiting for the timer
+    check_now: bool,
+    /// The cell of the region covered by the current subscription
+    center: Option<[i64; 3]>,
+    /// The cell of the region of the pending subscription
+    pending_center: Option<[i64; 3]>,
+    subscription: ReplaceableSubscription,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T: TableRow> Default for SpatialInterest<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: TableRow> SpatialInterest<T> {
+    /// Create a spatial interest with 100 unit cells and a radius of 500 units
+    pub fn new() -> Self {
+        Self {
+            axes: [None, None, None],
+            filter: None,
+            cell_size: 100.0,
+            radius: 500.0,
+            hysteresis: None,
+            timer: Timer::new(Duration::from_millis(500), TimerMode::Repeating),
+            check_now: true,
+            center: None,
+            pending_center: None,
+            subscription: ReplaceableSubscription::default(),
+            _marker: PhantomData,
+        }
+    }
+
+    fn axis<V: Coordinate>(mut self, index: usize, column: Column<T, V>) -> Self {
+        self.axes[index] = Some(Box::new(move |min, max| {
+            column
+                .ge(V::from_f64(min))
+                .and(column.le(V::from_f64(max)))
+        }));
+        self
+    }
+
+    /// Match the translation's x against `column`
+    pub fn x<V: Coordinate>(self, column: Column<T, V>) -> Self {
+        self.axis(0, column)
+    }
+
+    /// Match the translation's y against `column`
+    pub fn y<V: Coordinate>(self, column: Column<T, V>) -> Self {
+        self.axis(1, column)
+    }
+
+    /// Match the translation's z against `column`
+    pub fn z<V: Coordinate>(self, column: Column<T, V>) -> Self {
+        self.axis(2, column)
+    }
+
+    /// Only subscribe to rows that also match `condition`
+    pub fn with_filter(mut self, condition: Condition<T>) -> Self {
+        self.filter = Some(condition);
+        self
+    }
+
+    /// Set the size of the grid cells
+    pub fn with_cell_size(mut self, cell_size: f64) -> Self {
+        self.cell_size = cell_size;
+        self
+    }
+
+    /// Set the distance from the center of the focus cell to subscribe to
+    pub fn with_radius(mut self, radius: f64) -> Self {
+        self.radius = radius;
+        self
+    }
+
+    /// Set how far the focus may leave its cell before the region is re-centered
+    ///
+    /// Defaults to a quarter of the cell size.
+    pub fn with_hysteresis(mut self, hysteresis: f64) -> Self {
+        self.hysteresis = Some(hysteresis);
+        self
+    }
+
+    /// Set how often the focus position is checked
+    ///
+    /// Defaults to every 500 ms.
+    pub fn with_update_interval(mut self, interval: Duration) -> Self {
+        self.timer = Timer::new(interval, TimerMode::Repeating);
+        self
+    }
+
+    /// The cell the region is currently centered on, once its subscription is applied
+    pub fn center_cell(&self) -> Option<[i64; 3]> {
+        self.center
+    }
+
+    /// Whether the subscription for the current region has been applied
+    pub fn is_active(&self) -> bool {
+        self.subscription
+            .current()
+            .is_some_and(|handle| handle.is_active())
+    }
+
+    fn cell_of(&self, position: [f64; 3]) -> [i64; 3] {
+        position.map(|value| (value / self.cell_size).floor() as i64)
+    }
+
+    /// Get the cell to re-center on, if the focus has left the current one
+    fn next_center(&self, position: [f64; 3]) -> Option<[i64; 3]> {
+        let Some(center) = self.center else {
+            return Some(self.cell_of(position));
+        };
+
+        let margin = self.hysteresis.unwrap_or(self.cell_size / 4.0);
+        let outside = (0..3).any(|axis| {
+            self.axes[axis].is_some() && {
+                let min = center[axis] as f64 * self.cell_size - margin;
+                let max = (center[axis] + 1) as f64 * self.cell_size + margin;
+                position[axis] < min || position[axis] > max
+            }
+        });
+        outside.then(|| self.cell_of(position))
+    }
+
+    /// Build the query for the region around `center`
+    fn region_query(&self, center: [i64; 3]) -> StdbQuery<T> {
+        let mut query = match &self.filter {
+            Some(filter) => StdbQuery::filter(filter.clone()),
+            None => StdbQuery::all(),
+        };
+
+        for (axis, bounds) in self.axes.iter().enumerate() {
+            if let Some(bounds) = bounds {
+                let middle = (center[axis] as f64 + 0.5) * self.cell_size;
+                let min = ((middle - self.radius) / self.cell_size).floor() * self.cell_size;
+                let max = ((middle + self.radius) / self.cell_size).ceil() * self.cell_size;
+                query = query.and_filter(bounds(min, max));
+            }
+        }
+        query
+    }
+}
+
+/// Allows keeping subscriptions around focus entities
+pub trait SpatialInterestAppExtensions {
+    /// Enable [`SpatialInterest<T>`] components for table `T`
+    fn add_spatial_interest<T: TableRow>(&mut self) -> &mut Self;
+}
+
+impl SpatialInterestAppExtensions for App {
+    fn add_spatial_interest<T: TableRow>(&mut self) -> &mut Self {
+        self.add_message::<SubscriptionReplacedEvent>()
+            .add_systems(Update, update_spatial_interest::<T>)
+    }
+}
+
+fn update_spatial_interest<T: TableRow>(
+    time: Res<Time>,
+    stdb: Option<Res<StdbConnection>>,
+    mut focuses: Query<(&GlobalTransform, &mut SpatialInterest<T>)>,
+    mut replaced: MessageWriter<SubscriptionReplacedEvent>,
+) {
+    let Some(stdb) = stdb else {
+        return;
+    };
+
+    for (transform, mut interest) in &mut focuses {
+        let interest = &mut *interest;
+        if let Some(replacement) = interest.subscription.update() {
+            interest.center = interest.pending_center.take();
+            replaced.write(replacement.event());
+        } else if !interest.subscription.is_pending() {
+            // Rejected, or lost with the connection: try again on the next check
+            interest.pending_center = None;
+        }
+        if interest
+            .subscription
+            .current()
+            .is_some_and(SubscriptionHandle::is_ended)
+        {
+            // The region is no longer covered, e.g. after a disconnection
+            interest.center = None;
+            interest.check_now = true;
+        }
+
+        let due = interest.timer.tick(time.delta()).just_finished();
+        if !stdb.is_connected() || !(due || interest.check_now) {
+            continue;
+        }
+
+        let position = transform.translation().as_dvec3().to_array();
+        if let Some(center) = interest.next_center(position) {
+            if interest.center == Some(center) || interest.pending_center == Some(center) {
+                continue;
+            }
+            let query = interest.region_query(center);
+            let handle = stdb.subscription_builder().subscribe(query);
+            interest.pending_center = Some(center);
+            interest.check_now = false;
+            interest.subscription.replace(handle);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[allow(dead_code)]
+    #[derive(Debug, Clone, Deserialize, crate::TableRow)]
+    struct StarSystem {
+        #[stdb(primary_key)]
+        id: u32,
+        x: f64,
+        y: f64,
+        z: f64,
+    }
+
+    fn interest() -> SpatialInterest<StarSystem> {
+        SpatialInterest::new()
+            .x(crate::col!(StarSystem::x))
+            .y(crate::col!(StarSystem::y))
+            .with_cell_size(100.0)
+            .with_radius(120.0)
+            .with_hysteresis(10.0)
+    }
+
+    #[test]
+    fn test_region_is_snapped_to_cells() {
+        let interest = interest();
+        assert_eq!(
+            interest.region_query([0, -1, 0]).to_sql(),
+            "SELECT * FROM star_system WHERE \"x\" >= -100 AND \"x\" <= 200 \
+             AND \"y\" >= -200 AND \"y\" <= 100"
+        );
+    }
+
+    #[test]
+    fn test_hysteresis_prevents_thrashing() {
+        let mut interest = interest();
+        assert_eq!(interest.next_center([50.0, 50.0, 0.0]), Some([0, 0, 0]));
+        interest.center = Some([0, 0, 0]);
+
+        // Just across the boundary: inside the margin
+        assert_eq!(interest.next_center([105.0, 50.0, 0.0]), None);
+        // Z has no column, so it never triggers a move
+        assert_eq!(interest.next_center([50.0, 50.0, 5000.0]), None);
+        // Beyond the margin
+        assert_eq!(interest.next_center([115.0, 50.0, 0.0]), Some([1, 0, 0]));
+    }
+
+    #[test]
+    fn test_moving_focus_replaces_the_subscription() {
+        use crate::{StdbPlugin, StdbTestApp};
+        use bevy::time::TimeUpdateStrategy;
+
+        let mut test = StdbTestApp::new(StdbPlugin::default());
+        test.add_spatial_interest::<StarSystem>().insert_resource(
+            TimeUpdateStrategy::ManualDuration(Duration::from_millis(100)),
+        );
+        test.server().connect(None);
+        let focus = test
+            .world_mut()
+            .spawn((
+                GlobalTransform::from_translation(Vec3::new(50.0, 50.0, 0.0)),
+                interest().with_update_interval(Duration::from_millis(50)),
+            ))
+            .id();
+
+        test.update();
+        test.server().apply_subscriptions();
+        test.update();
+        let first = test.assert_message::<SubscriptionReplacedEvent>(|_| true);
+        assert_eq!(first.previous, None);
+
+        test.world_mut()
+            .entity_mut(focus)
+            .insert(GlobalTransform::from_translation(Vec3::new(
+                250.0, 50.0, 0.0,
+            )));
+        test.update();
+        test.server().apply_subscriptions();
+        test.update();
+
+        let replaced = test.assert_message::<SubscriptionReplacedEvent>(|_| true);
+        assert_eq!(replaced.previous, Some(first.current));
+        let subscriptions = test.server().subscriptions();
+        assert_eq!(subscriptions.len(), 1);
+        assert_eq!(subscriptions[0].id, replaced.current.0);
+        assert!(subscriptions[0].queries[0].contains("\"x\" >= 100"));
+    }
+
+    #[test]
+    fn test_region_is_covered_once_connected_and_applied() {
+        use crate::{StdbPlugin, StdbTestApp};
+        use bevy::time::TimeUpdateStrategy;
+
+        let mut test = StdbTestApp::new(StdbPlugin::default());
+        test.add_spatial_interest::<StarSystem>().insert_resource(
+            TimeUpdateStrategy::ManualDuration(Duration::from_millis(100)),
+        );
+        let focus = test
+            .world_mut()
+            .spawn((
+                GlobalTransform::from_translation(Vec3::new(50.0, 50.0, 0.0)),
+                interest().with_update_interval(Duration::from_secs(60)),
+            ))
+            .id();
+        let center = |test: &mut StdbTestApp| {
+            test.world()
+                .get::<SpatialInterest<StarSystem>>(focus)
+                .unwrap()
+                .center_cell()
+        };
+
+        // Nothing is sent before the connection is up
+        test.run_frames(2);
+        assert!(test.server().subscriptions().is_empty());
+        test.assert_no_message::<crate::SubscriptionErrorEvent>();
+
+        test.server().connect(None);
+        test.update();
+        let subscriptions = test.server().subscriptions();
+        assert_eq!(subscriptions.len(), 1);
+        assert_eq!(center(&mut test), None);
+
+        // A rejected region is not taken as covered, and is asked for again
+        test.server()
+            .fail_subscription(crate::SubscriptionId(subscriptions[0].id), "too many rows");
+        test.update();
+        assert_eq!(center(&mut test), None);
+        test.world_mut()
+            .get_mut::<SpatialInterest<StarSystem>>(focus)
+            .unwrap()
+            .timer
+            .set_elapsed(Duration::from_secs(60));
+        test.update();
+        assert_eq!(test.server().subscriptions().len(), 1);
+
+        test.server().apply_subscriptions();
+        test.update();
+        assert_eq!(center(&mut test), Some([0, 0, 0]));
+
+        // Lost with the connection, and covered again after reconnecting
+        test.server().disconnect();
+        test.update();
+        assert_eq!(center(&mut test), None);
+        test.server().connect(None);
+        test.update();
+        test.server().apply_subscriptions();
+        test.update();
+        assert_eq!(center(&mut test), Some([0, 0, 0]));
+    }
+}
@@ -1,36 +1,76 @@
-//! WASM-only SpacetimeDB integration for Bevy using TypeScript SDK bridge.
+//! SpacetimeDB integration for Bevy on WASM using the TypeScript SDK bridge.
 //!
 //! This crate provides a Bevy plugin that connects to SpacetimeDB via the TypeScript SDK
-//! in the browser environment. It maintains API compatibility with `bevy_spacetimedb` but
-//! only works on WASM targets (wasm32-unknown-unknown).
+//! in the browser environment. It maintains API compatibility with `bevy_spacetimedb`.
 //!
 //! # Requirements
 //!
-//! - Must be compiled for WASM target: `wasm32-unknown-unknown`
-//! - Requires SpacetimeDB TypeScript SDK loaded in the browser
-//! - Requires the JavaScript bridge (`js/spacetimedb-bridge.js`) loaded before WASM
-
-#![cfg_attr(not(target_arch = "wasm32"), allow(unused))]
+//! - Connecting to a server requires the WASM target: `wasm32-unknown-unknown`
+//! - Requires `@clockworklabs/spacetimedb-sdk` to be resolvable by the page, through
+//!   an import map or a bundler
+//!
+//! The JavaScript bridge (`js/spacetimedb-bridge.js`) ships with the crate. Enable the
+//! `global-bridge` feature to use a bridge the page puts in `__SPACETIMEDB_BRIDGE__`.
+//!
+//! On native targets the JavaScript bridge is not available and the plugin runs on a
+//! [`MockBackend`], so game systems can be tested with a plain `cargo test`.
 
-#[cfg(not(target_arch = "wasm32"))]
-compile_error!(
-    "bevy_spacetimedb_wasm only supports WASM targets (wasm32-unknown-unknown). \
-    For native builds, use the original bevy_spacetimedb crate at \
-    https://github.com/JulienLavocat/bevy_spacetimedb"
-);
+extern crate self as bevy_spacetimedb_wasm;
 
+mod aliases;
+mod backend;
+#[cfg(target_arch = "wasm32")]
 mod bridge;
+pub mod bytes;
 mod channel_receiver;
+#[cfg(target_arch = "wasm32")]
+mod codec;
+mod entities;
 mod events;
+mod interest;
+mod one_off;
 mod plugin;
+mod query;
+mod reactive;
 mod reducers;
+mod recording;
+mod states;
 mod stdb_connection;
+mod subscriptions;
 mod tables;
+mod testing;
+mod types;
 
+pub use aliases::*;
+#[cfg(target_arch = "wasm32")]
+pub use backend::JsBackend;
+pub use backend::{
+    BackendCallback, BackendValue, CodecError, MockBackend, MockReducerCall, MockSubscription,
+    NetworkConditioner, NetworkConditions, RecordingBackend, ResultCallback, StdbBackend,
+    TableCallbacks, ValueEncoding, SIMULATED_DISCONNECT,
+};
+pub use bevy_spacetimedb_macros::{sum_type, RegisterReducerEvent, TableRow};
+#[cfg(target_arch = "wasm32")]
 pub use bridge::get_bridge;
+pub use bytes::Bytes;
 pub use channel_receiver::AddEventChannelAppExtensions;
+pub use entities::*;
 pub use events::*;
+pub use interest::*;
+pub use one_off::*;
 pub use plugin::*;
+pub use query::*;
+pub use reactive::*;
 pub use reducers::*;
+pub use recording::*;
+pub use states::*;
 pub use stdb_connection::*;
+pub use subscriptions::*;
 pub use tables::*;
+pub use testing::*;
+pub use types::*;
+
+#[doc(hidden)]
+pub mod __private {
+    pub use serde;
+}
@@ -0,0 +1,248 @@
+//! One-off SQL queries returning typed rows
+
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use bevy::prelude::*;
+
+use crate::{
+    backend::BackendValue, AddEventChannelAppExtensions, StdbConnection, TableRow,
+};
+
+/// Identifies a one-off query in its result event
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct OneOffQueryId(u32);
+
+/// Why a one-off query failed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OneOffQueryError {
+    /// The server rejected the query
+    Rejected(String),
+    /// A returned row could not be deserialized into the row type
+    Decode {
+        /// Position of the row in the result
+        index: usize,
+        /// The deserialization error
+        error: String,
+    },
+}
+
+impl fmt::Display for OneOffQueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OneOffQueryError::Rejected(error) => write!(f, "query rejected: {}", error),
+            OneOffQueryError::Decode { index, error } => {
+                write!(f, "failed to deserialize row {}: {}", index, error)
+            }
+        }
+    }
+}
+
+impl std::error::Error for OneOffQueryError {}
+
+/// An event that is triggered when a one-off query of table `T` completes.
+#[derive(Message, Debug, Clone)]
+pub struct OneOffQueryResultEvent<T> {
+    /// The id returned by `StdbConnection::one_off_query`.
+    pub id: OneOffQueryId,
+    /// The SQL query that was run.
+    pub query: String,
+    /// The matching rows, or why the query failed.
+    pub result: Result<Vec<T>, OneOffQueryError>,
+}
+
+/// Senders of the result events of every registered table, keyed by row type
+#[derive(Clone, Default)]
+pub(crate) struct OneOffQueryRegistry {
+    senders: Arc<Mutex<HashMap<TypeId, Box<dyn Any + Send>>>>,
+    next_id: Arc<AtomicU32>,
+}
+
+impl OneOffQueryRegistry {
+    /// Setup the result event channel of table `T`
+    pub(crate) fn register<T: TableRow>(&self, app: &mut App) {
+        let mut senders = self.senders.lock().expect("one-off query registry poisoned");
+        if senders.contains_key(&TypeId::of::<T>()) {
+            return;
+        }
+
+        let (send, recv) = std::sync::mpsc::channel::<OneOffQueryResultEvent<T>>();
+        app.add_event_channel(recv);
+        senders.insert(TypeId::of::<T>(), Box::new(send));
+    }
+
+    fn sender<T: TableRow>(&self) -> Option<Sender<OneOffQueryResultEvent<T>>> {
+        let senders = self.senders.lock().expect("one-off query registry poisoned");
+        senders
+            .get(&TypeId::of::<T>())
+            .and_then(|sender| sender.downcast_ref::<Sender<OneOffQueryResultEvent<T>>>())
+            .cloned()
+    }
+
+    fn next_id(&self) -> OneOffQueryId {
+        OneOffQueryId(self.next_id.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// Decode the rows resolved by the backend
+fn decode_rows<T: TableRow>(
+    result: Result<BackendValue, String>,
+) -> Result<Vec<T>, OneOffQueryError> {
+    result
+        .map_err(OneOffQueryError::Rejected)?
+        .elements()
+        .iter()
+        .enumerate()
+        .map(|(index, row)| {
+            row.decode().map_err(|e| OneOffQueryError::Decode {
+                index,
+                error: e.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// The result of a one-off query, filled in by the backend callback
+struct PendingResult<T> {
+    result: Option<T>,
+    waker: Option<Waker>,
+}
+
+/// Future resolving once the backend has reported the result of a one-off query
+struct OneOffQueryFuture<T> {
+    pending: Arc<Mutex<PendingResult<T>>>,
+}
+
+impl<T> Future for OneOffQueryFuture<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let mut pending = self.pending.lock().expect("one-off query result poisoned");
+        match pending.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                pending.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl StdbConnection {
+    /// Run a SQL query once, without subscribing
+    ///
+    /// The rows are delivered as a [`OneOffQueryResultEvent<T>`] with the returned
+    /// id. `T` must be registered with `StdbPlugin::add_table` (or a variant).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `T` is not registered with the plugin.
+    ///
+    /// # Example
+    /// ```ignore
+    /// fn load_leaderboard(stdb: Res<StdbConnection>) {
+    ///     stdb.one_off_query::<Score>("SELECT * FROM score WHERE points > 1000");
+    /// }
+    ///
+    /// fn show_leaderboard(mut results: MessageReader<OneOffQueryResultEvent<Score>>) {
+    ///     for event in results.read() {
+    ///         match &event.result {
+    ///             Ok(scores) => info!("{} scores", scores.len()),
+    ///             Err(e) => error!("Leaderboard query failed: {}", e),
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub fn one_off_query<T: TableRow>(&self, query: impl Into<String>) -> OneOffQueryId {
+        let sender = self.one_off_queries.sender::<T>().unwrap_or_else(|| {
+            panic!(
+                "one_off_query::<{}>() requires the table to be registered with StdbPlugin::add_table",
+                std::any::type_name::<T>()
+            )
+        });
+
+        let id = self.one_off_queries.next_id();
+        let query = query.into();
+        let sql = query.clone();
+        self.backend.one_off_query(
+            &sql,
+            Box::new(move |result| {
+                let result = decode_rows(result);
+                let _ = sender.send(OneOffQueryResultEvent { id, query, result });
+            }),
+        );
+        id
+    }
+
+    /// Run a SQL query once, without subscribing, and await the rows
+    ///
+    /// Unlike [`StdbConnection::one_off_query`], `T` does not need to be registered.
+    pub fn one_off_query_async<T: TableRow>(
+        &self,
+        query: impl Into<String>,
+    ) -> impl Future<Output = Result<Vec<T>, OneOffQueryError>> + 'static {
+        let pending = Arc::new(Mutex::new(PendingResult {
+            result: None,
+            waker: None,
+        }));
+        let callback_pending = pending.clone();
+        self.backend.one_off_query(
+            &query.into(),
+            Box::new(move |result| {
+                let mut pending = callback_pending
+                    .lock()
+                    .expect("one-off query result poisoned");
+                pending.result = Some(decode_rows(result));
+                if let Some(waker) = pending.waker.take() {
+                    waker.wake();
+                }
+            }),
+        );
+        OneOffQueryFuture { pending }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, serde::Deserialize, crate::TableRow)]
+    struct Score {
+        points: u32,
+    }
+
+    #[test]
+    fn test_registry_is_keyed_by_row_type() {
+        let mut app = App::new();
+        let registry = OneOffQueryRegistry::default();
+        assert!(registry.sender::<Score>().is_none());
+
+        registry.register::<Score>(&mut app);
+        // Registering again is a no-op
+        registry.register::<Score>(&mut app);
+
+        let sender = registry.sender::<Score>().expect("registered");
+        sender
+            .send(OneOffQueryResultEvent {
+                id: registry.next_id(),
+                query: "SELECT * FROM score".to_string(),
+                result: Ok(vec![Score { points: 3 }]),
+            })
+            .unwrap();
+        app.update();
+
+        let events = app
+            .world()
+            .resource::<Messages<OneOffQueryResultEvent<Score>>>();
+        let event = events.iter_current_update_messages().next().unwrap();
+        assert_eq!(event.id, OneOffQueryId(0));
+        assert_eq!(event.result.as_ref().unwrap()[0].points, 3);
+    }
+}
@@ -1,10 +1,23 @@
+#[cfg(target_arch = "wasm32")]
+use crate::backend::JsBackend;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::backend::MockBackend;
 use crate::{
-    bridge::get_bridge, AddEventChannelAppExtensions, StdbConnectedEvent,
-    StdbConnectionErrorEvent, StdbDisconnectedEvent, StdbConnection,
-    tables::TableConfig,
+    backend::{
+        setup_network_conditioner, NetworkConditioner, NetworkConditions, RecordingBackend,
+        StdbBackend,
+    },
+    recording::end_recorder_frame,
+    reducers::ReducerSetupFn,
+    subscriptions::setup_subscription_events,
+    tables::{DecodeErrorReporter, TableConfig, TableSetupContext},
+    AddEventChannelAppExtensions, SessionRecorder, StdbConnectedEvent, StdbConnection,
+    StdbConnectionErrorEvent, StdbDecodeErrorEvent, StdbDisconnectedEvent, StdbReducerErrorEvent,
 };
-use bevy::app::{App, Plugin};
-use wasm_bindgen::prelude::*;
+use bevy::app::{App, Last, Plugin};
+#[cfg(not(target_arch = "wasm32"))]
+use bevy::log::warn;
+use std::sync::{atomic::Ordering, Arc};
 
 /// The main plugin for connecting SpacetimeDB to your Bevy application
 ///
@@ -38,6 +51,7 @@ use wasm_bindgen::prelude::*;
 ///     }
 /// }
 /// ```
+#[derive(Default)]
 pub struct StdbPlugin {
     /// The WebSocket URI of the SpacetimeDB server
     uri: Option<String>,
@@ -45,19 +59,18 @@ pub struct StdbPlugin {
     module_name: Option<String>,
     /// Optional authentication token
     auth_token: Option<String>,
+    /// Whether to disconnect when a row fails to deserialize
+    strict_decoding: bool,
+    /// The backend to use instead of the JavaScript bridge
+    backend: Option<Arc<dyn StdbBackend>>,
+    /// The network conditions to simulate in front of the backend
+    network_conditions: Option<NetworkConditions>,
+    /// Where to record the traffic of the backend
+    recorder: Option<SessionRecorder>,
     /// Table configurations
     pub(crate) table_configs: Vec<TableConfig>,
-}
-
-impl Default for StdbPlugin {
-    fn default() -> Self {
-        Self {
-            uri: None,
-            module_name: None,
-            auth_token: None,
-            table_configs: Vec::new(),
-        }
-    }
+    /// Setup of the reducers registered with `add_reducer`
+    pub(crate) reducer_configs: Vec<ReducerSetupFn>,
 }
 
 impl StdbPlugin {
@@ -104,10 +117,84 @@ impl StdbPlugin {
         self.auth_token = Some(token.into());
         self
     }
+
+    /// Disconnect from the server when a row fails to deserialize
+    ///
+    /// A `StdbDecodeErrorEvent` is sent for every row that fails to deserialize,
+    /// whether or not strict decoding is enabled. With strict decoding the first
+    /// failure also closes the connection, so schema drift can't go unnoticed.
+    ///
+    /// # Example
+    /// ```ignore
+    /// StdbPlugin::default()
+    ///     .with_strict_decoding(true)
+    /// ```
+    pub fn with_strict_decoding(mut self, strict: bool) -> Self {
+        self.strict_decoding = strict;
+        self
+    }
+
+    /// Connect through `backend` instead of the JavaScript bridge
+    ///
+    /// The URI, module name and token are not used. This is mostly useful with a
+    /// [`MockBackend`](crate::MockBackend) to test systems without a server.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let backend = MockBackend::new();
+    /// app.add_plugins(
+    ///     StdbPlugin::default()
+    ///         .with_backend(backend.clone())
+    ///         .add_table::<Player>(),
+    /// );
+    /// ```
+    pub fn with_backend(mut self, backend: impl StdbBackend) -> Self {
+        self.backend = Some(Arc::new(backend));
+        self
+    }
+
+    /// Simulate a bad network between the app and the backend
+    ///
+    /// Works with the JavaScript bridge as well as a [`MockBackend`](crate::MockBackend).
+    /// The [`NetworkConditioner`] is inserted as a resource, to change the
+    /// conditions or force a disconnect while the app runs.
+    ///
+    /// # Example
+    /// ```ignore
+    /// StdbPlugin::default()
+    ///     .with_network_conditions(
+    ///         NetworkConditions::default()
+    ///             .with_latency(Duration::from_millis(150))
+    ///             .with_jitter(Duration::from_millis(50))
+    ///             .with_reordering(1),
+    ///     )
+    /// ```
+    pub fn with_network_conditions(mut self, conditions: NetworkConditions) -> Self {
+        self.network_conditions = Some(conditions);
+        self
+    }
+
+    /// Record everything crossing the backend into `recorder`
+    ///
+    /// The recorder is also inserted as a resource. Save its
+    /// [`recording`](SessionRecorder::recording) to replay the session later
+    /// with a [`Replay`](crate::Replay).
+    ///
+    /// # Example
+    /// ```ignore
+    /// let recorder = SessionRecorder::new();
+    /// app.add_plugins(StdbPlugin::default().with_recorder(recorder.clone()));
+    /// ```
+    pub fn with_recorder(mut self, recorder: SessionRecorder) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
 }
 
-impl Plugin for StdbPlugin {
-    fn build(&self, app: &mut App) {
+impl StdbPlugin {
+    /// Create a connection through the JavaScript bridge
+    #[cfg(target_arch = "wasm32")]
+    fn default_backend(&self) -> Arc<dyn StdbBackend> {
         // Validate configuration
         let uri = self
             .uri
@@ -118,19 +205,42 @@ impl Plugin for StdbPlugin {
             .as_ref()
             .expect("No module name set for StdbPlugin. Set it with .with_module_name()");
 
-        // Get the JavaScript bridge
-        let bridge = get_bridge();
-
-        // Create the connection
-        let connection_id = bridge.create_connection(uri, module_name, self.auth_token.clone());
+        Arc::new(JsBackend::new(uri, module_name, self.auth_token.clone()))
+    }
 
-        web_sys::console::log_1(
-            &format!(
-                "Created SpacetimeDB connection {} to {}/{}",
-                connection_id, uri, module_name
-            )
-            .into(),
+    /// The JavaScript bridge is not available: use a mock that never connects
+    #[cfg(not(target_arch = "wasm32"))]
+    fn default_backend(&self) -> Arc<dyn StdbBackend> {
+        warn!(
+            "The SpacetimeDB bridge is only available on WASM, using a MockBackend. \
+            Use StdbPlugin::with_backend to drive it in tests."
         );
+        Arc::new(MockBackend::new())
+    }
+}
+
+impl Plugin for StdbPlugin {
+    fn build(&self, app: &mut App) {
+        let backend = match &self.backend {
+            Some(backend) => backend.clone(),
+            None => self.default_backend(),
+        };
+        let backend: Arc<dyn StdbBackend> = match &self.network_conditions {
+            Some(conditions) => {
+                let conditioner = NetworkConditioner::wrap(backend, conditions.clone());
+                setup_network_conditioner(app, conditioner.clone());
+                Arc::new(conditioner)
+            }
+            None => backend,
+        };
+        let backend: Arc<dyn StdbBackend> = match &self.recorder {
+            Some(recorder) => {
+                app.insert_resource(recorder.clone())
+                    .add_systems(Last, end_recorder_frame);
+                Arc::new(RecordingBackend::wrap(backend, recorder.clone()))
+            }
+            None => backend,
+        };
 
         // Setup connection lifecycle event channels
         let (connected_send, connected_recv) = std::sync::mpsc::channel::<StdbConnectedEvent>();
@@ -143,65 +253,65 @@ impl Plugin for StdbPlugin {
             .add_event_channel(error_recv);
 
         // Register connection lifecycle callbacks
-        let connected_cb = Closure::wrap(Box::new(move || {
-            let _ = connected_send.send(StdbConnectedEvent {});
-        }) as Box<dyn Fn()>);
-
-        let disconnected_cb = Closure::wrap(Box::new(move |err: JsValue| {
-            let err_msg = err.as_string();
+        backend.on_disconnect(Box::new(move |err| {
+            let err_msg = err.decode().ok().flatten();
             let _ = disconnected_send.send(StdbDisconnectedEvent { err: err_msg });
-        }) as Box<dyn Fn(JsValue)>);
+        }));
 
-        let error_cb = Closure::wrap(Box::new(move |err: JsValue| {
-            let err_msg = err.as_string().unwrap_or_else(|| "Unknown error".to_string());
+        backend.on_connection_error(Box::new(move |err| {
+            let err_msg = err.decode().unwrap_or_else(|_| "Unknown error".to_string());
             let _ = error_send.send(StdbConnectionErrorEvent { err: err_msg });
-        }) as Box<dyn Fn(JsValue)>);
-
-        // Register the callbacks with the bridge
-        let connected_id = bridge.register_callback(connected_cb.as_ref().unchecked_ref());
-        let disconnected_id = bridge.register_callback(disconnected_cb.as_ref().unchecked_ref());
-        let error_id = bridge.register_callback(error_cb.as_ref().unchecked_ref());
+        }));
 
-        bridge.on_connect(connection_id, connected_id);
-        bridge.on_disconnect(connection_id, disconnected_id);
-        bridge.on_connection_error(connection_id, error_id);
+        // Create the connection resource
+        let (reducer_error_send, reducer_error_recv) =
+            std::sync::mpsc::channel::<StdbReducerErrorEvent>();
+        app.add_event_channel(reducer_error_recv);
 
-        // Keep the closures alive for the lifetime of the application.
-        // This is intentional - these callbacks are registered with the JS SDK and must live
-        // as long as the connection exists. Storing them in a Resource would cause use-after-free
-        // when Bevy drops the resource but JS still holds references to the callbacks.
-        connected_cb.forget();
-        disconnected_cb.forget();
-        error_cb.forget();
+        let connection = StdbConnection::new(backend.clone(), reducer_error_send);
+        let connected = connection.connected.clone();
+        let subscriptions = connection.subscriptions.clone();
+        backend.on_disconnect(Box::new(move |_| {
+            connected.store(false, Ordering::Relaxed);
+            // Subscriptions do not survive the connection
+            subscriptions.end_all();
+        }));
+        setup_subscription_events(app, &connection);
 
         // Setup table subscriptions
+        let (decode_error_send, decode_error_recv) =
+            std::sync::mpsc::channel::<StdbDecodeErrorEvent>();
+        app.add_event_channel(decode_error_recv);
+
+        let context = TableSetupContext {
+            connection: connection.clone(),
+            decode_errors: DecodeErrorReporter::new(
+                decode_error_send,
+                &connection,
+                self.strict_decoding,
+            ),
+        };
         for table_config in &self.table_configs {
-            (table_config.setup_fn)(&bridge, connection_id, &table_config.events, app);
+            (table_config.setup_fn)(&context, &table_config.events, app);
+        }
+
+        let decode_errors = context.decode_errors;
+        let connected = connection.connected.clone();
+        backend.on_connect(Box::new(move |identity| {
+            connected.store(true, Ordering::Relaxed);
+            // Strict decoding disconnects again if this connection fails later
+            decode_errors.reset();
+            let identity = identity.decode().ok().flatten();
+            let _ = connected_send.send(StdbConnectedEvent { identity });
+        }));
+
+        for setup_fn in &self.reducer_configs {
+            setup_fn(backend.as_ref(), app);
         }
 
-        // Create and insert the connection resource
-        let connection = StdbConnection::new(bridge.clone(), connection_id);
         app.insert_resource(connection);
 
         // Connect to the server asynchronously
-        wasm_bindgen_futures::spawn_local(async move {
-            web_sys::console::log_1(
-                &format!("Connecting to SpacetimeDB connection {}...", connection_id).into(),
-            );
-
-            match wasm_bindgen_futures::JsFuture::from(bridge.connect(connection_id)).await {
-                Ok(_) => {
-                    web_sys::console::log_1(
-                        &format!("Successfully connected to SpacetimeDB (connection {})", connection_id)
-                            .into(),
-                    );
-                }
-                Err(e) => {
-                    web_sys::console::error_1(
-                        &format!("Failed to connect to SpacetimeDB: {:?}", e).into(),
-                    );
-                }
-            }
-        });
+        backend.connect();
     }
 }
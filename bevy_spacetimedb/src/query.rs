@@ -0,0 +1,423 @@
+//! Typed query builder for subscription SQL
+//!
+//! Queries are built from the metadata derived by `#[derive(TableRow)]`, so a
+//! renamed or misspelled column is a compile error instead of a subscription
+//! that silently fails at runtime.
+//!
+//! # Example
+//! ```ignore
+//! let query = StdbQuery::<Player>::filter(
+//!     col!(Player::lobby_id).eq(lobby).and(col!(Player::online).eq(true)),
+//! );
+//! assert_eq!(
+//!     query.to_sql(),
+//!     r#"SELECT * FROM players WHERE "lobby_id" = 7 AND "online" = true"#
+//! );
+//! stdb.subscription_builder().subscribe(query);
+//! ```
+
+use std::fmt;
+use std::marker::PhantomData;
+
+use crate::{ConnectionId, Identity, IntoQueries, TableRow};
+
+/// Table types whose columns can be referenced with [`col!`](crate::col)
+///
+/// Derived by `#[derive(TableRow)]`. `Columns` has one method per column
+/// returning its typed [`Column`].
+pub trait HasColumns: TableRow {
+    /// Accessors for the columns of the table
+    type Columns;
+
+    /// Instance of the column accessors
+    const COLUMNS: Self::Columns;
+}
+
+/// Reference a column of a table in a typed query
+///
+/// Fails to compile if the table has no such column.
+///
+/// # Example
+/// ```ignore
+/// col!(Player::lobby_id).eq(7)
+/// col!(my_module::Player, lobby_id).eq(7)
+/// ```
+#[macro_export]
+macro_rules! col {
+    ($table:ident :: $column:ident) => {
+        <$table as $crate::HasColumns>::COLUMNS.$column()
+    };
+    ($table:ty, $column:ident) => {
+        <$table as $crate::HasColumns>::COLUMNS.$column()
+    };
+}
+
+/// Values that can be written as SQL literals
+pub trait SqlLiteral {
+    /// Write the value as a SQL literal, quoted and escaped as needed
+    ///
+    /// # Panics
+    ///
+    /// SQL has no literal for NaN or infinity, so non-finite floats panic.
+    fn to_sql_literal(&self) -> String;
+}
+
+macro_rules! impl_sql_literal_display {
+    ($($ty:ty),*) => {
+        $(
+            impl SqlLiteral for $ty {
+                fn to_sql_literal(&self) -> String {
+                    self.to_string()
+                }
+            }
+        )*
+    };
+}
+
+impl_sql_literal_display!(bool, u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+macro_rules! impl_sql_literal_float {
+    ($($ty:ty),*) => {
+        $(
+            impl SqlLiteral for $ty {
+                fn to_sql_literal(&self) -> String {
+                    assert!(
+                        self.is_finite(),
+                        "{} can't be written as a SQL literal",
+                        self
+                    );
+                    self.to_string()
+                }
+            }
+        )*
+    };
+}
+
+impl_sql_literal_float!(f32, f64);
+
+impl SqlLiteral for str {
+    fn to_sql_literal(&self) -> String {
+        format!("'{}'", self.replace('\'', "''"))
+    }
+}
+
+impl SqlLiteral for String {
+    fn to_sql_literal(&self) -> String {
+        self.as_str().to_sql_literal()
+    }
+}
+
+impl SqlLiteral for Identity {
+    fn to_sql_literal(&self) -> String {
+        format!("0x{}", self.to_hex())
+    }
+}
+
+impl SqlLiteral for ConnectionId {
+    fn to_sql_literal(&self) -> String {
+        format!("0x{}", self.to_hex())
+    }
+}
+
+/// Values that can be compared with a column of type `V`
+///
+/// Implemented for `V` itself and for `&str` on `String` columns.
+pub trait ColumnValue<V> {
+    /// Write the value as a SQL literal for a column of type `V`
+    fn to_column_literal(&self) -> String;
+}
+
+impl<V: SqlLiteral> ColumnValue<V> for V {
+    fn to_column_literal(&self) -> String {
+        self.to_sql_literal()
+    }
+}
+
+impl ColumnValue<String> for &str {
+    fn to_column_literal(&self) -> String {
+        self.to_sql_literal()
+    }
+}
+
+/// A column of table `T` holding values of type `V`
+///
+/// Obtained with [`col!`](crate::col).
+pub struct Column<T, V> {
+    name: &'static str,
+    _marker: PhantomData<fn() -> (T, V)>,
+}
+
+impl<T, V> Clone for Column<T, V> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T, V> Copy for Column<T, V> {}
+
+impl<T, V> fmt::Debug for Column<T, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Column").field(&self.name).finish()
+    }
+}
+
+impl<T, V> Column<T, V> {
+    #[doc(hidden)]
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            _marker: PhantomData,
+        }
+    }
+
+    /// The name of the column
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn compare(self, op: &str, value: impl ColumnValue<V>) -> Condition<T> {
+        Condition::new(
+            format!(
+                "{} {} {}",
+                quote_identifier(self.name),
+                op,
+                value.to_column_literal()
+            ),
+            Precedence::Comparison,
+        )
+    }
+
+    /// The column equals `value`
+    pub fn eq(self, value: impl ColumnValue<V>) -> Condition<T> {
+        self.compare("=", value)
+    }
+
+    /// The column does not equal `value`
+    pub fn ne(self, value: impl ColumnValue<V>) -> Condition<T> {
+        self.compare("<>", value)
+    }
+
+    /// The column is less than `value`
+    pub fn lt(self, value: impl ColumnValue<V>) -> Condition<T> {
+        self.compare("<", value)
+    }
+
+    /// The column is less than or equal to `value`
+    pub fn le(self, value: impl ColumnValue<V>) -> Condition<T> {
+        self.compare("<=", value)
+    }
+
+    /// The column is greater than `value`
+    pub fn gt(self, value: impl ColumnValue<V>) -> Condition<T> {
+        self.compare(">", value)
+    }
+
+    /// The column is greater than or equal to `value`
+    pub fn ge(self, value: impl ColumnValue<V>) -> Condition<T> {
+        self.compare(">=", value)
+    }
+}
+
+/// Quote a column name, so names like `type` aren't read as keywords
+fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// Binding strength of the outermost operator of a condition
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Precedence {
+    Or,
+    And,
+    Comparison,
+}
+
+/// A `WHERE` condition on rows of table `T`
+pub struct Condition<T> {
+    sql: String,
+    precedence: Precedence,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for Condition<T> {
+    fn clone(&self) -> Self {
+        Self::new(self.sql.clone(), self.precedence)
+    }
+}
+
+impl<T> fmt::Debug for Condition<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Condition").field(&self.sql).finish()
+    }
+}
+
+impl<T> Condition<T> {
+    fn new(sql: String, precedence: Precedence) -> Self {
+        Self {
+            sql,
+            precedence,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Render as an operand of an operator with the given precedence
+    fn operand(&self, precedence: Precedence) -> String {
+        if self.precedence < precedence {
+            format!("({})", self.sql)
+        } else {
+            self.sql.clone()
+        }
+    }
+
+    /// Both conditions hold
+    pub fn and(self, other: Condition<T>) -> Condition<T> {
+        Condition::new(
+            format!(
+                "{} AND {}",
+                self.operand(Precedence::And),
+                other.operand(Precedence::And)
+            ),
+            Precedence::And,
+        )
+    }
+
+    /// Either condition holds
+    pub fn or(self, other: Condition<T>) -> Condition<T> {
+        Condition::new(
+            format!(
+                "{} OR {}",
+                self.operand(Precedence::Or),
+                other.operand(Precedence::Or)
+            ),
+            Precedence::Or,
+        )
+    }
+}
+
+/// A subscription query selecting rows of table `T`
+///
+/// Can be passed directly to `SubscriptionBuilder::subscribe`.
+pub struct StdbQuery<T> {
+    condition: Option<Condition<T>>,
+}
+
+impl<T> Clone for StdbQuery<T> {
+    fn clone(&self) -> Self {
+        Self {
+            condition: self.condition.clone(),
+        }
+    }
+}
+
+impl<T> fmt::Debug for StdbQuery<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StdbQuery")
+            .field("condition", &self.condition)
+            .finish()
+    }
+}
+
+impl<T: TableRow> StdbQuery<T> {
+    /// Select all rows of the table
+    pub fn all() -> Self {
+        Self { condition: None }
+    }
+
+    /// Select the rows matching `condition`
+    pub fn filter(condition: Condition<T>) -> Self {
+        Self {
+            condition: Some(condition),
+        }
+    }
+
+    /// Narrow the query to rows that also match `condition`
+    pub fn and_filter(self, condition: Condition<T>) -> Self {
+        Self {
+            condition: Some(match self.condition {
+                Some(existing) => existing.and(condition),
+                None => condition,
+            }),
+        }
+    }
+
+    /// Render the query as SQL
+    pub fn to_sql(&self) -> String {
+        match &self.condition {
+            Some(condition) => format!("SELECT * FROM {} WHERE {}", T::TABLE_NAME, condition.sql),
+            None => format!("SELECT * FROM {}", T::TABLE_NAME),
+        }
+    }
+}
+
+impl<T: TableRow> fmt::Display for StdbQuery<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_sql())
+    }
+}
+
+impl<T: TableRow> From<StdbQuery<T>> for String {
+    fn from(query: StdbQuery<T>) -> Self {
+        query.to_sql()
+    }
+}
+
+impl<T: TableRow> IntoQueries for StdbQuery<T> {
+    fn into_queries(self) -> Vec<String> {
+        vec![self.to_sql()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[allow(dead_code)]
+    #[derive(Debug, Clone, Deserialize, crate::TableRow)]
+    #[stdb(table = "players")]
+    struct Player {
+        #[stdb(primary_key)]
+        id: u64,
+        name: String,
+        lobby_id: u64,
+        online: bool,
+        r#type: u8,
+    }
+
+    #[test]
+    fn test_query_sql() {
+        assert_eq!(StdbQuery::<Player>::all().to_sql(), "SELECT * FROM players");
+        assert_eq!(
+            StdbQuery::<Player>::filter(col!(Player::lobby_id).eq(7)).to_sql(),
+            "SELECT * FROM players WHERE \"lobby_id\" = 7"
+        );
+        assert_eq!(
+            StdbQuery::<Player>::filter(col!(Player, r#type).ge(2))
+                .and_filter(col!(Player::online).eq(true))
+                .to_sql(),
+            r#"SELECT * FROM players WHERE "type" >= 2 AND "online" = true"#
+        );
+    }
+
+    #[test]
+    fn test_conditions_are_parenthesized_and_escaped() {
+        let condition = col!(Player::name)
+            .eq("O'Brien")
+            .or(col!(Player::id).lt(3))
+            .and(col!(Player::online).ne(false));
+        assert_eq!(
+            StdbQuery::<Player>::filter(condition).to_sql(),
+            r#"SELECT * FROM players WHERE ("name" = 'O''Brien' OR "id" < 3) AND "online" <> false"#
+        );
+        assert_eq!(
+            StdbQuery::<Player>::all().into_queries(),
+            vec!["SELECT * FROM players"]
+        );
+    }
+
+    #[test]
+    fn test_floats_must_be_finite() {
+        assert_eq!(1.5f64.to_sql_literal(), "1.5");
+        assert!(std::panic::catch_unwind(|| f64::NAN.to_sql_literal()).is_err());
+        assert!(std::panic::catch_unwind(|| f32::INFINITY.to_sql_literal()).is_err());
+    }
+}
@@ -0,0 +1,395 @@
+//! Subscriptions re-issued when a resource changes
+
+use std::marker::PhantomData;
+
+use bevy::prelude::*;
+
+use crate::{
+    subscriptions::{ReplaceableSubscription, Replacement},
+    DeleteEvent, InsertEvent, IntoQueries, StdbConnection, SubscriptionDiffEvent,
+    SubscriptionHandle, SubscriptionReplacedEvent, TableRow,
+};
+
+/// The subscription computed from resource `R`
+///
+/// Added by [`ResourceSubscriptionAppExtensions::add_stdb_subscription_for_resource`].
+#[derive(Resource)]
+pub struct ResourceSubscription<R: Resource> {
+    subscription: ReplaceableSubscription,
+    stale: bool,
+    /// The replacement made this frame, for the diff systems
+    replaced: Option<Replacement>,
+    _marker: PhantomData<fn() -> R>,
+}
+
+impl<R: Resource> Default for ResourceSubscription<R> {
+    fn default() -> Self {
+        Self {
+            subscription: ReplaceableSubscription::default(),
+            stale: false,
+            replaced: None,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<R: Resource> ResourceSubscription<R> {
+    /// The subscription currently in effect
+    ///
+    /// While a replacement is pending this is still the previous subscription.
+    pub fn current(&self) -> Option<&SubscriptionHandle> {
+        self.subscription.current()
+    }
+}
+
+/// The systems keeping the resource subscriptions up to date
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+struct ResourceSubscriptionSystems;
+
+/// Allows declaring subscriptions that follow the value of a resource
+pub trait ResourceSubscriptionAppExtensions {
+    /// Keep a subscription computed from resource `R` by `query_fn`
+    ///
+    /// The subscription is made when `R` is inserted, or once connected, and
+    /// re-issued whenever it changes: the new subscription is made first and the
+    /// previous one is only ended once the new one has been applied, so rows
+    /// covered by both are never removed. A [`SubscriptionReplacedEvent`] is sent
+    /// after each replacement. It is made again if it fails or the connection is
+    /// lost. Removing `R` ends the subscription.
+    ///
+    /// Use [`add_stdb_subscription_diff`](Self::add_stdb_subscription_diff) to
+    /// receive the rows entering and leaving scope with each replacement.
+    ///
+    /// # Example
+    /// ```ignore
+    /// app.add_stdb_subscription_for_resource(|lobby: &CurrentLobby| {
+    ///     StdbQuery::<Player>::filter(col!(Player::lobby_id).eq(lobby.id))
+    /// });
+    /// ```
+    fn add_stdb_subscription_for_resource<R: Resource, Q: IntoQueries>(
+        &mut self,
+        query_fn: impl Fn(&R) -> Q + Send + Sync + 'static,
+    ) -> &mut Self;
+
+    /// Send a [`SubscriptionDiffEvent<T>`] for each replacement of the subscription of `R`
+    ///
+    /// The event lists the rows of table `T` that entered scope with the new
+    /// subscription and the rows that left it with the previous one, and is sent
+    /// once the previous subscription has ended. `T` must be added to the plugin.
+    ///
+    /// # Example
+    /// ```ignore
+    /// app.add_stdb_subscription_for_resource(|lobby: &CurrentLobby| { /* ... */ })
+    ///     .add_stdb_subscription_diff::<CurrentLobby, Player>();
+    ///
+    /// fn on_lobby_changed(mut diffs: MessageReader<SubscriptionDiffEvent<Player>>) {
+    ///     for diff in diffs.read() {
+    ///         info!("{} joined, {} left", diff.entering.len(), diff.leaving.len());
+    ///     }
+    /// }
+    /// ```
+    fn add_stdb_subscription_diff<R: Resource, T: TableRow>(&mut self) -> &mut Self;
+}
+
+impl ResourceSubscriptionAppExtensions for App {
+    fn add_stdb_subscription_for_resource<R: Resource, Q: IntoQueries>(
+        &mut self,
+        query_fn: impl Fn(&R) -> Q + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.add_message::<SubscriptionReplacedEvent>()
+            .init_resource::<ResourceSubscription<R>>()
+            .add_systems(
+                Update,
+                (move |resource: Option<Res<R>>,
+                       stdb: Option<Res<StdbConnection>>,
+                       mut state: ResMut<ResourceSubscription<R>>,
+                       mut replaced: MessageWriter<SubscriptionReplacedEvent>| {
+                    state.replaced = None;
+                    let Some(resource) = resource else {
+                        if !state.subscription.is_empty() {
+                            // Dropping the subscription ends it
+                            *state = ResourceSubscription::default();
+                        }
+                        return;
+                    };
+
+                    if resource.is_changed() {
+                        state.stale = true;
+                    }
+                    if state.stale {
+                        if let Some(stdb) = stdb.filter(|stdb| stdb.is_connected()) {
+                            let handle = stdb.subscription_builder().subscribe(query_fn(&resource));
+                            state.subscription.replace(handle);
+                            state.stale = false;
+                        }
+                    }
+
+                    let had_pending = state.subscription.is_pending();
+                    if let Some(replacement) = state.subscription.update() {
+                        replaced.write(replacement.event());
+                        state.replaced = Some(replacement);
+                    } else if had_pending && !state.subscription.is_pending() {
+                        // Rejected, or lost with the connection
+                        state.stale = true;
+                    }
+                    if state
+                        .subscription
+                        .current()
+                        .is_some_and(SubscriptionHandle::is_ended)
+                    {
+                        // Lost with the connection: made again once reconnected
+                        state.stale = true;
+                    }
+                })
+                .in_set(ResourceSubscriptionSystems),
+            )
+    }
+
+    fn add_stdb_subscription_diff<R: Resource, T: TableRow>(&mut self) -> &mut Self {
+        self.add_message::<SubscriptionDiffEvent<T>>().add_systems(
+            Update,
+            collect_subscription_diff::<R, T>.after(ResourceSubscriptionSystems),
+        )
+    }
+}
+
+/// The rows gathered for the replacement in progress
+struct DiffState<T> {
+    /// The replacement, once the new subscription has been applied
+    replacement: Option<Replacement>,
+    entering: Vec<T>,
+    leaving: Vec<T>,
+}
+
+impl<T> Default for DiffState<T> {
+    fn default() -> Self {
+        Self {
+            replacement: None,
+            entering: Vec::new(),
+            leaving: Vec::new(),
+        }
+    }
+}
+
+/// Gather the rows entering and leaving scope around each replacement
+///
+/// The SDK does not say which subscription a row came with: the initial rows
+/// received while the new subscription is pending count as entering, and the
+/// rows removed until the previous one has ended count as leaving.
+fn collect_subscription_diff<R: Resource, T: TableRow>(
+    state: Option<Res<ResourceSubscription<R>>>,
+    mut inserts: MessageReader<InsertEvent<T>>,
+    mut deletes: MessageReader<DeleteEvent<T>>,
+    mut diff: Local<DiffState<T>>,
+    mut diffs: MessageWriter<SubscriptionDiffEvent<T>>,
+) {
+    let Some(state) = state else {
+        return;
+    };
+
+    let replacing = state.subscription.is_pending() || state.replaced.is_some();
+    let entering = inserts
+        .read()
+        .filter(|event| replacing && event.ctx.is_subscription());
+    diff.entering
+        .extend(entering.map(|event| event.row.clone()));
+    if let Some(replacement) = &state.replaced {
+        diff.replacement = Some(replacement.clone());
+    }
+
+    let leaving = deletes.read().filter(|event| {
+        event.ctx.is_unsubscription()
+            && diff
+                .replacement
+                .as_ref()
+                .is_some_and(|replacement| replacement.previous.is_some())
+    });
+    let leaving = leaving.map(|event| event.row.clone()).collect::<Vec<_>>();
+    diff.leaving.extend(leaving);
+
+    let done = diff.replacement.as_ref().is_some_and(|replacement| {
+        replacement
+            .previous
+            .as_ref()
+            .is_none_or(SubscriptionHandle::is_ended)
+    });
+    if done {
+        let replacement = diff.replacement.take().expect("checked above");
+        diffs.write(SubscriptionDiffEvent {
+            previous: replacement.previous.as_ref().map(SubscriptionHandle::id),
+            current: replacement.current,
+            entering: std::mem::take(&mut diff.entering),
+            leaving: std::mem::take(&mut diff.leaving),
+        });
+    } else if !replacing && diff.replacement.is_none() {
+        // A pending subscription that failed brings no rows
+        diff.entering.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Resource)]
+    struct CurrentLobby(u64);
+
+    #[allow(dead_code)]
+    #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, crate::TableRow)]
+    struct Player {
+        #[stdb(primary_key)]
+        id: u32,
+        lobby_id: u64,
+    }
+
+    fn lobby_app() -> crate::StdbTestApp {
+        use crate::{StdbPlugin, StdbTestApp};
+
+        let mut test = StdbTestApp::new(StdbPlugin::default().add_table::<Player>());
+        test.add_stdb_subscription_for_resource(|lobby: &CurrentLobby| {
+            format!("SELECT * FROM player WHERE lobby_id = {}", lobby.0)
+        })
+        .add_stdb_subscription_diff::<CurrentLobby, Player>()
+        .insert_resource(CurrentLobby(1));
+        test
+    }
+
+    #[test]
+    fn test_subscription_waits_for_connection() {
+        let mut app = App::new();
+        app.add_stdb_subscription_for_resource(|lobby: &CurrentLobby| {
+            format!("SELECT * FROM player WHERE lobby_id = {}", lobby.0)
+        });
+
+        app.update();
+        assert!(!app.world().resource::<ResourceSubscription<CurrentLobby>>().stale);
+
+        // Changed before a connection exists: kept until one does
+        app.insert_resource(CurrentLobby(7));
+        app.update();
+        app.update();
+        let state = app.world().resource::<ResourceSubscription<CurrentLobby>>();
+        assert!(state.stale);
+        assert!(state.current().is_none());
+    }
+
+    #[test]
+    fn test_changes_replace_the_subscription() {
+        use crate::{StdbPlugin, StdbTestApp};
+
+        let mut test = StdbTestApp::new(StdbPlugin::default());
+        test.add_stdb_subscription_for_resource(|lobby: &CurrentLobby| {
+            format!("SELECT * FROM player WHERE lobby_id = {}", lobby.0)
+        })
+        .insert_resource(CurrentLobby(1));
+        test.server().connect(None);
+        test.update();
+        test.server().apply_subscriptions();
+        test.update();
+        let first = test.assert_message::<SubscriptionReplacedEvent>(|_| true);
+        assert_eq!(first.previous, None);
+
+        test.world_mut().resource_mut::<CurrentLobby>().0 = 2;
+        test.update();
+        // The previous subscription is kept until the new one is applied
+        let subscriptions = test.server().subscriptions();
+        assert_eq!(subscriptions.len(), 2);
+        assert_eq!(subscriptions[0].id, first.current.0);
+        assert_eq!(
+            subscriptions[1].queries,
+            ["SELECT * FROM player WHERE lobby_id = 2"]
+        );
+        test.assert_no_message::<SubscriptionReplacedEvent>();
+
+        test.server().apply_subscriptions();
+        test.update();
+        let replaced = test.assert_message::<SubscriptionReplacedEvent>(|_| true);
+        assert_eq!(replaced.previous, Some(first.current));
+        assert_eq!(replaced.current.0, subscriptions[1].id);
+        let remaining = test.server().subscriptions();
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].id, replaced.current.0);
+    }
+
+    #[test]
+    fn test_resource_inserted_before_the_server_connects() {
+        let mut test = lobby_app();
+        test.run_frames(2);
+        assert!(test.server().subscriptions().is_empty());
+
+        test.server().connect(None);
+        test.update();
+        let subscriptions = test.server().subscriptions();
+        assert_eq!(subscriptions.len(), 1);
+        assert_eq!(
+            subscriptions[0].queries,
+            ["SELECT * FROM player WHERE lobby_id = 1"]
+        );
+    }
+
+    #[test]
+    fn test_subscription_is_made_again_after_failure_or_reconnection() {
+        let mut test = lobby_app();
+        test.server().connect(None);
+        test.update();
+        let id = test.server().subscriptions()[0].id;
+        test.server()
+            .fail_subscription(crate::SubscriptionId(id), "table is busy");
+        test.update();
+        test.update();
+        let retried = test.server().subscriptions();
+        assert_eq!(retried.len(), 1);
+        assert_ne!(retried[0].id, id);
+
+        test.server().apply_subscriptions();
+        test.update();
+        test.server().disconnect();
+        test.update();
+        assert!(test.server().subscriptions().is_empty());
+        test.server().connect(None);
+        test.update();
+        test.update();
+        assert_eq!(test.server().subscriptions().len(), 1);
+    }
+
+    #[test]
+    fn test_replacement_lists_entering_and_leaving_rows() {
+        use crate::TableEventKind;
+
+        let mut test = lobby_app();
+        test.server().connect(None);
+        test.update();
+        let first = test.server().subscriptions()[0].id;
+        let alice = Player { id: 1, lobby_id: 1 };
+        test.server().apply_subscription_with_rows(
+            crate::SubscriptionId(first),
+            std::slice::from_ref(&alice),
+        );
+        test.update();
+        let diff = test.assert_message::<SubscriptionDiffEvent<Player>>(|_| true);
+        assert_eq!(diff.previous, None);
+        assert_eq!(diff.entering, std::slice::from_ref(&alice));
+
+        test.world_mut().resource_mut::<CurrentLobby>().0 = 2;
+        test.update();
+        let second = test.server().subscriptions()[1].id;
+        let bob = Player { id: 2, lobby_id: 2 };
+        test.server().apply_subscription_with_rows(
+            crate::SubscriptionId(second),
+            std::slice::from_ref(&bob),
+        );
+        // Sent by the SDK as the previous subscription ends
+        test.server().backend().emit_table_event(
+            "player",
+            TableEventKind::Delete,
+            serde_json::json!({ "row": alice, "event": { "kind": "unsubscription" } }),
+        );
+        test.update();
+        test.update();
+        let diff = test.assert_message::<SubscriptionDiffEvent<Player>>(|_| true);
+        assert_eq!(diff.previous.map(|id| id.0), Some(first));
+        assert_eq!(diff.current.0, second);
+        assert_eq!(diff.entering, [bob]);
+        assert_eq!(diff.leaving, [alice]);
+    }
+}
@@ -0,0 +1,618 @@
+//! Recording bridge traffic and replaying it into a headless app
+//!
+//! [`StdbPlugin::with_recorder`](crate::StdbPlugin::with_recorder) records
+//! everything crossing the backend into a [`SessionRecorder`]: the connection
+//! events, subscription and table events the server sent, and the reducer calls,
+//! subscriptions and one-off queries the app made along with their outcomes.
+//!
+//! A [`Recording`] is saved as JSON Lines: a header line naming the format and
+//! its version, then one [`RecordedEntry`] per line. Files of a given version
+//! always read the same way; changes to the meaning of an entry bump
+//! [`RECORDING_VERSION`], and files of an unknown version are rejected.
+//!
+//! [`Replay`] feeds a recording back into an app through a [`MockBackend`],
+//! frame by frame, so a bug seen in a play session can be reproduced in a test.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use bevy::platform::time::Instant;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{BackendValue, MockBackend, MockReducerCall, TableEventKind};
+
+/// The `format` of the header line of a recording
+pub const RECORDING_FORMAT: &str = "bevy_spacetimedb_recording";
+
+/// The version of the recording format written by this crate
+pub const RECORDING_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct RecordingHeader {
+    format: String,
+    version: u32,
+}
+
+/// Something that crossed the backend
+///
+/// Events sent by the server keep the payload the backend reported, as JSON:
+/// 64-bit integers are numbers and wider ones decimal strings.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RecordedEvent {
+    /// The app started connecting
+    Connect,
+    /// The app started disconnecting
+    Disconnect,
+    /// The connection was established; `data` is the identity or null
+    Connected { data: Value },
+    /// The connection was closed; `data` is the error message or null
+    Disconnected { data: Value },
+    /// Connecting failed; `data` is the error message
+    ConnectionError { data: Value },
+    /// The app called a reducer
+    ReducerCall { reducer: String, args: Value },
+    /// A reducer call completed, successfully if there is no error
+    ReducerResult {
+        reducer: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+    /// The app subscribed to queries
+    Subscribe { id: u32, queries: Vec<String> },
+    /// The app ended a subscription
+    Unsubscribe { id: u32 },
+    /// A subscription was applied; `data` is `{ id, queries }`
+    SubscriptionApplied { data: Value },
+    /// A subscription failed; `data` is `{ id, queries, error }`
+    SubscriptionError { data: Value },
+    /// A subscription ended; `data` is `{ id, queries }`
+    SubscriptionEnded { data: Value },
+    /// A row was inserted; `data` is `{ row, event }`
+    Insert { table: String, data: Value },
+    /// A row was updated; `data` is `{ oldRow, newRow, event }`
+    Update { table: String, data: Value },
+    /// A row was deleted; `data` is `{ row, event }`
+    Delete { table: String, data: Value },
+    /// A reducer ran on the server; `data` is `{ event, args }`
+    Reducer { reducer: String, data: Value },
+    /// The app ran a one-off query
+    OneOffQuery { query: String },
+    /// A one-off query completed, with rows or an error
+    OneOffQueryResult {
+        query: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        rows: Option<Value>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+}
+
+/// A [`RecordedEvent`] and when it happened
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedEntry {
+    /// The number of app updates completed before the event
+    pub frame: u64,
+    /// Milliseconds since recording started
+    pub elapsed_ms: u64,
+    /// What happened
+    #[serde(flatten)]
+    pub event: RecordedEvent,
+}
+
+/// An error reading or writing a [`Recording`]
+#[derive(Debug)]
+pub enum RecordingError {
+    /// Reading or writing the file failed
+    Io(std::io::Error),
+    /// A line is not a valid header or entry (lines are numbered from 1)
+    Invalid { line: usize, message: String },
+    /// The recording was written in a version of the format this crate does not read
+    UnsupportedVersion(u32),
+}
+
+impl fmt::Display for RecordingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecordingError::Io(e) => write!(f, "{}", e),
+            RecordingError::Invalid { line, message } => write!(f, "line {}: {}", line, message),
+            RecordingError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported recording version {} (expected {})",
+                version, RECORDING_VERSION
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RecordingError {}
+
+impl From<std::io::Error> for RecordingError {
+    fn from(error: std::io::Error) -> Self {
+        RecordingError::Io(error)
+    }
+}
+
+/// A recorded session
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Recording {
+    /// The entries, in the order they happened
+    pub entries: Vec<RecordedEntry>,
+}
+
+impl Recording {
+    /// Render as JSON Lines, starting with the header line
+    pub fn to_json_lines(&self) -> String {
+        let header = RecordingHeader {
+            format: RECORDING_FORMAT.to_string(),
+            version: RECORDING_VERSION,
+        };
+        std::iter::once(serde_json::to_string(&header))
+            .chain(self.entries.iter().map(serde_json::to_string))
+            .map(|line| line.expect("recorded entries serialize") + "\n")
+            .collect()
+    }
+
+    /// Parse JSON Lines written by [`Recording::to_json_lines`]
+    pub fn from_json_lines(text: &str) -> Result<Self, RecordingError> {
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line))
+            .filter(|(_, line)| !line.trim().is_empty());
+        let invalid = |line: usize, message: String| RecordingError::Invalid { line, message };
+
+        let (number, header) = lines
+            .next()
+            .ok_or_else(|| invalid(1, "missing header".to_string()))?;
+        let header: RecordingHeader =
+            serde_json::from_str(header).map_err(|e| invalid(number, e.to_string()))?;
+        if header.format != RECORDING_FORMAT {
+            return Err(invalid(
+                number,
+                format!("not a recording: {}", header.format),
+            ));
+        }
+        if header.version != RECORDING_VERSION {
+            return Err(RecordingError::UnsupportedVersion(header.version));
+        }
+
+        let entries = lines
+            .map(|(number, line)| {
+                serde_json::from_str(line).map_err(|e| invalid(number, e.to_string()))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { entries })
+    }
+
+    /// Write to a file (not available on WASM: save [`Recording::to_json_lines`] instead)
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), RecordingError> {
+        Ok(std::fs::write(path, self.to_json_lines())?)
+    }
+
+    /// Read a file written by [`Recording::save`]
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, RecordingError> {
+        Self::from_json_lines(&std::fs::read_to_string(path)?)
+    }
+
+    /// The reducer calls made by the app, to compare with those of a replay
+    pub fn reducer_calls(&self) -> Vec<MockReducerCall> {
+        self.entries
+            .iter()
+            .filter_map(|entry| match &entry.event {
+                RecordedEvent::ReducerCall { reducer, args } => Some(MockReducerCall {
+                    reducer: reducer.clone(),
+                    args: args.clone(),
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+struct RecorderState {
+    start: Instant,
+    frame: u64,
+    entries: Vec<RecordedEntry>,
+}
+
+/// Records the traffic of a connection, see [`StdbPlugin::with_recorder`](crate::StdbPlugin::with_recorder)
+///
+/// Clones share the same recording; the plugin also inserts one as a resource.
+///
+/// # Example
+/// ```ignore
+/// let recorder = SessionRecorder::new();
+/// app.add_plugins(StdbPlugin::default().with_recorder(recorder.clone()));
+///
+/// // Later, e.g. when the session ends
+/// recorder.recording().save("session.jsonl")?;
+/// ```
+#[derive(Resource, Clone)]
+pub struct SessionRecorder {
+    state: Arc<Mutex<RecorderState>>,
+}
+
+impl Default for SessionRecorder {
+    fn default() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(RecorderState {
+                start: Instant::now(),
+                frame: 0,
+                entries: Vec::new(),
+            })),
+        }
+    }
+}
+
+impl SessionRecorder {
+    /// Start an empty recording
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn state(&self) -> MutexGuard<'_, RecorderState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The entries recorded so far
+    pub fn recording(&self) -> Recording {
+        Recording {
+            entries: self.state().entries.clone(),
+        }
+    }
+
+    /// Append an event, returning its index
+    pub(crate) fn record(&self, event: RecordedEvent) -> usize {
+        let mut state = self.state();
+        let entry = RecordedEntry {
+            frame: state.frame,
+            elapsed_ms: state.start.elapsed().as_millis() as u64,
+            event,
+        };
+        state.entries.push(entry);
+        state.entries.len() - 1
+    }
+
+    /// Set the id of the subscription recorded at `index`, once it is known
+    pub(crate) fn set_subscription_id(&self, index: usize, subscription_id: u32) {
+        if let Some(RecordedEvent::Subscribe { id, .. }) = self
+            .state()
+            .entries
+            .get_mut(index)
+            .map(|entry| &mut entry.event)
+        {
+            *id = subscription_id;
+        }
+    }
+
+    fn end_frame(&self) {
+        self.state().frame += 1;
+    }
+}
+
+/// Count the updates the entries are recorded against
+pub(crate) fn end_recorder_frame(recorder: Res<SessionRecorder>) {
+    recorder.end_frame();
+}
+
+/// Feeds a [`Recording`] into an app through a [`MockBackend`]
+///
+/// Events sent by the server are delivered before the update of the frame they
+/// were recorded in. Reducer calls resolve with their recorded outcome as soon
+/// as they are made, in the order they were recorded. Subscriptions are matched
+/// with the recorded ones in the order they are made.
+///
+/// # Example
+/// ```ignore
+/// let recording = Recording::load("session.jsonl")?;
+/// let mut replay = Replay::new(recording.clone());
+/// let mut test = StdbTestApp::with_mock(plugin(), replay.backend());
+/// test.add_systems(Update, greet_new_players);
+///
+/// replay.run(&mut test);
+/// assert_eq!(replay.backend().reducer_calls(), recording.reducer_calls());
+/// ```
+pub struct Replay {
+    entries: Vec<RecordedEntry>,
+    position: usize,
+    frame: u64,
+    backend: MockBackend,
+    /// Replayed subscription ids by recorded id
+    subscription_ids: HashMap<u32, u32>,
+}
+
+impl Replay {
+    /// Prepare to replay `recording`
+    pub fn new(recording: Recording) -> Self {
+        let backend = MockBackend::new();
+        let mut subscription_ids = HashMap::new();
+        for entry in &recording.entries {
+            match &entry.event {
+                RecordedEvent::ReducerResult { reducer, error } => {
+                    backend.push_reducer_result(reducer.clone(), error.clone().map_or(Ok(()), Err))
+                }
+                // The mock numbers subscriptions from 0 in the order they are made
+                RecordedEvent::Subscribe { id, .. } => {
+                    let replayed = subscription_ids.len() as u32;
+                    subscription_ids.insert(*id, replayed);
+                }
+                _ => {}
+            }
+        }
+
+        Self {
+            entries: recording.entries,
+            position: 0,
+            frame: 0,
+            backend,
+            subscription_ids,
+        }
+    }
+
+    /// The backend to give to the app, e.g. through [`StdbTestApp::with_mock`](crate::StdbTestApp::with_mock)
+    pub fn backend(&self) -> MockBackend {
+        self.backend.clone()
+    }
+
+    /// The number of updates run so far
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Whether every entry has been delivered
+    pub fn is_finished(&self) -> bool {
+        self.position == self.entries.len()
+    }
+
+    /// Deliver the events of the current frame and update the app
+    pub fn step(&mut self, app: &mut App) {
+        while let Some(entry) = self.entries.get(self.position) {
+            if entry.frame > self.frame {
+                break;
+            }
+            self.position += 1;
+            self.deliver(entry.event.clone());
+        }
+        app.update();
+        self.frame += 1;
+    }
+
+    /// Step until every entry has been delivered
+    pub fn run(&mut self, app: &mut App) {
+        while !self.is_finished() {
+            self.step(app);
+        }
+    }
+
+    fn subscription_id(&self, data: &Value) -> Option<u32> {
+        let id = data.get("id")?.as_u64()?;
+        self.subscription_ids.get(&u32::try_from(id).ok()?).copied()
+    }
+
+    fn deliver(&self, event: RecordedEvent) {
+        let message = |data: &Value| data.as_str().map(str::to_string);
+        match event {
+            RecordedEvent::Connected { data } => self
+                .backend
+                .accept_connection(serde_json::from_value(data).ok().flatten()),
+            RecordedEvent::Disconnected { data } => self.backend.close_connection(message(&data)),
+            RecordedEvent::ConnectionError { data } => self
+                .backend
+                .fail_connection(message(&data).unwrap_or_else(|| data.to_string())),
+            RecordedEvent::SubscriptionApplied { data } => {
+                if let Some(id) = self.subscription_id(&data) {
+                    self.backend.apply_subscription(id);
+                }
+            }
+            RecordedEvent::SubscriptionError { data } => {
+                if let Some(id) = self.subscription_id(&data) {
+                    let error = data.get("error").and_then(message).unwrap_or_default();
+                    self.backend.fail_subscription(id, error);
+                }
+            }
+            // Already ended if the app unsubscribed
+            RecordedEvent::SubscriptionEnded { data } => {
+                if let Some(id) = self.subscription_id(&data) {
+                    self.backend.end_subscription(id);
+                }
+            }
+            RecordedEvent::Insert { table, data } => {
+                self.backend
+                    .emit_table_event(&table, TableEventKind::Insert, data)
+            }
+            RecordedEvent::Update { table, data } => {
+                self.backend
+                    .emit_table_event(&table, TableEventKind::Update, data)
+            }
+            RecordedEvent::Delete { table, data } => {
+                self.backend
+                    .emit_table_event(&table, TableEventKind::Delete, data)
+            }
+            RecordedEvent::Reducer { reducer, data } => {
+                self.backend.emit_reducer_event(&reducer, data)
+            }
+            RecordedEvent::OneOffQueryResult { query, rows, error } => {
+                let result = match error {
+                    Some(error) => Err(error),
+                    None => Ok(BackendValue::Json(
+                        rows.unwrap_or_else(|| Value::Array(Vec::new())),
+                    )),
+                };
+                self.backend.finish_one_off_query(&query, result);
+            }
+            // Made by the app itself, or preloaded into the backend
+            RecordedEvent::Connect
+            | RecordedEvent::Disconnect
+            | RecordedEvent::ReducerCall { .. }
+            | RecordedEvent::ReducerResult { .. }
+            | RecordedEvent::Subscribe { .. }
+            | RecordedEvent::Unsubscribe { .. }
+            | RecordedEvent::OneOffQuery { .. } => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        DeleteEvent, InsertEvent, StdbConnection, StdbPlugin, StdbReducerErrorEvent, StdbTestApp,
+    };
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, crate::TableRow)]
+    struct Player {
+        #[stdb(primary_key)]
+        id: u64,
+        name: String,
+    }
+
+    crate::define_reducer!(Greet(player_id: u64));
+
+    /// What the app saw, to compare a session with its replay
+    #[derive(Resource, Default)]
+    struct Seen(Vec<String>);
+
+    fn subscribe_players(stdb: Res<StdbConnection>) {
+        stdb.subscription_builder()
+            .subscribe("SELECT * FROM player");
+    }
+
+    fn greet_new_players(
+        mut inserts: MessageReader<InsertEvent<Player>>,
+        mut deletes: MessageReader<DeleteEvent<Player>>,
+        mut errors: MessageReader<StdbReducerErrorEvent>,
+        stdb: Res<StdbConnection>,
+        mut seen: ResMut<Seen>,
+    ) {
+        for event in inserts.read() {
+            seen.0.push(format!("insert {}", event.row.name));
+            stdb.reducers().call::<Greet>((event.row.id,)).unwrap();
+        }
+        for event in deletes.read() {
+            seen.0.push(format!("delete {}", event.row.name));
+        }
+        for error in errors.read() {
+            seen.0
+                .push(format!("{} failed: {}", error.reducer, error.err));
+        }
+    }
+
+    fn test_app(plugin: StdbPlugin, backend: MockBackend) -> StdbTestApp {
+        let mut test = StdbTestApp::with_mock(plugin.add_table::<Player>(), backend);
+        test.init_resource::<Seen>()
+            .add_systems(Startup, subscribe_players)
+            .add_systems(Update, greet_new_players);
+        test
+    }
+
+    fn player(id: u64, name: &str) -> Player {
+        Player {
+            id,
+            name: name.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_replay_reproduces_session() {
+        let recorder = SessionRecorder::new();
+        let mut test = test_app(
+            StdbPlugin::default().with_recorder(recorder.clone()),
+            MockBackend::new(),
+        );
+        test.server().connect(None);
+        test.update();
+        test.server().apply_subscriptions();
+        test.server().fail_reducer::<Greet>("busy");
+        test.server().insert(player(1, "Alice"));
+        test.run_frames(2);
+        test.server().insert(player(2, "Bob"));
+        test.server().delete(player(1, "Alice"));
+        test.run_frames(2);
+
+        let recording = recorder.recording();
+        assert_eq!(
+            Recording::from_json_lines(&recording.to_json_lines()).unwrap(),
+            recording
+        );
+        assert_eq!(recording.reducer_calls(), test.server().reducer_calls());
+
+        let mut replay = Replay::new(recording.clone());
+        let mut replayed = test_app(StdbPlugin::default(), replay.backend());
+        replay.run(&mut replayed);
+        replayed.update();
+
+        assert_eq!(replay.backend().reducer_calls(), recording.reducer_calls());
+        assert!(replay.backend().subscriptions()[0].applied);
+        assert_eq!(
+            replayed.world().resource::<Seen>().0,
+            test.world().resource::<Seen>().0
+        );
+        assert_eq!(
+            test.world().resource::<Seen>().0,
+            [
+                "insert Alice",
+                "Greet failed: busy",
+                "insert Bob",
+                "delete Alice"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_replay_decodes_wide_connection_ids() {
+        let caller = crate::ConnectionId::from_u128(u128::MAX);
+        let text = format!(
+            r#"{{"format":"bevy_spacetimedb_recording","version":1}}
+{{"frame":0,"elapsed_ms":0,"type":"connect"}}
+{{"frame":1,"elapsed_ms":16,"type":"connected","data":null}}
+{{"frame":1,"elapsed_ms":16,"type":"insert","table":"player","data":{{"row":{{"id":1,"name":"Alice"}},"event":{{"kind":"reducer","reducerName":"join","callerIdentity":{{"__identity__":"{}"}},"callerConnectionId":{}}}}}}}
+"#,
+            crate::Identity::ZERO,
+            serde_json::to_string(&caller).unwrap()
+        );
+
+        let mut replay = Replay::new(Recording::from_json_lines(&text).unwrap());
+        let mut replayed = test_app(StdbPlugin::default(), replay.backend());
+        replay.run(&mut replayed);
+        replayed.update();
+
+        let insert = replayed.assert_message::<InsertEvent<Player>>(|_| true);
+        let reducer = insert.ctx.reducer().expect("reducer context");
+        assert_eq!(reducer.caller_connection_id, Some(caller));
+    }
+
+    #[test]
+    fn test_recording_format_is_stable() {
+        let text = r#"{"format":"bevy_spacetimedb_recording","version":1}
+{"frame":0,"elapsed_ms":0,"type":"connect"}
+{"frame":1,"elapsed_ms":16,"type":"connected","data":null}
+{"frame":1,"elapsed_ms":16,"type":"insert","table":"player","data":{"row":{"id":1,"name":"Alice"}}}
+{"frame":2,"elapsed_ms":33,"type":"reducer_call","reducer":"Greet","args":[1]}
+{"frame":3,"elapsed_ms":50,"type":"reducer_result","reducer":"Greet","error":"busy"}
+"#;
+        let recording = Recording::from_json_lines(text).unwrap();
+        assert_eq!(recording.to_json_lines(), text);
+        assert_eq!(
+            recording.entries[4].event,
+            RecordedEvent::ReducerResult {
+                reducer: "Greet".to_string(),
+                error: Some("busy".to_string()),
+            }
+        );
+
+        let newer = text.replace(r#""version":1"#, r#""version":2"#);
+        assert!(matches!(
+            Recording::from_json_lines(&newer),
+            Err(RecordingError::UnsupportedVersion(2))
+        ));
+        assert!(matches!(
+            Recording::from_json_lines("{\"format\":\"other\",\"version\":1}\n"),
+            Err(RecordingError::Invalid { line: 1, .. })
+        ));
+    }
+}
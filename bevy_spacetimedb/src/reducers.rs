@@ -1,4 +1,13 @@
-use crate::bridge::SpacetimeDBBridge;
+use bevy::app::App;
+use bevy::log::{error, info};
+
+use std::sync::mpsc::Sender;
+
+use crate::backend::{BackendValue, CodecError, StdbBackend};
+use crate::{
+    AddEventChannelAppExtensions, ReducerEvent, ReducerResultEvent, StdbPlugin,
+    StdbReducerErrorEvent,
+};
 
 /// Trait for reducers that can be called on the SpacetimeDB server
 ///
@@ -29,19 +38,90 @@ pub trait Reducer: Send + Sync + 'static {
     type Args: serde::Serialize;
 }
 
+/// Trait for events sent each time a reducer runs on the server
+///
+/// Derive it with `#[derive(RegisterReducerEvent)]` and register the type with
+/// [`StdbPlugin::add_reducer`]. Each run of the reducer seen by this client is
+/// then sent as a `ReducerResultEvent<Self>`.
+///
+/// # Example
+/// ```ignore
+/// #[derive(RegisterReducerEvent)]
+/// pub struct SetName {
+///     pub event: ReducerEvent,
+///     pub name: String,
+/// }
+///
+/// fn on_set_name(mut events: ReadReducerEvent<SetName>) {
+///     for SetName { event, name } in events.read().map(|e| &e.result) {
+///         info!("{} renamed to {} ({:?})", event.caller_identity, name, event.status);
+///     }
+/// }
+/// ```
+pub trait RegisterableReducerEvent: Send + Sync + Sized + 'static {
+    /// The name of the reducer as defined in your SpacetimeDB module
+    const REDUCER_NAME: &'static str;
+
+    /// Build the event from a run of the reducer and the array of its arguments
+    fn from_reducer_event(event: ReducerEvent, args: BackendValue) -> Result<Self, CodecError>;
+}
+
+impl StdbPlugin {
+    /// Send a `ReducerResultEvent<T>` each time the reducer of `T` runs
+    ///
+    /// # Example
+    /// ```ignore
+    /// StdbPlugin::default()
+    ///     .add_reducer::<SetName>()
+    /// ```
+    pub fn add_reducer<T: RegisterableReducerEvent>(mut self) -> Self {
+        self.reducer_configs.push(setup_reducer_events::<T>);
+        self
+    }
+}
+
+/// Function registering the event channel and backend callback of one reducer
+pub(crate) type ReducerSetupFn = fn(&dyn StdbBackend, &mut App);
+
+/// Setup the events of a reducer
+fn setup_reducer_events<T: RegisterableReducerEvent>(backend: &dyn StdbBackend, app: &mut App) {
+    let (send, recv) = std::sync::mpsc::channel::<ReducerResultEvent<T>>();
+    app.add_event_channel(recv);
+
+    backend.on_reducer(
+        T::REDUCER_NAME,
+        Box::new(move |data: BackendValue| {
+            let result = data
+                .decode_field::<ReducerEvent>("event")
+                .and_then(|event| T::from_reducer_event(event, data.field("args")));
+            match result {
+                Ok(result) => {
+                    let _ = send.send(ReducerResultEvent::new(result));
+                }
+                Err(e) => error!(
+                    "Failed to deserialize event of reducer {}: {} (payload: {})",
+                    T::REDUCER_NAME,
+                    e,
+                    data.to_json_string()
+                ),
+            }
+        }),
+    );
+}
+
 /// Helper for calling reducers on the SpacetimeDB server
 ///
 /// Obtained via `StdbConnection::reducers()`.
 pub struct ReducerCaller<'a> {
-    pub(crate) bridge: &'a SpacetimeDBBridge,
-    pub(crate) connection_id: u32,
+    pub(crate) backend: &'a dyn StdbBackend,
+    pub(crate) errors: &'a Sender<StdbReducerErrorEvent>,
 }
 
 impl<'a> ReducerCaller<'a> {
     /// Call a reducer on the SpacetimeDB server
     ///
     /// This is fire-and-forget - the call happens asynchronously and any errors
-    /// will be logged to the console.
+    /// will be logged and sent as a `StdbReducerErrorEvent`.
     ///
     /// # Example
     /// ```ignore
@@ -51,32 +131,25 @@ impl<'a> ReducerCaller<'a> {
     ///         .expect("Failed to serialize reducer args");
     /// }
     /// ```
-    pub fn call<R: Reducer>(&self, args: R::Args) -> Result<(), serde_wasm_bindgen::Error> {
-        // Serialize the arguments to JsValue
-        let args_value = serde_wasm_bindgen::to_value(&args)?;
-
-        let connection_id = self.connection_id;
-        let reducer_name = R::NAME.to_string();
-
-        // Call the reducer and get the promise
-        let promise = self.bridge.call_reducer(connection_id, &reducer_name, args_value);
-
-        // Call the reducer asynchronously
-        wasm_bindgen_futures::spawn_local(async move {
-            match wasm_bindgen_futures::JsFuture::from(promise).await
-            {
-                Ok(_) => {
-                    web_sys::console::log_1(
-                        &format!("Called reducer: {}", reducer_name).into(),
-                    );
-                }
+    pub fn call<R: Reducer>(&self, args: R::Args) -> Result<(), CodecError> {
+        // Serialize the arguments the way the backend expects them
+        let args_value = BackendValue::encode(&args, self.backend.encoding())?;
+
+        let errors = self.errors.clone();
+        self.backend.call_reducer(
+            R::NAME,
+            args_value,
+            Box::new(move |result| match result {
+                Ok(()) => info!("Called reducer: {}", R::NAME),
                 Err(e) => {
-                    web_sys::console::error_1(
-                        &format!("Failed to call reducer {}: {:?}", reducer_name, e).into(),
-                    );
+                    error!("Failed to call reducer {}: {}", R::NAME, e);
+                    let _ = errors.send(StdbReducerErrorEvent {
+                        reducer: R::NAME.to_string(),
+                        err: e,
+                    });
                 }
-            }
-        });
+            }),
+        );
 
         Ok(())
     }
@@ -109,3 +182,77 @@ macro_rules! define_reducer {
         }
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Identity, ReducerStatus, RegisterReducerEvent, StdbTestApp};
+
+    #[derive(Debug, Clone, RegisterReducerEvent)]
+    struct SetName {
+        event: ReducerEvent,
+        id: u64,
+        name: String,
+    }
+
+    #[derive(Debug, Clone, RegisterReducerEvent)]
+    #[stdb(reducer = "generate_galaxy")]
+    struct Generate {
+        event: ReducerEvent,
+    }
+
+    fn test_app() -> StdbTestApp {
+        StdbTestApp::new(
+            StdbPlugin::default()
+                .add_reducer::<SetName>()
+                .add_reducer::<Generate>(),
+        )
+    }
+
+    #[test]
+    fn test_reducer_runs_are_sent_as_events() {
+        let mut test = test_app();
+        let caller = Identity::from_be_byte_array([7; 32]);
+
+        test.server()
+            .run_reducer::<SetName>((3u64, "Alice"), caller);
+        test.server().run_reducer::<Generate>((), Identity::ZERO);
+        test.run_frames(1);
+
+        let set_name = test.assert_message::<ReducerResultEvent<SetName>>(|_| true);
+        assert_eq!(set_name.result.event.reducer_name, "set_name");
+        assert!(set_name.result.event.is_caused_by(&caller));
+        assert!(set_name.result.event.is_committed());
+        assert_eq!(
+            (set_name.result.id, set_name.result.name.as_str()),
+            (3, "Alice")
+        );
+        test.assert_message::<ReducerResultEvent<Generate>>(|event| {
+            event.result.event.reducer_name == "generate_galaxy"
+        });
+    }
+
+    #[test]
+    fn test_failed_runs_and_mismatched_arguments() {
+        let mut test = test_app();
+
+        test.server().backend().run_reducer(
+            "set_name",
+            &(3u64, "Alice"),
+            Identity::ZERO,
+            ReducerStatus::Failed("name taken".to_string()),
+        );
+        test.run_frames(1);
+        let failed = test.assert_message::<ReducerResultEvent<SetName>>(|_| true);
+        assert_eq!(
+            failed.result.event.status,
+            ReducerStatus::Failed("name taken".to_string())
+        );
+
+        // Arguments that don't match the struct are logged and dropped
+        test.server()
+            .run_reducer::<SetName>(("Alice", 3u64), Identity::ZERO);
+        test.run_frames(1);
+        test.assert_no_message::<ReducerResultEvent<SetName>>();
+    }
+}
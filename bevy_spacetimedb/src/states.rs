@@ -0,0 +1,274 @@
+//! Subscriptions scoped to Bevy states
+
+use std::collections::HashSet;
+
+use bevy::ecs::message::MessageCursor;
+use bevy::prelude::*;
+
+use crate::{IntoQueries, StdbConnectedEvent, StdbConnection, SubscriptionHandle};
+
+/// Marks the entity holding a [`SubscriptionHandle`] that is active while in state `S`
+///
+/// Spawned by [`StateSubscriptionAppExtensions::add_stdb_subscription_in_state`]. The
+/// subscription is ended and the entity despawned when the state is exited.
+#[derive(Component, Debug, Clone)]
+pub struct StdbStateScoped<S: States>(pub S);
+
+/// Which `add_stdb_subscription_in_state` call a scoped subscription comes from
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+struct StateScopeSlot(usize);
+
+/// The number of `add_stdb_subscription_in_state` calls so far
+#[derive(Resource, Default)]
+struct StateScopeSlots(usize);
+
+/// States for which the unsubscribe-on-exit system has been added
+#[derive(Resource)]
+struct StateScopedExits<S: States>(HashSet<S>);
+
+/// Allows declaring subscriptions that are active only while in a given state
+pub trait StateSubscriptionAppExtensions {
+    /// Subscribe while in `state` and unsubscribe on exiting it
+    ///
+    /// The subscription is made on entering `state`, or once connected if the
+    /// connection is not up yet, and made again after a reconnection.
+    ///
+    /// `query_fn` is a system returning the queries, so it can read resources such
+    /// as the current match id. Rows leaving scope on exit arrive as `DeleteEvent`s
+    /// whose `ctx.is_unsubscription()` is set; tables added with
+    /// `add_table_as_entities` despawn their entities.
+    ///
+    /// # Example
+    /// ```ignore
+    /// app.add_stdb_subscription_in_state(GameState::InMatch, |current: Res<CurrentMatch>| {
+    ///     StdbQuery::<Player>::filter(col!(Player::match_id).eq(current.id))
+    /// });
+    /// ```
+    fn add_stdb_subscription_in_state<S: States, Q: IntoQueries + 'static, M>(
+        &mut self,
+        state: S,
+        query_fn: impl IntoSystem<(), Q, M> + 'static,
+    ) -> &mut Self;
+}
+
+impl StateSubscriptionAppExtensions for App {
+    fn add_stdb_subscription_in_state<S: States, Q: IntoQueries + 'static, M>(
+        &mut self,
+        state: S,
+        query_fn: impl IntoSystem<(), Q, M> + 'static,
+    ) -> &mut Self {
+        let slot = {
+            let mut slots = self.world_mut().get_resource_or_init::<StateScopeSlots>();
+            slots.0 += 1;
+            StateScopeSlot(slots.0)
+        };
+
+        let scope = state.clone();
+        self.add_systems(
+            OnEnter(state.clone()),
+            move |stdb: Option<Res<StdbConnection>>| {
+                if stdb.is_none() {
+                    warn!(
+                        "Cannot subscribe on entering {:?}: StdbPlugin is not added",
+                        scope
+                    );
+                }
+            },
+        );
+
+        let scope = state.clone();
+        self.add_systems(
+            Update,
+            query_fn
+                .pipe(
+                    move |In(queries): In<Q>,
+                          stdb: Res<StdbConnection>,
+                          slots: Query<(Entity, &StateScopeSlot)>,
+                          mut commands: Commands| {
+                        // The subscription of an earlier connection ended with it
+                        for (entity, _) in slots.iter().filter(|(_, s)| **s == slot) {
+                            commands.entity(entity).try_despawn();
+                        }
+                        let handle = stdb.subscription_builder().subscribe(queries);
+                        commands.spawn((handle, StdbStateScoped(scope.clone()), slot));
+                    },
+                )
+                .run_if(in_state(state.clone()).and(
+                    // Once connected if not made yet, and again after each reconnection
+                    move |stdb: Option<Res<StdbConnection>>,
+                          connected: Option<Res<Messages<StdbConnectedEvent>>>,
+                          mut cursor: Local<MessageCursor<StdbConnectedEvent>>,
+                          slots: Query<&StateScopeSlot>| {
+                        let reconnected =
+                            connected.is_some_and(|messages| cursor.read(&messages).count() > 0);
+                        stdb.is_some_and(|stdb| stdb.is_connected())
+                            && (reconnected || !slots.iter().any(|s| *s == slot))
+                    },
+                )),
+        );
+
+        let mut exits = self
+            .world_mut()
+            .get_resource_or_insert_with(|| StateScopedExits::<S>(HashSet::new()));
+        if exits.0.insert(state.clone()) {
+            self.add_systems(OnExit(state.clone()), unsubscribe_on_exit::<S>(state));
+        }
+        self
+    }
+}
+
+type ScopedSubscriptions<'w, 's, S> =
+    Query<'w, 's, (Entity, &'static SubscriptionHandle, &'static StdbStateScoped<S>)>;
+
+/// End the subscriptions scoped to `state`
+fn unsubscribe_on_exit<S: States>(state: S) -> impl FnMut(Commands, ScopedSubscriptions<S>) {
+    move |mut commands, subscriptions| {
+        for (entity, handle, scoped) in &subscriptions {
+            if scoped.0 == state {
+                handle.unsubscribe();
+                commands.entity(entity).try_despawn();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::state::app::StatesPlugin;
+
+    #[derive(States, Debug, Clone, PartialEq, Eq, Hash, Default)]
+    enum GameState {
+        #[default]
+        Menu,
+        InMatch,
+    }
+
+    #[test]
+    fn test_exit_system_is_added_once_per_state() {
+        let mut app = App::new();
+        app.add_plugins(StatesPlugin)
+            .init_state::<GameState>()
+            .add_stdb_subscription_in_state(GameState::InMatch, || "SELECT * FROM player")
+            .add_stdb_subscription_in_state(GameState::InMatch, || "SELECT * FROM lobby");
+
+        assert_eq!(
+            app.world().resource::<StateScopedExits<GameState>>().0.len(),
+            1
+        );
+
+        // Without a connection, entering the state only warns
+        app.world_mut()
+            .resource_mut::<NextState<GameState>>()
+            .set(GameState::InMatch);
+        app.update();
+        assert_eq!(
+            app.world_mut()
+                .query::<&StdbStateScoped<GameState>>()
+                .iter(app.world())
+                .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_subscription_follows_state() {
+        use crate::{StdbPlugin, StdbTestApp};
+
+        #[derive(Resource)]
+        struct CurrentMatch(u64);
+
+        let mut test = StdbTestApp::new(StdbPlugin::default());
+        test.add_plugins(StatesPlugin)
+            .init_state::<GameState>()
+            .insert_resource(CurrentMatch(7))
+            .add_stdb_subscription_in_state(GameState::InMatch, |current: Res<CurrentMatch>| {
+                format!("SELECT * FROM player WHERE match_id = {}", current.0)
+            });
+        test.server().connect(None);
+        test.update();
+        assert!(test.server().subscriptions().is_empty());
+
+        test.world_mut()
+            .resource_mut::<NextState<GameState>>()
+            .set(GameState::InMatch);
+        test.update();
+        let subscriptions = test.server().subscriptions();
+        assert_eq!(subscriptions.len(), 1);
+        assert_eq!(
+            subscriptions[0].queries,
+            ["SELECT * FROM player WHERE match_id = 7"]
+        );
+        let scoped = test
+            .world_mut()
+            .query_filtered::<Entity, With<StdbStateScoped<GameState>>>()
+            .single(test.world())
+            .unwrap();
+
+        test.world_mut()
+            .resource_mut::<NextState<GameState>>()
+            .set(GameState::Menu);
+        test.update();
+        assert!(test.server().subscriptions().is_empty());
+        assert!(test.world().get_entity(scoped).is_err());
+    }
+
+    fn in_match_app() -> crate::StdbTestApp {
+        use crate::{StdbPlugin, StdbTestApp};
+
+        let mut test = StdbTestApp::new(StdbPlugin::default());
+        test.add_plugins(StatesPlugin)
+            .init_state::<GameState>()
+            .add_stdb_subscription_in_state(GameState::InMatch, || "SELECT * FROM player");
+        test.world_mut()
+            .resource_mut::<NextState<GameState>>()
+            .set(GameState::InMatch);
+        test
+    }
+
+    #[test]
+    fn test_subscription_waits_for_connection() {
+        let mut test = in_match_app();
+        test.run_frames(2);
+        assert!(test.server().subscriptions().is_empty());
+        test.assert_no_message::<crate::SubscriptionErrorEvent>();
+
+        test.server().connect(None);
+        test.run_frames(2);
+        let subscriptions = test.server().subscriptions();
+        assert_eq!(subscriptions.len(), 1);
+        assert_eq!(subscriptions[0].queries, ["SELECT * FROM player"]);
+    }
+
+    #[test]
+    fn test_subscription_is_made_again_after_reconnection() {
+        let mut test = in_match_app();
+        test.server().connect(None);
+        test.update();
+        test.server().apply_subscriptions();
+        let first = test
+            .world_mut()
+            .query::<&SubscriptionHandle>()
+            .single(test.world())
+            .unwrap()
+            .id();
+
+        test.server().disconnect();
+        test.update();
+        test.server().connect(None);
+        test.run_frames(2);
+        let handles = test
+            .world_mut()
+            .query::<&SubscriptionHandle>()
+            .iter(test.world())
+            .map(SubscriptionHandle::id)
+            .collect::<Vec<_>>();
+        assert_eq!(handles.len(), 1);
+        assert_ne!(handles[0], first);
+        assert!(test
+            .server()
+            .subscriptions()
+            .iter()
+            .any(|subscription| subscription.id == handles[0].0 && !subscription.applied));
+    }
+}
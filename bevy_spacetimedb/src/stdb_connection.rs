@@ -1,45 +1,51 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc::Sender, Arc};
+
 use bevy::prelude::Resource;
-use crate::bridge::SpacetimeDBBridge;
+use crate::backend::StdbBackend;
+use crate::one_off::OneOffQueryRegistry;
 use crate::reducers::ReducerCaller;
+use crate::subscriptions::{SubscriptionBuilder, SubscriptionRegistry};
+use crate::StdbReducerErrorEvent;
 
-/// A connection to the SpacetimeDB server via the TypeScript SDK bridge
+/// A connection to the SpacetimeDB server
 #[derive(Resource, Clone)]
 pub struct StdbConnection {
-    /// The JavaScript bridge instance (wrapped in SendSyncWrapper for WASM single-threaded context)
-    pub(crate) bridge: SendSyncWrapper<SpacetimeDBBridge>,
-    /// The connection ID
-    pub(crate) connection_id: u32,
-}
-
-/// Wrapper to make JS types Send + Sync in WASM single-threaded context
-/// SAFETY: WASM is single-threaded, so Send + Sync are safe.
-/// This assumes single-threaded WASM without atomics. If WASM gains threading support,
-/// this wrapper will need to be reevaluated for soundness.
-#[cfg(target_feature = "atomics")]
-compile_error!("SendSyncWrapper assumes single-threaded WASM. Review safety with atomics enabled.");
-
-#[derive(Clone)]
-pub(crate) struct SendSyncWrapper<T>(T);
-
-unsafe impl<T> Send for SendSyncWrapper<T> {}
-unsafe impl<T> Sync for SendSyncWrapper<T> {}
-
-impl<T> std::ops::Deref for SendSyncWrapper<T> {
-    type Target = T;
-    fn deref(&self) -> &Self::Target {
-        &self.0
-    }
+    /// The backend the connection goes through
+    pub(crate) backend: Arc<dyn StdbBackend>,
+    /// Lifecycle state of the subscriptions started on this connection
+    pub(crate) subscriptions: SubscriptionRegistry,
+    /// Result event senders of one-off queries, per registered table
+    pub(crate) one_off_queries: OneOffQueryRegistry,
+    /// Sender of the events of failed reducer calls
+    pub(crate) reducer_errors: Sender<StdbReducerErrorEvent>,
+    /// Whether the backend reported the connection as established and not yet closed
+    pub(crate) connected: Arc<AtomicBool>,
 }
 
 impl StdbConnection {
     /// Create a new connection resource
-    pub(crate) fn new(bridge: SpacetimeDBBridge, connection_id: u32) -> Self {
+    pub(crate) fn new(
+        backend: Arc<dyn StdbBackend>,
+        reducer_errors: Sender<StdbReducerErrorEvent>,
+    ) -> Self {
         Self {
-            bridge: SendSyncWrapper(bridge),
-            connection_id,
+            backend,
+            subscriptions: SubscriptionRegistry::default(),
+            one_off_queries: OneOffQueryRegistry::default(),
+            reducer_errors,
+            connected: Arc::new(AtomicBool::new(false)),
         }
     }
 
+    /// Whether the connection is established
+    ///
+    /// Set before the matching `StdbConnectedEvent` or `StdbDisconnectedEvent` is
+    /// sent. Subscriptions made while not connected fail right away.
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Relaxed)
+    }
+
     /// Get a reducer caller for invoking reducers on the SpacetimeDB server
     ///
     /// # Example
@@ -52,36 +58,35 @@ impl StdbConnection {
     /// ```
     pub fn reducers(&self) -> ReducerCaller<'_> {
         ReducerCaller {
-            bridge: &self.bridge,
-            connection_id: self.connection_id,
+            backend: self.backend.as_ref(),
+            errors: &self.reducer_errors,
         }
     }
 
+    /// Get a builder for subscribing to SQL queries
+    ///
+    /// # Example
+    /// ```ignore
+    /// fn subscribe_system(stdb: Res<StdbConnection>, mut commands: Commands) {
+    ///     let handle = stdb
+    ///         .subscription_builder()
+    ///         .subscribe("SELECT * FROM player WHERE online = true");
+    ///     commands.insert_resource(handle);
+    /// }
+    /// ```
+    pub fn subscription_builder(&self) -> SubscriptionBuilder<'_> {
+        SubscriptionBuilder::new(self)
+    }
+
     /// Get the connection ID
     pub fn connection_id(&self) -> u32 {
-        self.connection_id
+        self.backend.connection_id()
     }
 
     /// Disconnect from the SpacetimeDB server
     ///
     /// This returns immediately and the disconnection happens asynchronously.
     pub fn disconnect(&self) {
-        let connection_id = self.connection_id;
-
-        // Call disconnect directly - no async needed since it returns a Promise
-        let promise = self.bridge.disconnect(connection_id);
-
-        wasm_bindgen_futures::spawn_local(async move {
-            match wasm_bindgen_futures::JsFuture::from(promise).await {
-                Ok(_) => {
-                    web_sys::console::log_1(&format!("Disconnected from SpacetimeDB").into());
-                }
-                Err(e) => {
-                    web_sys::console::error_1(
-                        &format!("Failed to disconnect: {:?}", e).into(),
-                    );
-                }
-            }
-        });
+        self.backend.disconnect();
     }
 }
@@ -0,0 +1,445 @@
+//! SQL subscriptions with handles and lifecycle events
+//!
+//! Subscriptions are started with [`StdbConnection::subscription_builder`]. Their
+//! outcome is reported as [`SubscriptionAppliedEvent`], [`SubscriptionErrorEvent`]
+//! and [`SubscriptionEndedEvent`], identified by the [`SubscriptionId`] of the
+//! returned [`SubscriptionHandle`].
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use bevy::prelude::*;
+use serde::{de::DeserializeOwned, Deserialize};
+
+use crate::{
+    backend::{BackendCallback, StdbBackend}, AddEventChannelAppExtensions, StdbConnection,
+    SubscriptionAppliedEvent, SubscriptionEndedEvent, SubscriptionErrorEvent,
+    SubscriptionReplacedEvent,
+};
+
+/// Identifies a subscription in its handle and events
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize)]
+#[serde(transparent)]
+pub struct SubscriptionId(pub(crate) u32);
+
+/// Types that can be used as the queries of a subscription
+///
+/// Implemented for a single query (`&str`, `String`) and for arrays, slices and
+/// vectors of queries.
+pub trait IntoQueries {
+    /// Convert into a list of SQL queries
+    fn into_queries(self) -> Vec<String>;
+}
+
+impl IntoQueries for &str {
+    fn into_queries(self) -> Vec<String> {
+        vec![self.to_string()]
+    }
+}
+
+impl IntoQueries for String {
+    fn into_queries(self) -> Vec<String> {
+        vec![self]
+    }
+}
+
+impl<S: Into<String>, const N: usize> IntoQueries for [S; N] {
+    fn into_queries(self) -> Vec<String> {
+        self.into_iter().map(Into::into).collect()
+    }
+}
+
+impl<S: Into<String>> IntoQueries for Vec<S> {
+    fn into_queries(self) -> Vec<String> {
+        self.into_iter().map(Into::into).collect()
+    }
+}
+
+impl<S: AsRef<str>> IntoQueries for &[S] {
+    fn into_queries(self) -> Vec<String> {
+        self.iter().map(|query| query.as_ref().to_string()).collect()
+    }
+}
+
+/// Lifecycle state of one subscription, shared by all clones of its handle
+#[derive(Debug, Default)]
+struct SubscriptionState {
+    applied: AtomicBool,
+    unsubscribed: AtomicBool,
+    ended: AtomicBool,
+}
+
+/// Lifecycle state of the subscriptions of a connection, updated by the backend callbacks
+#[derive(Clone, Default)]
+pub(crate) struct SubscriptionRegistry {
+    inner: Arc<Mutex<RegistryState>>,
+}
+
+#[derive(Default)]
+struct RegistryState {
+    states: HashMap<SubscriptionId, Arc<SubscriptionState>>,
+    /// Subscriptions being started, whose id the backend has not returned yet
+    starting: usize,
+}
+
+impl SubscriptionRegistry {
+    fn lock(&self) -> std::sync::MutexGuard<'_, RegistryState> {
+        self.inner.lock().expect("subscription registry poisoned")
+    }
+
+    /// Note that a subscription is being started, before asking the backend
+    fn start(&self) {
+        self.lock().starting += 1;
+    }
+
+    /// Get the state for the subscription started last, now that its id is known
+    ///
+    /// The backend may already have reported an error for it (e.g. an invalid
+    /// connection), in which case the returned state is already ended.
+    fn register(&self, id: SubscriptionId) -> Arc<SubscriptionState> {
+        let mut registry = self.lock();
+        registry.starting = registry.starting.saturating_sub(1);
+        let state = registry.states.entry(id).or_default().clone();
+        if state.ended.load(Ordering::Relaxed) {
+            registry.states.remove(&id);
+        }
+        if registry.starting == 0 {
+            // Errors reported for other ids while starting were not for us
+            registry
+                .states
+                .retain(|_, state| !state.ended.load(Ordering::Relaxed));
+        }
+        state
+    }
+
+    fn mark_applied(&self, id: SubscriptionId) {
+        if let Some(state) = self.lock().states.get(&id) {
+            state.applied.store(true, Ordering::Relaxed);
+        }
+    }
+
+    fn mark_ended(&self, id: SubscriptionId) {
+        let mut registry = self.lock();
+        match registry.states.remove(&id) {
+            Some(state) => state.ended.store(true, Ordering::Relaxed),
+            // Reported before the handle was registered
+            None if registry.starting > 0 => {
+                let state = SubscriptionState::default();
+                state.ended.store(true, Ordering::Relaxed);
+                registry.states.insert(id, Arc::new(state));
+            }
+            // Reported again, or after the handle was ended
+            None => {}
+        }
+    }
+
+    /// End every subscription, as they do when the connection closes
+    pub(crate) fn end_all(&self) {
+        for (_, state) in self.lock().states.drain() {
+            state.ended.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Builder for a new subscription
+///
+/// Obtained via [`StdbConnection::subscription_builder`].
+pub struct SubscriptionBuilder<'a> {
+    connection: &'a StdbConnection,
+}
+
+impl<'a> SubscriptionBuilder<'a> {
+    pub(crate) fn new(connection: &'a StdbConnection) -> Self {
+        Self { connection }
+    }
+
+    /// Subscribe to the rows matching `queries`
+    ///
+    /// Returns immediately. Matching rows arrive as `InsertEvent`s, followed by a
+    /// [`SubscriptionAppliedEvent`] with the handle's id, or a
+    /// [`SubscriptionErrorEvent`] if the server rejects the queries.
+    ///
+    /// # Example
+    /// ```ignore
+    /// fn on_connected(
+    ///     mut events: MessageReader<StdbConnectedEvent>,
+    ///     stdb: Res<StdbConnection>,
+    ///     mut commands: Commands,
+    /// ) {
+    ///     for _ in events.read() {
+    ///         let handle = stdb
+    ///             .subscription_builder()
+    ///             .subscribe(["SELECT * FROM player", "SELECT * FROM lobby"]);
+    ///         commands.insert_resource(handle);
+    ///     }
+    /// }
+    /// ```
+    pub fn subscribe(self, queries: impl IntoQueries) -> SubscriptionHandle {
+        let queries = queries.into_queries();
+        self.connection.subscriptions.start();
+        let id = SubscriptionId(self.connection.backend.subscribe(&queries));
+
+        SubscriptionHandle {
+            id,
+            queries: queries.into(),
+            state: self.connection.subscriptions.register(id),
+            connection: self.connection.clone(),
+        }
+    }
+}
+
+/// A handle on a subscription
+///
+/// Cloning the handle does not create a new subscription; all clones refer to
+/// the same one. Dropping the handle does not unsubscribe.
+#[derive(Resource, Component, Clone)]
+pub struct SubscriptionHandle {
+    id: SubscriptionId,
+    queries: Arc<[String]>,
+    state: Arc<SubscriptionState>,
+    connection: StdbConnection,
+}
+
+impl SubscriptionHandle {
+    /// The id used by the events of this subscription
+    pub fn id(&self) -> SubscriptionId {
+        self.id
+    }
+
+    /// The queries of this subscription
+    pub fn queries(&self) -> &[String] {
+        &self.queries
+    }
+
+    /// Whether the subscription has been applied and not yet unsubscribed or failed
+    pub fn is_active(&self) -> bool {
+        self.state.applied.load(Ordering::Relaxed)
+            && !self.state.unsubscribed.load(Ordering::Relaxed)
+            && !self.state.ended.load(Ordering::Relaxed)
+    }
+
+    /// Whether the subscription has ended after an unsubscribe, an error or a disconnection
+    pub fn is_ended(&self) -> bool {
+        self.state.ended.load(Ordering::Relaxed)
+    }
+
+    /// End the subscription
+    ///
+    /// A [`SubscriptionEndedEvent`] is sent once the server has confirmed it.
+    /// Calling this more than once, or after the subscription failed, does nothing.
+    pub fn unsubscribe(&self) {
+        if self.is_ended() || self.state.unsubscribed.swap(true, Ordering::Relaxed) {
+            return;
+        }
+        self.connection.backend.unsubscribe(self.id.0);
+    }
+}
+
+impl std::fmt::Debug for SubscriptionHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SubscriptionHandle")
+            .field("id", &self.id)
+            .field("queries", &self.queries)
+            .field("state", &self.state)
+            .finish()
+    }
+}
+
+/// A subscription that is replaced without a gap in coverage
+///
+/// A replacement stays pending until it is applied; only then is the previous
+/// subscription ended, so rows covered by both are never removed in between.
+/// Both subscriptions are ended when this is dropped.
+#[derive(Debug, Default)]
+pub(crate) struct ReplaceableSubscription {
+    current: Option<SubscriptionHandle>,
+    pending: Option<SubscriptionHandle>,
+}
+
+impl ReplaceableSubscription {
+    /// Start replacing the current subscription with `handle`
+    ///
+    /// A replacement that is still pending is ended right away.
+    pub(crate) fn replace(&mut self, handle: SubscriptionHandle) {
+        if let Some(pending) = self.pending.replace(handle) {
+            pending.unsubscribe();
+        }
+    }
+
+    /// Promote the pending subscription once it has been applied
+    ///
+    /// Returns the replacement that took place, if any. Call this every frame.
+    pub(crate) fn update(&mut self) -> Option<Replacement> {
+        let pending = self.pending.as_ref()?;
+        if pending.is_ended() {
+            self.pending = None;
+            return None;
+        }
+        if !pending.is_active() {
+            return None;
+        }
+
+        let current = pending.id();
+        let previous = std::mem::replace(&mut self.current, self.pending.take());
+        if let Some(previous) = &previous {
+            previous.unsubscribe();
+        }
+        Some(Replacement { previous, current })
+    }
+
+    /// Whether a replacement is waiting to be applied
+    pub(crate) fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Whether neither a current nor a pending subscription exists
+    pub(crate) fn is_empty(&self) -> bool {
+        self.current.is_none() && self.pending.is_none()
+    }
+
+    /// The subscription currently in effect
+    pub(crate) fn current(&self) -> Option<&SubscriptionHandle> {
+        self.current.as_ref()
+    }
+}
+
+/// A replacement made by [`ReplaceableSubscription::update`]
+#[derive(Debug, Clone)]
+pub(crate) struct Replacement {
+    /// The replaced subscription, being unsubscribed
+    pub(crate) previous: Option<SubscriptionHandle>,
+    /// The id of the subscription now in effect
+    pub(crate) current: SubscriptionId,
+}
+
+impl Replacement {
+    pub(crate) fn event(&self) -> SubscriptionReplacedEvent {
+        SubscriptionReplacedEvent {
+            previous: self.previous.as_ref().map(SubscriptionHandle::id),
+            current: self.current,
+        }
+    }
+}
+
+impl Drop for ReplaceableSubscription {
+    fn drop(&mut self) {
+        for handle in self.current.iter().chain(self.pending.iter()) {
+            handle.unsubscribe();
+        }
+    }
+}
+
+/// Setup the subscription event channels and backend callbacks of a connection
+pub(crate) fn setup_subscription_events(app: &mut App, connection: &StdbConnection) {
+    add_subscription_listener::<SubscriptionAppliedEvent>(
+        app,
+        connection,
+        <dyn StdbBackend>::on_subscription_applied,
+        |registry, event| registry.mark_applied(event.id),
+    );
+    add_subscription_listener::<SubscriptionErrorEvent>(
+        app,
+        connection,
+        <dyn StdbBackend>::on_subscription_error,
+        |registry, event| registry.mark_ended(event.id),
+    );
+    add_subscription_listener::<SubscriptionEndedEvent>(
+        app,
+        connection,
+        <dyn StdbBackend>::on_subscription_ended,
+        |registry, event| registry.mark_ended(event.id),
+    );
+}
+
+fn add_subscription_listener<E: Message + DeserializeOwned>(
+    app: &mut App,
+    connection: &StdbConnection,
+    register: fn(&dyn StdbBackend, BackendCallback),
+    update: impl Fn(&SubscriptionRegistry, &E) + Send + Sync + 'static,
+) {
+    let (send, recv) = std::sync::mpsc::channel::<E>();
+    app.add_event_channel(recv);
+
+    let registry = connection.subscriptions.clone();
+    register(
+        connection.backend.as_ref(),
+        Box::new(move |data| match data.decode::<E>() {
+            Ok(event) => {
+                update(&registry, &event);
+                let _ = send.send(event);
+            }
+            Err(e) => {
+                error!(
+                    "Failed to deserialize {}: {}",
+                    std::any::type_name::<E>(),
+                    e
+                );
+            }
+        }),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_into_queries() {
+        assert_eq!("SELECT * FROM a".into_queries(), vec!["SELECT * FROM a"]);
+        assert_eq!(
+            ["SELECT * FROM a", "SELECT * FROM b"].into_queries(),
+            vec!["SELECT * FROM a", "SELECT * FROM b"]
+        );
+        let owned = vec![String::from("SELECT * FROM c")];
+        assert_eq!(owned.as_slice().into_queries(), owned.clone().into_queries());
+    }
+
+    #[test]
+    fn test_registry_tracks_lifecycle() {
+        let registry = SubscriptionRegistry::default();
+
+        registry.start();
+        let state = registry.register(SubscriptionId(0));
+        assert!(!state.applied.load(Ordering::Relaxed));
+        registry.mark_applied(SubscriptionId(0));
+        assert!(state.applied.load(Ordering::Relaxed));
+        registry.mark_ended(SubscriptionId(0));
+        assert!(state.ended.load(Ordering::Relaxed));
+        assert!(registry.lock().states.is_empty());
+
+        // An error reported before the handle exists still ends the subscription
+        registry.start();
+        registry.mark_ended(SubscriptionId(1));
+        assert!(registry.register(SubscriptionId(1)).ended.load(Ordering::Relaxed));
+        assert!(registry.lock().states.is_empty());
+    }
+
+    #[test]
+    fn test_registry_ignores_late_reports() {
+        let registry = SubscriptionRegistry::default();
+
+        // Duplicate or late reports for ids no longer tracked leave nothing behind
+        registry.mark_ended(SubscriptionId(0));
+        registry.mark_ended(SubscriptionId(0));
+        assert!(registry.lock().states.is_empty());
+
+        // Nor do reports for other ids while a subscription is starting
+        registry.start();
+        registry.mark_ended(SubscriptionId(5));
+        assert!(!registry.register(SubscriptionId(6)).ended.load(Ordering::Relaxed));
+        assert_eq!(registry.lock().states.len(), 1);
+    }
+
+    #[test]
+    fn test_registry_ends_everything_on_disconnect() {
+        let registry = SubscriptionRegistry::default();
+        registry.start();
+        let state = registry.register(SubscriptionId(0));
+        registry.mark_applied(SubscriptionId(0));
+
+        registry.end_all();
+        assert!(state.ended.load(Ordering::Relaxed));
+        assert!(registry.lock().states.is_empty());
+    }
+}
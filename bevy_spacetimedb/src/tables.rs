@@ -1,13 +1,21 @@
 use crate::{
-    bridge::SpacetimeDBBridge, AddEventChannelAppExtensions, DeleteEvent, InsertEvent,
-    InsertUpdateEvent, StdbPlugin, UpdateEvent,
+    backend::{BackendValue, TableCallbacks},
+    AddEventChannelAppExtensions, DeleteEvent, EventContext, InsertEvent, InsertUpdateEvent,
+    StdbConnection, StdbDecodeErrorEvent, StdbPlugin, TableEventKind, UpdateEvent,
 };
 use bevy::app::App;
-use wasm_bindgen::prelude::*;
+use bevy::ecs::message::Message;
+use bevy::log::{error, info, warn};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    mpsc::Sender,
+    Arc,
+};
 
 /// Trait for table rows that can be synchronized from SpacetimeDB
 ///
-/// Implement this for your table types (usually auto-generated).
+/// Implement this for your table types (usually auto-generated), or derive it
+/// with `#[derive(TableRow)]`.
 ///
 /// # Example
 /// ```ignore
@@ -29,6 +37,38 @@ use wasm_bindgen::prelude::*;
 pub trait TableRow: serde::de::DeserializeOwned + Send + Sync + Clone + 'static {
     /// The name of the table in the SpacetimeDB module
     const TABLE_NAME: &'static str;
+
+    /// The name of the primary key column, if the table has one
+    const PRIMARY_KEY: Option<&'static str> = None;
+
+    /// The names of columns with a unique constraint
+    const UNIQUE_COLUMNS: &'static [&'static str] = &[];
+
+    /// The names of indexed columns
+    const INDEXED_COLUMNS: &'static [&'static str] = &[];
+}
+
+/// Trait for table rows that have a primary key
+///
+/// Required by [`StdbPlugin::add_table_as_entities`] to track which entity
+/// mirrors which row across updates and deletes.
+///
+/// # Example
+/// ```ignore
+/// impl HasPrimaryKey for Player {
+///     type PrimaryKey = u64;
+///
+///     fn primary_key(&self) -> Self::PrimaryKey {
+///         self.id
+///     }
+/// }
+/// ```
+pub trait HasPrimaryKey: TableRow {
+    /// The type of the primary key column
+    type PrimaryKey: Eq + std::hash::Hash + Clone + std::fmt::Debug + Send + Sync + 'static;
+
+    /// Get the primary key of this row
+    fn primary_key(&self) -> Self::PrimaryKey;
 }
 
 /// Configuration for which table events to subscribe to
@@ -120,24 +160,138 @@ impl StdbPlugin {
     }
 }
 
+/// Function registering the event channels and backend callbacks of one table
+pub(crate) type TableSetupFn = Box<dyn Fn(&TableSetupContext, &TableEvents, &mut App) + Send + Sync>;
+
 /// Internal table configuration
 pub(crate) struct TableConfig {
     #[allow(dead_code)]
     pub table_name: String,
     pub events: TableEvents,
-    pub setup_fn: Box<dyn Fn(&SpacetimeDBBridge, u32, &TableEvents, &mut App) + Send + Sync>,
+    pub setup_fn: TableSetupFn,
+}
+
+/// State shared by the setup of every table
+pub(crate) struct TableSetupContext {
+    pub connection: StdbConnection,
+    pub decode_errors: DecodeErrorReporter,
+}
+
+/// Reports rows that fail to deserialize as `StdbDecodeErrorEvent`s
+///
+/// In strict mode the first failure of each connection also disconnects from
+/// the server.
+#[derive(Clone)]
+pub(crate) struct DecodeErrorReporter {
+    sender: Sender<StdbDecodeErrorEvent>,
+    strict: Option<StdbConnection>,
+    disconnected: Arc<AtomicBool>,
+}
+
+impl DecodeErrorReporter {
+    pub(crate) fn new(
+        sender: Sender<StdbDecodeErrorEvent>,
+        connection: &StdbConnection,
+        strict: bool,
+    ) -> Self {
+        Self {
+            sender,
+            strict: strict.then(|| connection.clone()),
+            disconnected: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Allow the next failure to disconnect again, e.g. after reconnecting
+    pub(crate) fn reset(&self) {
+        self.disconnected.store(false, Ordering::SeqCst);
+    }
+
+    /// Log and send a decode error for the raw event payload `data`
+    pub(crate) fn report(
+        &self,
+        table: &str,
+        event_kind: TableEventKind,
+        data: &BackendValue,
+        error: impl std::fmt::Display,
+    ) {
+        let event = StdbDecodeErrorEvent {
+            table: table.to_string(),
+            event_kind,
+            raw_json: data.to_json_string(),
+            error: error.to_string(),
+        };
+
+        error!(
+            "Failed to deserialize {:?} row for table {}: {} (payload: {})",
+            event.event_kind, event.table, event.error, event.raw_json
+        );
+
+        let _ = self.sender.send(event);
+
+        if let Some(connection) = &self.strict {
+            if !self.disconnected.swap(true, Ordering::SeqCst) {
+                error!("Strict decoding enabled, disconnecting from SpacetimeDB");
+                connection.disconnect();
+            }
+        }
+    }
+}
+
+/// Decode the `event` field of a table event into an [`EventContext`]
+///
+/// Never fails: an undecodable context is logged and reported as `Unknown`, so
+/// the row itself is still delivered.
+fn decode_event_context(data: &BackendValue) -> EventContext {
+    let event = data.field("event");
+    if event.is_null() {
+        return EventContext::Unknown;
+    }
+
+    let mut ctx = match event.decode::<EventContext>() {
+        Ok(ctx) => ctx,
+        Err(e) => {
+            warn!(
+                "Failed to deserialize event context: {} (payload: {})",
+                e,
+                event.to_json_string()
+            );
+            return EventContext::Unknown;
+        }
+    };
+
+    if let EventContext::Reducer(reducer) = &mut ctx {
+        reducer.args = event.decode_field("args").ok();
+    }
+    ctx
+}
+
+/// A change to one row of table `T`, sent to a single channel so changes keep their order
+#[derive(Message, Debug, Clone)]
+pub(crate) enum RowChange<T> {
+    Insert(T),
+    Update { old: T, new: T },
+    Delete(T),
 }
 
 /// Setup event subscriptions for a table
-fn setup_table_events<T: TableRow>(
-    bridge: &SpacetimeDBBridge,
-    connection_id: u32,
+pub(crate) fn setup_table_events<T: TableRow>(
+    context: &TableSetupContext,
     events: &TableEvents,
     app: &mut App,
 ) {
-    let mut insert_callback_id = None;
-    let mut update_callback_id = None;
-    let mut delete_callback_id = None;
+    setup_table_events_with_changes::<T>(context, events, app, None);
+}
+
+/// Setup event subscriptions for a table, also sending every change in arrival order to `changes`
+pub(crate) fn setup_table_events_with_changes<T: TableRow>(
+    context: &TableSetupContext,
+    events: &TableEvents,
+    app: &mut App,
+    changes: Option<Sender<RowChange<T>>>,
+) {
+    context.connection.one_off_queries.register::<T>(app);
+
+    let mut callbacks = TableCallbacks::default();
 
     // Setup InsertUpdate event channel if both insert and update are enabled
     let insert_update_send = if events.insert && events.update {
@@ -154,41 +308,33 @@ fn setup_table_events<T: TableRow>(
         app.add_event_channel(recv);
 
         let insert_update_send_clone = insert_update_send.clone();
-        let callback = Closure::wrap(Box::new(move |data: JsValue| {
-            if let Some(json) = data.as_string() {
-                match serde_json::from_str::<serde_json::Value>(&json) {
-                    Ok(value) => {
-                        if let Ok(row) = serde_json::from_value::<T>(value["row"].clone()) {
-                            let _ = send.send(InsertEvent { row: row.clone() });
-
-                            // Also send to InsertUpdateEvent if enabled
-                            if let Some(ref insert_update) = insert_update_send_clone {
-                                let _ = insert_update.send(InsertUpdateEvent { old: None, new: row });
-                            }
-                        } else {
-                            web_sys::console::error_1(
-                                &format!(
-                                    "Failed to deserialize row for table {}",
-                                    T::TABLE_NAME
-                                )
-                                .into(),
-                            );
-                        }
+        let changes = changes.clone();
+        let decode_errors = context.decode_errors.clone();
+        callbacks.on_insert = Some(Box::new(move |data: BackendValue| {
+            match data.decode_field::<T>("row") {
+                Ok(row) => {
+                    let ctx = decode_event_context(&data);
+
+                    if let Some(ref changes) = changes {
+                        let _ = changes.send(RowChange::Insert(row.clone()));
                     }
-                    Err(e) => {
-                        web_sys::console::error_1(
-                            &format!("Failed to parse JSON for insert event: {}", e).into(),
-                        );
+
+                    // Also send to InsertUpdateEvent if enabled
+                    if let Some(ref insert_update) = insert_update_send_clone {
+                        let _ = insert_update.send(InsertUpdateEvent {
+                            old: None,
+                            new: row.clone(),
+                            ctx: ctx.clone(),
+                        });
                     }
+
+                    let _ = send.send(InsertEvent { row, ctx });
+                }
+                Err(e) => {
+                    decode_errors.report(T::TABLE_NAME, TableEventKind::Insert, &data, e);
                 }
             }
-        }) as Box<dyn Fn(JsValue)>);
-
-        let id = bridge.register_callback(callback.as_ref().unchecked_ref());
-        // Keep the closure alive for the app lifetime. This is intentional - these callbacks
-        // are registered with the JS SDK and must live as long as the connection exists.
-        callback.forget();
-        insert_callback_id = Some(id);
+        }));
     }
 
     // Setup update events
@@ -197,44 +343,39 @@ fn setup_table_events<T: TableRow>(
         app.add_event_channel(recv);
 
         let insert_update_send_clone = insert_update_send;
-        let callback = Closure::wrap(Box::new(move |data: JsValue| {
-            if let Some(json) = data.as_string() {
-                match serde_json::from_str::<serde_json::Value>(&json) {
-                    Ok(value) => {
-                        let old_result = serde_json::from_value::<T>(value["oldRow"].clone());
-                        let new_result = serde_json::from_value::<T>(value["newRow"].clone());
-
-                        if let (Ok(old), Ok(new)) = (old_result, new_result) {
-                            let _ = send.send(UpdateEvent { old: old.clone(), new: new.clone() });
-
-                            // Also send to InsertUpdateEvent if enabled
-                            if let Some(ref insert_update) = insert_update_send_clone {
-                                let _ = insert_update.send(InsertUpdateEvent { old: Some(old), new });
-                            }
-                        } else {
-                            web_sys::console::error_1(
-                                &format!(
-                                    "Failed to deserialize rows for table {}",
-                                    T::TABLE_NAME
-                                )
-                                .into(),
-                            );
-                        }
+        let changes = changes.clone();
+        let decode_errors = context.decode_errors.clone();
+        callbacks.on_update = Some(Box::new(move |data: BackendValue| {
+            let old_result = data.decode_field::<T>("oldRow");
+            let new_result = data.decode_field::<T>("newRow");
+
+            match (old_result, new_result) {
+                (Ok(old), Ok(new)) => {
+                    let ctx = decode_event_context(&data);
+
+                    if let Some(ref changes) = changes {
+                        let _ = changes.send(RowChange::Update {
+                            old: old.clone(),
+                            new: new.clone(),
+                        });
                     }
-                    Err(e) => {
-                        web_sys::console::error_1(
-                            &format!("Failed to parse JSON for update event: {}", e).into(),
-                        );
+
+                    // Also send to InsertUpdateEvent if enabled
+                    if let Some(ref insert_update) = insert_update_send_clone {
+                        let _ = insert_update.send(InsertUpdateEvent {
+                            old: Some(old.clone()),
+                            new: new.clone(),
+                            ctx: ctx.clone(),
+                        });
                     }
+
+                    let _ = send.send(UpdateEvent { old, new, ctx });
+                }
+                (Err(e), _) | (_, Err(e)) => {
+                    decode_errors.report(T::TABLE_NAME, TableEventKind::Update, &data, e);
                 }
             }
-        }) as Box<dyn Fn(JsValue)>);
-
-        let id = bridge.register_callback(callback.as_ref().unchecked_ref());
-        // Keep the closure alive for the app lifetime. This is intentional - these callbacks
-        // are registered with the JS SDK and must live as long as the connection exists.
-        callback.forget();
-        update_callback_id = Some(id);
+        }));
     }
 
     // Setup delete events
@@ -242,55 +383,202 @@ fn setup_table_events<T: TableRow>(
         let (send, recv) = std::sync::mpsc::channel::<DeleteEvent<T>>();
         app.add_event_channel(recv);
 
-        let callback = Closure::wrap(Box::new(move |data: JsValue| {
-            if let Some(json) = data.as_string() {
-                match serde_json::from_str::<serde_json::Value>(&json) {
-                    Ok(value) => {
-                        if let Ok(row) = serde_json::from_value::<T>(value["row"].clone()) {
-                            let _ = send.send(DeleteEvent { row });
-                        } else {
-                            web_sys::console::error_1(
-                                &format!(
-                                    "Failed to deserialize row for table {}",
-                                    T::TABLE_NAME
-                                )
-                                .into(),
-                            );
-                        }
-                    }
-                    Err(e) => {
-                        web_sys::console::error_1(
-                            &format!("Failed to parse JSON for delete event: {}", e).into(),
-                        );
+        let decode_errors = context.decode_errors.clone();
+        callbacks.on_delete = Some(Box::new(move |data: BackendValue| {
+            match data.decode_field::<T>("row") {
+                Ok(row) => {
+                    let ctx = decode_event_context(&data);
+
+                    if let Some(ref changes) = changes {
+                        let _ = changes.send(RowChange::Delete(row.clone()));
                     }
+                    let _ = send.send(DeleteEvent { row, ctx });
+                }
+                Err(e) => {
+                    decode_errors.report(T::TABLE_NAME, TableEventKind::Delete, &data, e);
                 }
             }
-        }) as Box<dyn Fn(JsValue)>);
-
-        let id = bridge.register_callback(callback.as_ref().unchecked_ref());
-        // Keep the closure alive for the app lifetime. This is intentional - these callbacks
-        // are registered with the JS SDK and must live as long as the connection exists.
-        callback.forget();
-        delete_callback_id = Some(id);
+        }));
     }
 
     // Subscribe to the table with the registered callbacks
-    bridge.subscribe_table(
-        connection_id,
+    context
+        .connection
+        .backend
+        .subscribe_table(T::TABLE_NAME, callbacks);
+
+    info!(
+        "Subscribed to table {} (insert: {}, update: {}, delete: {})",
         T::TABLE_NAME,
-        insert_callback_id,
-        update_callback_id,
-        delete_callback_id,
+        events.insert,
+        events.update,
+        events.delete
     );
+}
 
-    web_sys::console::log_1(
-        &format!(
-            "Subscribed to table {} (insert: {}, update: {}, delete: {})",
-            T::TABLE_NAME,
-            events.insert,
-            events.update,
-            events.delete
-        )
-        .into(),
-    );
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[allow(dead_code)]
+    #[derive(Clone, serde::Deserialize, crate::TableRow)]
+    #[stdb(table = "players")]
+    struct Player {
+        #[stdb(primary_key)]
+        id: u64,
+        #[stdb(unique)]
+        name: String,
+        #[stdb(index)]
+        lobby_id: u32,
+    }
+
+    #[allow(dead_code)]
+    #[derive(Clone, serde::Deserialize, crate::TableRow)]
+    struct GameServer {
+        #[stdb(index)]
+        region: String,
+    }
+
+    #[crate::sum_type]
+    #[derive(Debug, Clone, PartialEq, serde::Deserialize)]
+    enum BodyType {
+        Star,
+        Planet(u32),
+        Moon { parent: u64 },
+    }
+
+    #[derive(Clone, serde::Deserialize, crate::TableRow)]
+    struct Body {
+        kind: BodyType,
+        parent: Option<u64>,
+    }
+
+    #[test]
+    fn test_sum_type_and_option_columns_decode() {
+        let rows: Vec<Body> = serde_json::from_value(serde_json::json!([
+            { "kind": { "tag": "Star" } },
+            { "kind": { "tag": "Planet", "value": 3 }, "parent": 1 },
+            { "kind": { "tag": "Moon", "value": { "parent": 2 } }, "parent": null },
+        ]))
+        .unwrap();
+
+        assert_eq!(rows[0].kind, BodyType::Star);
+        assert_eq!(rows[0].parent, None);
+        assert_eq!(rows[1].kind, BodyType::Planet(3));
+        assert_eq!(rows[1].parent, Some(1));
+        assert_eq!(rows[2].kind, BodyType::Moon { parent: 2 });
+        assert_eq!(rows[2].parent, None);
+    }
+
+    #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, crate::TableRow)]
+    #[stdb(table = "score")]
+    struct Score {
+        points: u32,
+    }
+
+    /// A row of the same table that doesn't match `Score`
+    #[derive(Clone, serde::Serialize, serde::Deserialize, crate::TableRow)]
+    #[stdb(table = "score")]
+    struct BadScore {
+        points: String,
+    }
+
+    fn bad_score() -> BadScore {
+        BadScore {
+            points: "many".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_decode_errors_are_reported() {
+        use crate::{StdbDisconnectedEvent, StdbPlugin, StdbTestApp};
+
+        let mut test = StdbTestApp::new(StdbPlugin::default().add_table::<Score>());
+        test.server().connect(None);
+        test.server().insert(bad_score());
+        test.server().insert(Score { points: 3 });
+        test.update();
+
+        let error = test.assert_message::<StdbDecodeErrorEvent>(|_| true);
+        assert_eq!(error.table, "score");
+        assert_eq!(error.event_kind, TableEventKind::Insert);
+        assert!(error.raw_json.contains("many"));
+        // Lenient decoding keeps the connection and the rows that do decode
+        test.assert_message::<InsertEvent<Score>>(|event| event.row.points == 3);
+        test.assert_no_message::<StdbDisconnectedEvent>();
+        assert!(test.server().backend().is_connected());
+    }
+
+    #[test]
+    fn test_strict_decoding_disconnects_once_per_connection() {
+        use crate::{StdbDisconnectedEvent, StdbPlugin, StdbTestApp};
+
+        let mut test = StdbTestApp::new(
+            StdbPlugin::default()
+                .add_table::<Score>()
+                .with_strict_decoding(true),
+        );
+        test.server().connect(None);
+        test.server().insert(bad_score());
+        test.server().insert(bad_score());
+        test.update();
+        assert_eq!(test.read_messages::<StdbDecodeErrorEvent>().len(), 2);
+        assert_eq!(test.read_messages::<StdbDisconnectedEvent>().len(), 1);
+        assert!(!test.server().backend().is_connected());
+
+        // The same connection is reused: a later failure disconnects again
+        test.server().connect(None);
+        test.server().insert(bad_score());
+        test.update();
+        assert_eq!(test.read_messages::<StdbDisconnectedEvent>().len(), 1);
+        assert!(!test.server().backend().is_connected());
+    }
+
+    #[test]
+    fn test_derive_table_row_metadata() {
+        assert_eq!(Player::TABLE_NAME, "players");
+        assert_eq!(Player::PRIMARY_KEY, Some("id"));
+        assert_eq!(Player::UNIQUE_COLUMNS, &["name"]);
+        assert_eq!(Player::INDEXED_COLUMNS, &["lobby_id"]);
+
+        let player = Player {
+            id: 7,
+            name: "Alice".to_string(),
+            lobby_id: 1,
+        };
+        assert_eq!(player.primary_key(), 7);
+    }
+
+    #[derive(Clone, serde::Deserialize, crate::TableRow)]
+    #[serde(rename_all = "camelCase")]
+    struct Ship {
+        #[stdb(primary_key)]
+        ship_id: u64,
+        #[serde(rename = "hull_pts")]
+        #[stdb(index)]
+        hull_points: u32,
+        owner_name: String,
+    }
+
+    #[test]
+    fn test_derive_table_row_follows_serde_renames() {
+        assert_eq!(Ship::PRIMARY_KEY, Some("shipId"));
+        assert_eq!(Ship::INDEXED_COLUMNS, &["hull_pts"]);
+        assert_eq!(crate::col!(Ship::owner_name).name(), "ownerName");
+
+        let ship: Ship = serde_json::from_value(serde_json::json!({
+            "shipId": 1, "hull_pts": 80, "ownerName": "Alice",
+        }))
+        .unwrap();
+        assert_eq!(ship.primary_key(), 1);
+        assert_eq!(ship.hull_points, 80);
+        assert_eq!(ship.owner_name, "Alice");
+    }
+
+    #[test]
+    fn test_derive_table_row_defaults_to_snake_case_name() {
+        assert_eq!(GameServer::TABLE_NAME, "game_server");
+        assert_eq!(GameServer::PRIMARY_KEY, None);
+        assert!(GameServer::UNIQUE_COLUMNS.is_empty());
+    }
 }
@@ -0,0 +1,345 @@
+//! Test harness for simulating server traffic
+//!
+//! [`StdbTestApp`] runs a Bevy app with `StdbPlugin` on a [`MockBackend`]. The
+//! test scripts what the server does through [`MockServer`], steps frames, and
+//! checks the messages the app received and the reducers it called.
+
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::ops::{Deref, DerefMut};
+
+use bevy::ecs::message::MessageCursor;
+use bevy::prelude::*;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    Identity, MockBackend, MockReducerCall, MockSubscription, Reducer, ReducerStatus,
+    RegisterableReducerEvent, StdbPlugin, SubscriptionId, TableRow,
+};
+
+/// The server side of a [`StdbTestApp`]
+///
+/// Everything happens immediately; the app sees the resulting messages on its
+/// next update.
+#[derive(Clone)]
+pub struct MockServer {
+    backend: MockBackend,
+}
+
+impl MockServer {
+    /// The backend the app is connected through
+    pub fn backend(&self) -> &MockBackend {
+        &self.backend
+    }
+
+    /// Accept the connection, with the given identity
+    pub fn connect(&self, identity: Option<Identity>) {
+        self.backend.accept_connection(identity);
+    }
+
+    /// Close the connection
+    pub fn disconnect(&self) {
+        self.backend.close_connection(None);
+    }
+
+    /// Close the connection because of an error
+    pub fn disconnect_with_error(&self, err: impl Into<String>) {
+        self.backend.close_connection(Some(err.into()));
+    }
+
+    /// Refuse the connection
+    pub fn fail_connection(&self, err: impl Into<String>) {
+        self.backend.fail_connection(err);
+    }
+
+    /// Insert a row
+    pub fn insert<T: TableRow + Serialize>(&self, row: T) {
+        self.backend.insert(&row);
+    }
+
+    /// Update a row from `old` to `new`
+    pub fn update<T: TableRow + Serialize>(&self, old: T, new: T) {
+        self.backend.update(&old, &new);
+    }
+
+    /// Delete a row
+    pub fn delete<T: TableRow + Serialize>(&self, row: T) {
+        self.backend.delete(&row);
+    }
+
+    /// The subscriptions that have not ended
+    pub fn subscriptions(&self) -> Vec<MockSubscription> {
+        self.backend.subscriptions()
+    }
+
+    /// Apply every pending subscription
+    pub fn apply_subscriptions(&self) {
+        self.backend.apply_subscriptions();
+    }
+
+    /// Apply a subscription, with `rows` as its initial rows
+    ///
+    /// The rows arrive as `InsertEvent`s with `ctx.is_subscription()` set.
+    pub fn apply_subscription_with_rows<T: TableRow + Serialize>(
+        &self,
+        id: SubscriptionId,
+        rows: &[T],
+    ) {
+        self.backend.apply_subscription_with_rows(id.0, rows);
+    }
+
+    /// Reject a subscription
+    pub fn fail_subscription(&self, id: SubscriptionId, err: impl Into<String>) {
+        self.backend.fail_subscription(id.0, err);
+    }
+
+    /// Make the next call to reducer `R` fail with `err`
+    pub fn fail_reducer<R: Reducer>(&self, err: impl Into<String>) {
+        self.backend.fail_next_reducer_call(R::NAME, err);
+    }
+
+    /// Report a committed run of the reducer of `T`, called by `caller` with `args`
+    pub fn run_reducer<T: RegisterableReducerEvent>(&self, args: impl Serialize, caller: Identity) {
+        self.backend
+            .run_reducer(T::REDUCER_NAME, &args, caller, ReducerStatus::Committed);
+    }
+
+    /// Every reducer call made so far
+    pub fn reducer_calls(&self) -> Vec<MockReducerCall> {
+        self.backend.reducer_calls()
+    }
+
+    /// The arguments of every call made so far to reducer `R`
+    ///
+    /// # Panics
+    ///
+    /// Panics if recorded arguments do not deserialize into `R::Args`.
+    pub fn calls_to<R: Reducer>(&self) -> Vec<R::Args>
+    where
+        R::Args: DeserializeOwned,
+    {
+        self.backend
+            .reducer_calls()
+            .iter()
+            .filter(|call| call.reducer == R::NAME)
+            .map(|call| {
+                call.args_as().unwrap_or_else(|| {
+                    panic!("arguments of {} do not match its Args: {}", R::NAME, call.args)
+                })
+            })
+            .collect()
+    }
+
+    /// Answer the oldest pending one-off query equal to `query` with `rows`
+    ///
+    /// Returns whether such a query was pending.
+    pub fn resolve_one_off_query<T: Serialize>(&self, query: &str, rows: &[T]) -> bool {
+        self.backend.resolve_one_off_query(query, rows)
+    }
+}
+
+/// A Bevy app wired to a [`MockServer`]
+///
+/// Dereferences to the [`App`], so systems and resources are added and frames
+/// stepped as usual.
+///
+/// # Example
+/// ```ignore
+/// let mut test = StdbTestApp::new(StdbPlugin::default().add_table::<Player>());
+/// test.add_systems(Update, greet_new_players);
+///
+/// test.server().connect(None);
+/// test.server().insert(Player { id: 1, name: "Alice".into() });
+/// test.update();
+///
+/// assert_eq!(test.server().calls_to::<SendGreeting>(), [(1,)]);
+/// test.assert_message::<InsertEvent<Player>>(|event| event.row.id == 1);
+/// ```
+pub struct StdbTestApp {
+    app: App,
+    server: MockServer,
+    cursors: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
+}
+
+impl StdbTestApp {
+    /// Build an app with the minimal plugins and `plugin` on a mock backend
+    pub fn new(plugin: StdbPlugin) -> Self {
+        Self::with_mock(plugin, MockBackend::new())
+    }
+
+    /// Build an app with the minimal plugins and `plugin` on `backend`
+    ///
+    /// Useful to drive the backend from elsewhere, e.g. a [`Replay`](crate::Replay).
+    pub fn with_mock(plugin: StdbPlugin, backend: MockBackend) -> Self {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugins(plugin.with_backend(backend.clone()));
+
+        Self {
+            app,
+            server: MockServer { backend },
+            cursors: HashMap::new(),
+        }
+    }
+
+    /// The server the app is connected to
+    pub fn server(&self) -> &MockServer {
+        &self.server
+    }
+
+    /// Run `frames` updates
+    pub fn run_frames(&mut self, frames: usize) -> &mut Self {
+        for _ in 0..frames {
+            self.app.update();
+        }
+        self
+    }
+
+    /// The messages of type `M` sent since the last call
+    ///
+    /// Bevy keeps messages for two updates: read them at least that often.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `M` is not registered with the app.
+    pub fn read_messages<M: Message + Clone>(&mut self) -> Vec<M> {
+        let messages = self.app.world().get_resource::<Messages<M>>().unwrap_or_else(|| {
+            panic!("{} is not registered with the app", std::any::type_name::<M>())
+        });
+        let cursor = self
+            .cursors
+            .entry(TypeId::of::<M>())
+            .or_insert_with(|| Box::new(messages.get_cursor()))
+            .downcast_mut::<MessageCursor<M>>()
+            .expect("cursor of another message type");
+        cursor.read(messages).cloned().collect()
+    }
+
+    /// Read the messages of type `M` and return the first one matching `predicate`
+    ///
+    /// # Panics
+    ///
+    /// Panics, listing the messages read, if none matches.
+    pub fn assert_message<M: Message + Clone + Debug>(
+        &mut self,
+        predicate: impl Fn(&M) -> bool,
+    ) -> M {
+        let messages = self.read_messages::<M>();
+        match messages.iter().find(|message| predicate(message)) {
+            Some(message) => message.clone(),
+            None => panic!(
+                "no matching {} among {:#?}",
+                std::any::type_name::<M>(),
+                messages
+            ),
+        }
+    }
+
+    /// Read the messages of type `M` and check there are none
+    ///
+    /// # Panics
+    ///
+    /// Panics, listing the messages read, if there are any.
+    pub fn assert_no_message<M: Message + Clone + Debug>(&mut self) {
+        let messages = self.read_messages::<M>();
+        assert!(
+            messages.is_empty(),
+            "unexpected {}: {:#?}",
+            std::any::type_name::<M>(),
+            messages
+        );
+    }
+}
+
+impl Deref for StdbTestApp {
+    type Target = App;
+
+    fn deref(&self) -> &App {
+        &self.app
+    }
+}
+
+impl DerefMut for StdbTestApp {
+    fn deref_mut(&mut self) -> &mut App {
+        &mut self.app
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        InsertEvent, StdbConnection, StdbDisconnectedEvent, StdbReducerErrorEvent,
+        SubscriptionErrorEvent,
+    };
+    use serde::Deserialize;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, crate::TableRow)]
+    struct Player {
+        #[stdb(primary_key)]
+        id: u64,
+        name: String,
+    }
+
+    crate::define_reducer!(Greet(player_id: u64));
+
+    fn greet_new_players(
+        mut inserts: MessageReader<InsertEvent<Player>>,
+        stdb: Res<StdbConnection>,
+    ) {
+        for event in inserts.read() {
+            stdb.reducers().call::<Greet>((event.row.id,)).unwrap();
+        }
+    }
+
+    fn test_app() -> StdbTestApp {
+        let mut test = StdbTestApp::new(StdbPlugin::default().add_table::<Player>());
+        test.add_systems(Update, greet_new_players);
+        test.server().connect(None);
+        test
+    }
+
+    #[test]
+    fn test_scripted_rows_drive_systems() {
+        let mut test = test_app();
+        test.server().insert(Player {
+            id: 7,
+            name: "Alice".to_string(),
+        });
+        test.update();
+
+        test.assert_message::<InsertEvent<Player>>(|event| event.row.name == "Alice");
+        assert_eq!(test.server().calls_to::<Greet>(), [(7,)]);
+
+        // Already read
+        test.assert_no_message::<InsertEvent<Player>>();
+    }
+
+    #[test]
+    fn test_failures_are_reported() {
+        let mut test = test_app();
+        test.server().fail_reducer::<Greet>("player left");
+        test.server().insert(Player {
+            id: 1,
+            name: "Bob".to_string(),
+        });
+        test.run_frames(2);
+
+        let error = test.assert_message::<StdbReducerErrorEvent>(|_| true);
+        assert_eq!((error.reducer.as_str(), error.err.as_str()), ("Greet", "player left"));
+
+        let handle = test
+            .world()
+            .resource::<StdbConnection>()
+            .subscription_builder()
+            .subscribe("SELECT * FROM nope");
+        test.server().fail_subscription(handle.id(), "no such table");
+        test.server().disconnect();
+        test.update();
+
+        test.assert_message::<SubscriptionErrorEvent>(|event| event.id == handle.id());
+        test.assert_message::<StdbDisconnectedEvent>(|event| event.err.is_none());
+        assert!(handle.is_ended());
+    }
+}
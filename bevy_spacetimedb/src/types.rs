@@ -0,0 +1,468 @@
+//! Rust counterparts of the SpacetimeDB special types
+//!
+//! The TypeScript SDK exposes these as class instances. The bridge normalizes them
+//! into plain objects before handing rows to Rust, and turns them back into class
+//! instances for reducer arguments:
+//!
+//! | Type           | Bridge encoding                                        |
+//! |----------------|--------------------------------------------------------|
+//! | `Identity`     | `{ __identity__: "<64 hex digits>" }`                  |
+//! | `ConnectionId` | `{ __connection_id__: "<decimal digits>" }`            |
+//! | `Timestamp`    | `{ __timestamp_micros_since_unix_epoch__: BigInt }`    |
+//! | `TimeDuration` | `{ __time_duration_micros__: BigInt }`                 |
+//! | `ScheduleAt`   | `{ tag: "Interval" \| "Time", value: ... }`            |
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+/// Error returned when parsing an [`Identity`] or [`ConnectionId`] from hex fails
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseHexError {
+    expected_digits: usize,
+    input: String,
+}
+
+impl fmt::Display for ParseHexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "expected {} hex digits, got {:?}",
+            self.expected_digits, self.input
+        )
+    }
+}
+
+impl std::error::Error for ParseHexError {}
+
+/// Decode a hex string (with optional `0x` prefix) into a fixed number of bytes
+fn parse_hex<const N: usize>(input: &str) -> Result<[u8; N], ParseHexError> {
+    let error = || ParseHexError {
+        expected_digits: N * 2,
+        input: input.to_string(),
+    };
+
+    let digits = input.strip_prefix("0x").unwrap_or(input);
+    if digits.len() != N * 2 || !digits.is_ascii() {
+        return Err(error());
+    }
+
+    let mut bytes = [0u8; N];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&digits[i * 2..i * 2 + 2], 16).map_err(|_| error())?;
+    }
+    Ok(bytes)
+}
+
+/// A SpacetimeDB identity, a 256-bit value identifying a user
+///
+/// Displayed and parsed as 64 hex digits, matching `Identity.toHexString()` in the
+/// TypeScript SDK.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(into = "IdentityRepr", try_from = "IdentityRepr")]
+pub struct Identity([u8; 32]);
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "Identity")]
+struct IdentityRepr {
+    #[serde(rename = "__identity__")]
+    hex: String,
+}
+
+impl From<Identity> for IdentityRepr {
+    fn from(identity: Identity) -> Self {
+        Self {
+            hex: identity.to_hex(),
+        }
+    }
+}
+
+impl TryFrom<IdentityRepr> for Identity {
+    type Error = ParseHexError;
+
+    fn try_from(repr: IdentityRepr) -> Result<Self, Self::Error> {
+        Identity::from_hex(&repr.hex)
+    }
+}
+
+impl Identity {
+    /// The all-zero identity
+    pub const ZERO: Self = Self([0; 32]);
+
+    /// Create an identity from its big-endian bytes
+    pub const fn from_be_byte_array(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    /// Get the big-endian bytes of this identity
+    pub const fn to_be_byte_array(&self) -> [u8; 32] {
+        self.0
+    }
+
+    /// Parse an identity from 64 hex digits, with or without a `0x` prefix
+    pub fn from_hex(hex: &str) -> Result<Self, ParseHexError> {
+        parse_hex(hex).map(Self)
+    }
+
+    /// Format this identity as 64 lowercase hex digits
+    pub fn to_hex(&self) -> String {
+        self.0.iter().map(|byte| format!("{byte:02x}")).collect()
+    }
+}
+
+impl fmt::Display for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_hex())
+    }
+}
+
+impl fmt::Debug for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Identity({})", self.to_hex())
+    }
+}
+
+impl FromStr for Identity {
+    type Err = ParseHexError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_hex(s)
+    }
+}
+
+/// A SpacetimeDB connection id, a 128-bit value identifying one client connection
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename = "ConnectionId")]
+pub struct ConnectionId {
+    #[serde(rename = "__connection_id__", with = "wide_u128")]
+    id: u128,
+}
+
+/// Serde shim for the value of a connection id
+///
+/// JSON numbers can't hold more than 64 bits, so values beyond that are written
+/// as decimal strings. Both forms are read back.
+mod wide_u128 {
+    use std::fmt;
+
+    use serde::de::{self, Visitor};
+    use serde::{Deserializer, Serializer};
+
+    pub(super) fn serialize<S: Serializer>(value: &u128, serializer: S) -> Result<S::Ok, S::Error> {
+        match u64::try_from(*value) {
+            Ok(value) => serializer.serialize_u64(value),
+            Err(_) => serializer.collect_str(value),
+        }
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<u128, D::Error> {
+        deserializer.deserialize_any(WideVisitor)
+    }
+
+    struct WideVisitor;
+
+    impl<'de> Visitor<'de> for WideVisitor {
+        type Value = u128;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("an unsigned 128-bit integer or its decimal digits")
+        }
+
+        fn visit_u64<E: de::Error>(self, value: u64) -> Result<u128, E> {
+            Ok(value.into())
+        }
+
+        fn visit_i64<E: de::Error>(self, value: i64) -> Result<u128, E> {
+            u128::try_from(value)
+                .map_err(|_| E::invalid_value(de::Unexpected::Signed(value), &self))
+        }
+
+        fn visit_u128<E: de::Error>(self, value: u128) -> Result<u128, E> {
+            Ok(value)
+        }
+
+        fn visit_str<E: de::Error>(self, value: &str) -> Result<u128, E> {
+            value
+                .parse()
+                .map_err(|_| E::invalid_value(de::Unexpected::Str(value), &self))
+        }
+    }
+}
+
+impl ConnectionId {
+    /// The all-zero connection id
+    pub const ZERO: Self = Self { id: 0 };
+
+    /// Create a connection id from its numeric value
+    pub const fn from_u128(id: u128) -> Self {
+        Self { id }
+    }
+
+    /// Get the numeric value of this connection id
+    pub const fn to_u128(&self) -> u128 {
+        self.id
+    }
+
+    /// Parse a connection id from 32 hex digits, with or without a `0x` prefix
+    pub fn from_hex(hex: &str) -> Result<Self, ParseHexError> {
+        parse_hex(hex).map(|bytes| Self::from_u128(u128::from_be_bytes(bytes)))
+    }
+
+    /// Format this connection id as 32 lowercase hex digits
+    pub fn to_hex(&self) -> String {
+        format!("{:032x}", self.id)
+    }
+}
+
+impl fmt::Display for ConnectionId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_hex())
+    }
+}
+
+impl fmt::Debug for ConnectionId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ConnectionId({})", self.to_hex())
+    }
+}
+
+impl FromStr for ConnectionId {
+    type Err = ParseHexError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_hex(s)
+    }
+}
+
+/// A point in time, in microseconds since the Unix epoch
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename = "Timestamp")]
+pub struct Timestamp {
+    #[serde(rename = "__timestamp_micros_since_unix_epoch__")]
+    micros_since_unix_epoch: i64,
+}
+
+impl Timestamp {
+    /// The Unix epoch
+    pub const UNIX_EPOCH: Self = Self {
+        micros_since_unix_epoch: 0,
+    };
+
+    /// Create a timestamp from microseconds since the Unix epoch
+    pub const fn from_micros_since_unix_epoch(micros: i64) -> Self {
+        Self {
+            micros_since_unix_epoch: micros,
+        }
+    }
+
+    /// Get the number of microseconds since the Unix epoch
+    pub const fn to_micros_since_unix_epoch(&self) -> i64 {
+        self.micros_since_unix_epoch
+    }
+
+    /// Convert to a [`SystemTime`]
+    pub fn to_system_time(&self) -> SystemTime {
+        let offset = Duration::from_micros(self.micros_since_unix_epoch.unsigned_abs());
+        if self.micros_since_unix_epoch >= 0 {
+            UNIX_EPOCH + offset
+        } else {
+            UNIX_EPOCH - offset
+        }
+    }
+
+    /// Convert from a [`SystemTime`], saturating at the range of `i64` microseconds
+    pub fn from_system_time(time: SystemTime) -> Self {
+        let micros = match time.duration_since(UNIX_EPOCH) {
+            Ok(after) => i64::try_from(after.as_micros()).unwrap_or(i64::MAX),
+            Err(before) => i64::try_from(before.duration().as_micros())
+                .map(|micros| -micros)
+                .unwrap_or(i64::MIN),
+        };
+        Self::from_micros_since_unix_epoch(micros)
+    }
+
+    /// The signed duration from `earlier` to `self`
+    pub fn duration_since(&self, earlier: Timestamp) -> TimeDuration {
+        TimeDuration::from_micros(
+            self.micros_since_unix_epoch
+                .saturating_sub(earlier.micros_since_unix_epoch),
+        )
+    }
+}
+
+impl From<SystemTime> for Timestamp {
+    fn from(time: SystemTime) -> Self {
+        Self::from_system_time(time)
+    }
+}
+
+impl From<Timestamp> for SystemTime {
+    fn from(timestamp: Timestamp) -> Self {
+        timestamp.to_system_time()
+    }
+}
+
+/// A signed span of time, in microseconds
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(rename = "TimeDuration")]
+pub struct TimeDuration {
+    #[serde(rename = "__time_duration_micros__")]
+    micros: i64,
+}
+
+impl TimeDuration {
+    /// A zero-length duration
+    pub const ZERO: Self = Self { micros: 0 };
+
+    /// Create a duration from a number of microseconds
+    pub const fn from_micros(micros: i64) -> Self {
+        Self { micros }
+    }
+
+    /// Get the number of microseconds in this duration
+    pub const fn to_micros(&self) -> i64 {
+        self.micros
+    }
+
+    /// Convert from a [`Duration`], saturating at `i64::MAX` microseconds
+    pub fn from_duration(duration: Duration) -> Self {
+        Self::from_micros(i64::try_from(duration.as_micros()).unwrap_or(i64::MAX))
+    }
+
+    /// Convert to a [`Duration`], or `None` if this duration is negative
+    pub fn to_duration(&self) -> Option<Duration> {
+        u64::try_from(self.micros).ok().map(Duration::from_micros)
+    }
+}
+
+impl From<Duration> for TimeDuration {
+    fn from(duration: Duration) -> Self {
+        Self::from_duration(duration)
+    }
+}
+
+impl TryFrom<TimeDuration> for Duration {
+    type Error = TimeDuration;
+
+    /// Fails with the original value if it is negative
+    fn try_from(duration: TimeDuration) -> Result<Self, Self::Error> {
+        duration.to_duration().ok_or(duration)
+    }
+}
+
+/// When a scheduled reducer should run
+#[crate::sum_type]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ScheduleAt {
+    /// Run repeatedly with this interval
+    Interval(TimeDuration),
+    /// Run once at this point in time
+    Time(Timestamp),
+}
+
+impl From<TimeDuration> for ScheduleAt {
+    fn from(interval: TimeDuration) -> Self {
+        Self::Interval(interval)
+    }
+}
+
+impl From<Timestamp> for ScheduleAt {
+    fn from(time: Timestamp) -> Self {
+        Self::Time(time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const HEX: &str = "c200f1e2d3c4b5a69788796a5b4c3d2e1f00112233445566778899aabbccddee";
+
+    #[test]
+    fn test_identity_hex_roundtrip() {
+        let identity = Identity::from_hex(HEX).unwrap();
+        assert_eq!(identity.to_hex(), HEX);
+        assert_eq!(identity.to_be_byte_array()[0], 0xc2);
+        assert_eq!(format!("0x{HEX}").parse::<Identity>().unwrap(), identity);
+
+        assert!(Identity::from_hex("c200").is_err());
+        assert!(Identity::from_hex(&HEX.replace('c', "g")).is_err());
+    }
+
+    #[test]
+    fn test_identity_serde_encoding() {
+        let identity = Identity::from_hex(HEX).unwrap();
+        let value = serde_json::to_value(identity).unwrap();
+        assert_eq!(value, json!({ "__identity__": HEX }));
+        assert_eq!(serde_json::from_value::<Identity>(value).unwrap(), identity);
+    }
+
+    #[test]
+    fn test_connection_id_hex_roundtrip() {
+        let id = ConnectionId::from_u128(0x0123_4567_89ab_cdef);
+        assert_eq!(id.to_hex(), "00000000000000000123456789abcdef");
+        assert_eq!(ConnectionId::from_hex(&id.to_hex()).unwrap(), id);
+        assert_eq!(
+            serde_json::to_value(id).unwrap(),
+            json!({ "__connection_id__": 0x0123_4567_89ab_cdef_u64 })
+        );
+    }
+
+    #[test]
+    fn test_wide_connection_id_json_roundtrip() {
+        let id = ConnectionId::from_u128(u128::MAX);
+        let value = serde_json::to_value(id).unwrap();
+        assert_eq!(value, json!({ "__connection_id__": u128::MAX.to_string() }));
+        assert_eq!(serde_json::from_value::<ConnectionId>(value).unwrap(), id);
+        assert!(
+            serde_json::from_value::<ConnectionId>(json!({ "__connection_id__": -1 })).is_err()
+        );
+    }
+
+    #[test]
+    fn test_timestamp_system_time_conversion() {
+        let timestamp = Timestamp::from_micros_since_unix_epoch(1_700_000_000_123_456);
+        let time = timestamp.to_system_time();
+        assert_eq!(
+            time.duration_since(UNIX_EPOCH).unwrap(),
+            Duration::from_micros(1_700_000_000_123_456)
+        );
+        assert_eq!(Timestamp::from(time), timestamp);
+
+        let before_epoch = Timestamp::from_micros_since_unix_epoch(-5);
+        assert_eq!(Timestamp::from(before_epoch.to_system_time()), before_epoch);
+    }
+
+    #[test]
+    fn test_time_duration_conversion() {
+        let duration = TimeDuration::from(Duration::from_millis(1500));
+        assert_eq!(duration.to_micros(), 1_500_000);
+        assert_eq!(
+            Duration::try_from(duration).unwrap(),
+            Duration::from_millis(1500)
+        );
+        assert!(TimeDuration::from_micros(-1).to_duration().is_none());
+
+        let earlier = Timestamp::from_micros_since_unix_epoch(1_000);
+        let later = Timestamp::from_micros_since_unix_epoch(4_000);
+        assert_eq!(later.duration_since(earlier), TimeDuration::from_micros(3_000));
+    }
+
+    #[test]
+    fn test_schedule_at_serde_encoding() {
+        let schedule = ScheduleAt::Interval(TimeDuration::from_micros(250));
+        let value = serde_json::to_value(schedule).unwrap();
+        assert_eq!(
+            value,
+            json!({ "tag": "Interval", "value": { "__time_duration_micros__": 250 } })
+        );
+        assert_eq!(serde_json::from_value::<ScheduleAt>(value).unwrap(), schedule);
+    }
+}
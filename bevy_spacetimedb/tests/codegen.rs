@@ -0,0 +1,56 @@
+//! Compiles the code `stdb-codegen` generates for `module_schema.json` against the crate
+//!
+//! Regenerate `generated/module.rs` from the workspace root with:
+//!   cargo run -p bevy_spacetimedb_codegen -- bevy_spacetimedb/tests/module_schema.json \
+//!     -o bevy_spacetimedb/tests/generated/module.rs
+
+use bevy_spacetimedb_wasm::{Reducer, TableRow};
+
+// Not every generated type is used by the tests
+#[allow(dead_code)]
+mod module {
+    include!("generated/module.rs");
+}
+
+use module::*;
+
+#[test]
+fn tables_carry_their_names_and_keys() {
+    assert_eq!(Players::TABLE_NAME, "players");
+    assert_eq!(Players::PRIMARY_KEY, Some("id"));
+    assert_eq!(Players::UNIQUE_COLUMNS, &["external_id"]);
+    assert_eq!(Players::INDEXED_COLUMNS, &["current_system"]);
+
+    assert_eq!(ArchivedPlayers::TABLE_NAME, "archived_players");
+    assert_eq!(StarSystem::TABLE_NAME, "star_systems");
+    assert_eq!(GalaxyTick::PRIMARY_KEY, Some("scheduled_id"));
+}
+
+#[test]
+fn reducers_carry_their_names() {
+    assert_eq!(PlayerRegister::NAME, "player_register");
+    assert_eq!(PlayerMoveSystem::NAME, "player_move_system");
+    assert_eq!(GenerateGalaxy::NAME, "generate_galaxy");
+
+    let args: <PlayerMoveSystem as Reducer>::Args = (3, Point { x: 1.0, y: 2.0 });
+    assert_eq!(
+        serde_json::to_value(&args).unwrap(),
+        serde_json::json!([3, { "x": 1.0, "y": 2.0 }])
+    );
+}
+
+#[test]
+fn rows_decode_from_the_bridge() {
+    let system: StarSystem = serde_json::from_value(serde_json::json!({
+        "id": 7,
+        "name": "Sol",
+        "body_type": { "tag": "Planet", "value": 3 },
+        "position": { "x": 0.5, "y": -1.0 },
+        "neighbours": [1, 2],
+        "type": "main",
+    }))
+    .unwrap();
+
+    assert_eq!(system.body_type, BodyTypes::Planet(3));
+    assert_eq!(system.r#type, "main");
+}
@@ -1,19 +1,26 @@
 //! Integration tests for bevy_spacetimedb_wasm
 //!
 //! REQUIREMENTS:
-//! 1. SpacetimeDB server MUST be running on localhost:3000
-//! 2. Node.js bridge setup (via node_setup.js)
+//! 1. A server MUST be running on localhost:3000: SpacetimeDB with the test
+//!    module, or the fake server (`./run_tests.sh --fake-server`)
+//! 2. Node.js bridge setup (via node_setup.mjs), which installs the bridge
+//!    from `js/` on top of the TypeScript SDK
 //!
 //! Setup:
-//!   cd tests && npm install
+//!   (cd js && npm install) && (cd tests && npm install)
 //!   spacetime start  # Start SpacetimeDB server
 //!
 //! Run with:
-//!   wasm-pack test --node -- --test integration_test
+//!   NODE_OPTIONS="--import $PWD/tests/node_setup.mjs" \
+//!     wasm-pack test --node -- --features global-bridge --test integration_test
 //!
 //! These are REAL integration tests - they connect to an actual SpacetimeDB server.
 //! Tests will FAIL if the server is not running.
 
+#![cfg(target_arch = "wasm32")]
+
+use std::fmt::Debug;
+
 use wasm_bindgen_test::*;
 use bevy_spacetimedb_wasm::*;
 use bevy::prelude::*;
@@ -22,6 +29,12 @@ use wasm_bindgen_futures::JsFuture;
 
 wasm_bindgen_test_configure!(run_in_node_experimental);
 
+const URI: &str = "http://127.0.0.1:3000";
+const MODULE_NAME: &str = "bevy_spacetimedb_test_module";
+
+/// How many frames to wait for a message before failing
+const MAX_FRAMES: usize = 500;
+
 // ============================================================================
 // Test Data Structures
 // ============================================================================
@@ -36,6 +49,117 @@ impl TableRow for TestPlayer {
     const TABLE_NAME: &'static str = "test_player";
 }
 
+pub struct CreatePlayerReducer;
+
+impl Reducer for CreatePlayerReducer {
+    const NAME: &'static str = "create_player";
+    type Args = (u32, String);
+}
+
+pub struct DeletePlayerReducer;
+
+impl Reducer for DeletePlayerReducer {
+    const NAME: &'static str = "delete_player";
+    type Args = (u32,);
+}
+
+#[derive(Debug, Clone, RegisterReducerEvent)]
+pub struct CreatePlayer {
+    pub event: ReducerEvent,
+    pub id: u32,
+    pub name: String,
+}
+
+// ============================================================================
+// Test Helpers
+// ============================================================================
+
+/// The messages of type `M` received so far
+#[derive(Resource)]
+struct Received<M>(Vec<M>);
+
+fn record<M: Message + Clone>(mut messages: MessageReader<M>, mut received: ResMut<Received<M>>) {
+    received.0.extend(messages.read().cloned());
+}
+
+/// Keep every message of type `M`, for `wait_for`
+fn keep<M: Message + Clone>(app: &mut App) {
+    app.insert_resource(Received::<M>(Vec::new()))
+        .add_systems(Last, record::<M>);
+}
+
+/// An app connected to the server through the bridge, with `test_player` registered
+fn test_app() -> App {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins).add_plugins(
+        StdbPlugin::default()
+            .with_uri(URI)
+            .with_module_name(MODULE_NAME)
+            .add_table::<TestPlayer>()
+            .add_reducer::<CreatePlayer>(),
+    );
+    keep::<StdbConnectedEvent>(&mut app);
+    keep::<StdbConnectionErrorEvent>(&mut app);
+    keep::<SubscriptionAppliedEvent>(&mut app);
+    keep::<SubscriptionErrorEvent>(&mut app);
+    keep::<InsertEvent<TestPlayer>>(&mut app);
+    keep::<DeleteEvent<TestPlayer>>(&mut app);
+    keep::<StdbReducerErrorEvent>(&mut app);
+    keep::<ReducerResultEvent<CreatePlayer>>(&mut app);
+    app
+}
+
+/// Wait for `ms` milliseconds, letting the bridge's callbacks run
+async fn sleep(ms: i32) {
+    let promise = js_sys::Promise::new(&mut |resolve, _| {
+        let set_timeout: js_sys::Function =
+            js_sys::Reflect::get(&js_sys::global(), &"setTimeout".into())
+                .expect("no setTimeout")
+                .into();
+        set_timeout
+            .call2(&JsValue::NULL, &resolve, &ms.into())
+            .expect("setTimeout failed");
+    });
+    JsFuture::from(promise).await.expect("sleep failed");
+}
+
+/// Update `app` until it received a message of type `M` matching `predicate`
+///
+/// # Panics
+///
+/// Panics, listing the messages received, if none arrives in time.
+async fn wait_for<M: Message + Clone + Debug>(app: &mut App, predicate: impl Fn(&M) -> bool) -> M {
+    for _ in 0..MAX_FRAMES {
+        app.update();
+        let received = &app.world().resource::<Received<M>>().0;
+        if let Some(message) = received.iter().find(|message| predicate(message)) {
+            return message.clone();
+        }
+        sleep(10).await;
+    }
+    panic!(
+        "no matching {} among {:#?}",
+        std::any::type_name::<M>(),
+        app.world().resource::<Received<M>>().0
+    );
+}
+
+/// Connect `app` and subscribe to `test_player`
+async fn connect_and_subscribe(app: &mut App) {
+    wait_for::<StdbConnectedEvent>(app, |_| true).await;
+    let handle = app
+        .world()
+        .resource::<StdbConnection>()
+        .subscription_builder()
+        .subscribe("SELECT * FROM test_player");
+    let id = handle.id();
+    wait_for::<SubscriptionAppliedEvent>(app, |applied| applied.id == id).await;
+}
+
+fn reducers(app: &App) -> ReducerCaller<'_> {
+    app.world().resource::<StdbConnection>().reducers()
+}
+
 // ============================================================================
 // Integration Tests - ALL tests require SpacetimeDB server on localhost:3000
 // ============================================================================
@@ -48,11 +172,7 @@ async fn test_real_connection() {
 
     let bridge = get_bridge();
 
-    let connection_id = bridge.create_connection(
-        "http://localhost:3000",
-        "bevy_spacetimedb_test_module",
-        None
-    );
+    let connection_id = bridge.create_connection(URI, MODULE_NAME, None);
 
     // Connect - this WILL fail if server is not running
     JsFuture::from(bridge.connect(connection_id))
@@ -69,3 +189,62 @@ async fn test_real_connection() {
     web_sys::console::log_1(&"✓ Disconnected successfully".into());
     web_sys::console::log_1(&"========================================".into());
 }
+
+#[wasm_bindgen_test]
+async fn test_subscription_applied() {
+    let mut app = test_app();
+    connect_and_subscribe(&mut app).await;
+
+    let errors = &app.world().resource::<Received<SubscriptionErrorEvent>>().0;
+    assert!(
+        errors.is_empty(),
+        "unexpected subscription errors: {:#?}",
+        errors
+    );
+}
+
+#[wasm_bindgen_test]
+async fn test_row_events() {
+    let mut app = test_app();
+    connect_and_subscribe(&mut app).await;
+
+    reducers(&app)
+        .call::<CreatePlayerReducer>((101, "Alice".to_string()))
+        .unwrap();
+    let inserted = wait_for::<InsertEvent<TestPlayer>>(&mut app, |e| e.row.id == 101).await;
+    assert_eq!(inserted.row.name, "Alice");
+
+    let run = wait_for::<ReducerResultEvent<CreatePlayer>>(&mut app, |e| e.result.id == 101).await;
+    assert!(run.result.event.is_committed());
+    assert_eq!(run.result.name, "Alice");
+
+    reducers(&app).call::<DeletePlayerReducer>((101,)).unwrap();
+    let deleted = wait_for::<DeleteEvent<TestPlayer>>(&mut app, |e| e.row.id == 101).await;
+    assert_eq!(deleted.row.name, "Alice");
+}
+
+#[wasm_bindgen_test]
+async fn test_reducer_failure() {
+    let mut app = test_app();
+    connect_and_subscribe(&mut app).await;
+
+    reducers(&app)
+        .call::<CreatePlayerReducer>((102, "Bob".to_string()))
+        .unwrap();
+    wait_for::<InsertEvent<TestPlayer>>(&mut app, |e| e.row.id == 102).await;
+
+    // The id is taken: the reducer fails and nothing is inserted
+    reducers(&app)
+        .call::<CreatePlayerReducer>((102, "Carol".to_string()))
+        .unwrap();
+    let error = wait_for::<StdbReducerErrorEvent>(&mut app, |e| e.reducer == "create_player").await;
+    assert!(!error.err.is_empty());
+    let inserts = &app
+        .world()
+        .resource::<Received<InsertEvent<TestPlayer>>>()
+        .0;
+    assert!(inserts.iter().all(|e| e.row.name != "Carol"));
+
+    reducers(&app).call::<DeletePlayerReducer>((102,)).unwrap();
+    wait_for::<DeleteEvent<TestPlayer>>(&mut app, |e| e.row.id == 102).await;
+}
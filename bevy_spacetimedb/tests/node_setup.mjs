@@ -0,0 +1,25 @@
+// Node.js test setup for bevy_spacetimedb_wasm
+//
+// Installs the bridge from `js/spacetimedb-bridge.js` as the global bridge, so
+// the tests drive the TypeScript SDK the way a page does. Node.js has no
+// WebSocket of its own before v22, `ws` stands in for it.
+
+import WebSocket from 'ws';
+
+globalThis.WebSocket ??= WebSocket;
+
+// Imported once WebSocket is in place
+const { SpacetimeDBBridge } = await import('../js/spacetimedb-bridge.js');
+
+// Set up global bridge for wasm-bindgen tests
+globalThis.__SPACETIMEDB_BRIDGE__ = new SpacetimeDBBridge();
+
+// Mock browser globals for WASM
+globalThis.window = {
+    __SPACETIMEDB_BRIDGE__: globalThis.__SPACETIMEDB_BRIDGE__,
+    setTimeout: setTimeout,
+    clearTimeout: clearTimeout,
+    performance: globalThis.performance
+};
+
+console.log('Node.js test environment ready');
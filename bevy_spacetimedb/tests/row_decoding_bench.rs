@@ -0,0 +1,101 @@
+//! Benchmark comparing the two ways of getting table rows from JS into Rust
+//!
+//! - JSON: the bridge calls `JSON.stringify` on each event, Rust parses it into a
+//!   `serde_json::Value` and then into the row type (the previous transport).
+//! - Direct: the bridge passes the JS object and Rust deserializes it straight
+//!   into the row type with `serde_wasm_bindgen` (the current transport).
+//!
+//! Does not need a SpacetimeDB server. Run with:
+//!   wasm-pack test --node --release -- --features global-bridge --test row_decoding_bench
+
+#![cfg(target_arch = "wasm32")]
+
+use serde::Deserialize;
+use wasm_bindgen::JsValue;
+use wasm_bindgen_test::*;
+
+wasm_bindgen_test_configure!(run_in_node_experimental);
+
+const ROW_COUNT: usize = 10_000;
+const ITERATIONS: usize = 5;
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+struct BenchRow {
+    id: u32,
+    name: String,
+    x: f32,
+    y: f32,
+    z: f32,
+    owner: String,
+    tags: Vec<String>,
+}
+
+/// Build table event payloads as plain JS objects, the way the bridge hands them over
+fn make_events() -> Vec<JsValue> {
+    (0..ROW_COUNT)
+        .map(|i| {
+            let json = format!(
+                r#"{{"row":{{"id":{i},"name":"system-{i}","x":{i}.5,"y":-{i}.25,"z":0.125,"owner":"player-{owner}","tags":["core","rim","{i}"]}},"reducerEvent":null}}"#,
+                owner = i % 64,
+            );
+            js_sys::JSON::parse(&json).expect("valid JSON")
+        })
+        .collect()
+}
+
+fn decode_via_json(events: &[JsValue]) -> Vec<BenchRow> {
+    events
+        .iter()
+        .map(|event| {
+            let json: String = js_sys::JSON::stringify(event).unwrap().into();
+            let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+            serde_json::from_value(value["row"].clone()).unwrap()
+        })
+        .collect()
+}
+
+fn decode_direct(events: &[JsValue]) -> Vec<BenchRow> {
+    events
+        .iter()
+        .map(|event| {
+            let row = js_sys::Reflect::get(event, &JsValue::from_str("row")).unwrap();
+            serde_wasm_bindgen::from_value(row).unwrap()
+        })
+        .collect()
+}
+
+/// Run `decode` `ITERATIONS` times and return the best time in milliseconds
+fn time_best(events: &[JsValue], decode: fn(&[JsValue]) -> Vec<BenchRow>) -> f64 {
+    (0..ITERATIONS)
+        .map(|_| {
+            let start = js_sys::Date::now();
+            let rows = decode(events);
+            let elapsed = js_sys::Date::now() - start;
+            assert_eq!(rows.len(), ROW_COUNT);
+            elapsed
+        })
+        .fold(f64::INFINITY, f64::min)
+}
+
+#[wasm_bindgen_test]
+fn bench_row_decoding() {
+    let events = make_events();
+
+    // Both paths must produce identical rows before timing means anything
+    assert_eq!(decode_via_json(&events), decode_direct(&events));
+
+    let json_ms = time_best(&events, decode_via_json);
+    let direct_ms = time_best(&events, decode_direct);
+
+    web_sys::console::log_1(
+        &format!(
+            "Decoding {} rows (best of {}): JSON round-trip {:.1} ms, direct {:.1} ms ({:.2}x)",
+            ROW_COUNT,
+            ITERATIONS,
+            json_ms,
+            direct_ms,
+            json_ms / direct_ms.max(f64::EPSILON),
+        )
+        .into(),
+    );
+}
@@ -0,0 +1,173 @@
+//! # bevy_spacetimedb_codegen
+//!
+//! Generates the `TableRow` structs, sum types and `Reducer` impls mirroring a
+//! SpacetimeDB module for `bevy_spacetimedb_wasm`, from the module schema printed
+//! by `spacetime describe --json <module>`.
+//!
+//! For each table, a struct named after its row type (or after the table, when
+//! several tables share a row type) with the table name and primary key, unique
+//! and indexed columns. For each other named type, a struct or a `#[sum_type]`
+//! enum. For each reducer clients can call, a unit struct implementing `Reducer`
+//! with the reducer's name and argument tuple.
+//!
+//! SpacetimeDB's special types map to the crate's `Identity`, `ConnectionId`,
+//! `Timestamp`, `TimeDuration` and `ScheduleAt`, and byte arrays to `Bytes`.
+//! 256-bit integers, anonymous product and sum types, and multi-column primary
+//! keys are not supported.
+//!
+//! The generated code uses `serde`, which the crate using it must depend on.
+//!
+//! Run `stdb-codegen --help` for the binary, or generate from a build script:
+//!
+//! ```no_run
+//! // build.rs
+//! let out = std::path::Path::new(&std::env::var("OUT_DIR").unwrap()).join("stdb.rs");
+//! bevy_spacetimedb_codegen::generate_file("module_schema.json", out).unwrap();
+//! println!("cargo:rerun-if-changed=module_schema.json");
+//!
+//! // src/stdb.rs
+//! // include!(concat!(env!("OUT_DIR"), "/stdb.rs"));
+//! ```
+
+use std::fmt;
+use std::path::Path;
+
+mod rust;
+mod schema;
+
+pub use schema::ModuleSchema;
+
+/// An error reading a schema or generating code from it
+#[derive(Debug)]
+pub struct CodegenError(String);
+
+impl CodegenError {
+    pub(crate) fn new(message: impl Into<String>) -> Self {
+        Self(message.into())
+    }
+}
+
+impl fmt::Display for CodegenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for CodegenError {}
+
+impl From<serde_json::Error> for CodegenError {
+    fn from(error: serde_json::Error) -> Self {
+        Self(format!("invalid schema JSON: {}", error))
+    }
+}
+
+impl From<std::io::Error> for CodegenError {
+    fn from(error: std::io::Error) -> Self {
+        Self(error.to_string())
+    }
+}
+
+/// Generate the Rust code mirroring a module
+pub fn generate(schema: &ModuleSchema) -> Result<String, CodegenError> {
+    rust::generate(schema)
+}
+
+/// Generate the Rust code mirroring the module whose schema is in `schema_path`
+/// into `out_path`, e.g. from a build script
+pub fn generate_file(
+    schema_path: impl AsRef<Path>,
+    out_path: impl AsRef<Path>,
+) -> Result<(), CodegenError> {
+    let schema_path = schema_path.as_ref();
+    let json = std::fs::read_to_string(schema_path)
+        .map_err(|e| CodegenError::new(format!("{}: {}", schema_path.display(), e)))?;
+    let code = generate(&ModuleSchema::from_json(&json)?)?;
+    std::fs::write(out_path, code)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE: &str = include_str!("../../bevy_spacetimedb/tests/module_schema.json");
+    const GENERATED: &str = include_str!("../../bevy_spacetimedb/tests/generated/module.rs");
+
+    fn generate_json(json: serde_json::Value) -> Result<String, CodegenError> {
+        generate(&ModuleSchema::from_json(&json.to_string())?)
+    }
+
+    fn module(types: serde_json::Value, tables: serde_json::Value) -> serde_json::Value {
+        serde_json::json!({
+            "typespace": { "types": types },
+            "tables": tables,
+            "reducers": [],
+            "types": [],
+        })
+    }
+
+    #[test]
+    fn checked_in_output_is_up_to_date() {
+        let code = generate(&ModuleSchema::from_json(FIXTURE).unwrap()).unwrap();
+        assert!(
+            code == GENERATED,
+            "bevy_spacetimedb/tests/generated/module.rs is stale; regenerate it"
+        );
+    }
+
+    #[test]
+    fn maps_keys_and_skips_lifecycle_reducers() {
+        let code = generate(&ModuleSchema::from_json(FIXTURE).unwrap()).unwrap();
+
+        assert!(code.contains("#[stdb(table = \"players\")]\npub struct Players {"));
+        assert!(
+            code.contains(
+                "    #[stdb(primary_key)]\n    pub id: ::bevy_spacetimedb_wasm::Identity,"
+            )
+        );
+        assert!(code.contains("    #[stdb(unique)]\n    pub external_id: u64,"));
+        assert!(code.contains(
+            "    const NAME: &'static str = \"player_move_system\";\n    type Args = (u32, Point);"
+        ));
+        assert!(!code.contains("on_connect"));
+    }
+
+    #[test]
+    fn reducers_colliding_with_types_are_suffixed() {
+        let mut json = module(
+            serde_json::json!([{ "Product": { "elements": [
+                { "name": { "some": "id" }, "algebraic_type": { "U32": [] } },
+            ] } }]),
+            serde_json::json!([{ "name": "spawn", "product_type_ref": 0, "primary_key": [0] }]),
+        );
+        json["reducers"] = serde_json::json!([
+            { "name": "spawn", "params": { "elements": [] }, "lifecycle": { "none": [] } },
+        ]);
+
+        let code = generate_json(json).unwrap();
+        assert!(code.contains("pub struct Spawn {"));
+        assert!(code.contains("pub struct SpawnReducer;"));
+    }
+
+    #[test]
+    fn rejects_unsupported_schemas() {
+        let row = serde_json::json!([{ "Product": { "elements": [
+            { "name": { "some": "a" }, "algebraic_type": { "U32": [] } },
+            { "name": { "some": "b" }, "algebraic_type": { "U256": [] } },
+        ] } }]);
+
+        let composite = module(
+            row.clone(),
+            serde_json::json!([{ "name": "pairs", "product_type_ref": 0, "primary_key": [0, 1] }]),
+        );
+        let error = generate_json(composite).unwrap_err().to_string();
+        assert!(error.contains("multi-column primary key"), "{}", error);
+
+        let wide = module(
+            row,
+            serde_json::json!([{ "name": "pairs", "product_type_ref": 0, "primary_key": [0] }]),
+        );
+        let error = generate_json(wide).unwrap_err().to_string();
+        assert!(error.contains("U256"), "{}", error);
+    }
+}
@@ -0,0 +1,69 @@
+//! `stdb-codegen`: generate Rust code from a module schema
+
+use std::process::ExitCode;
+
+use bevy_spacetimedb_codegen::{ModuleSchema, generate};
+
+const USAGE: &str = "\
+Usage: stdb-codegen <schema.json> [--out <file>]
+
+Generates bevy_spacetimedb_wasm tables and reducers from the output of
+`spacetime describe --json <module>`.
+
+Options:
+  -o, --out <file>  File to write the code to [default: standard output]
+  -h, --help        Print this help";
+
+fn main() -> ExitCode {
+    let mut schema_path = None;
+    let mut out = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let result = match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return ExitCode::SUCCESS;
+            }
+            "-o" | "--out" => args
+                .next()
+                .map(|path| out = Some(path))
+                .ok_or_else(|| "--out needs a file".to_string()),
+            path if schema_path.is_none() && !path.starts_with('-') => {
+                schema_path = Some(path.to_string());
+                Ok(())
+            }
+            other => Err(format!("unknown argument: {}", other)),
+        };
+        if let Err(e) = result {
+            eprintln!("{}\n\n{}", e, USAGE);
+            return ExitCode::FAILURE;
+        }
+    }
+
+    let Some(schema_path) = schema_path else {
+        eprintln!("missing schema file\n\n{}", USAGE);
+        return ExitCode::FAILURE;
+    };
+    match run(&schema_path, out.as_deref()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(schema_path: &str, out: Option<&str>) -> Result<(), String> {
+    let json =
+        std::fs::read_to_string(schema_path).map_err(|e| format!("{}: {}", schema_path, e))?;
+    let schema = ModuleSchema::from_json(&json).map_err(|e| format!("{}: {}", schema_path, e))?;
+    let code = generate(&schema).map_err(|e| e.to_string())?;
+    match out {
+        Some(path) => std::fs::write(path, code).map_err(|e| format!("{}: {}", path, e)),
+        None => {
+            print!("{}", code);
+            Ok(())
+        }
+    }
+}
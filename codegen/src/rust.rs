@@ -0,0 +1,313 @@
+//! Rendering a module schema as Rust code for `bevy_spacetimedb_wasm`
+
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Write;
+
+use heck::ToUpperCamelCase;
+
+use crate::CodegenError;
+use crate::schema::{AlgebraicType, Element, ModuleSchema, TableDef};
+
+const CRATE: &str = "::bevy_spacetimedb_wasm";
+
+/// Named types the crate provides, by their single field
+const SPECIAL_PRODUCTS: &[(&str, &str)] = &[
+    ("__identity__", "Identity"),
+    ("__connection_id__", "ConnectionId"),
+    ("__timestamp_micros_since_unix_epoch__", "Timestamp"),
+    ("__time_duration_micros__", "TimeDuration"),
+];
+
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "dyn", "else", "enum", "extern", "false",
+    "fn", "for", "gen", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+    "ref", "return", "static", "struct", "trait", "true", "type", "unsafe", "use", "where",
+    "while", "abstract", "become", "box", "do", "final", "macro", "override", "priv", "try",
+    "typeof", "unsized", "virtual", "yield",
+];
+
+/// A field or parameter name as a Rust identifier
+fn ident(name: &str) -> Result<String, CodegenError> {
+    let valid = name
+        .chars()
+        .next()
+        .is_some_and(|c| c == '_' || c.is_alphabetic())
+        && name.chars().all(|c| c == '_' || c.is_alphanumeric());
+    if !valid || matches!(name, "self" | "Self" | "super" | "crate" | "_") {
+        Err(CodegenError::new(format!(
+            "`{}` is not a valid Rust identifier",
+            name
+        )))
+    } else if KEYWORDS.contains(&name) {
+        Ok(format!("r#{}", name))
+    } else {
+        Ok(name.to_string())
+    }
+}
+
+struct Generator<'a> {
+    schema: &'a ModuleSchema,
+    /// Rust names of the named types, by typespace entry
+    names: BTreeMap<u32, String>,
+    out: String,
+}
+
+/// Render `schema` as Rust code
+pub(crate) fn generate(schema: &ModuleSchema) -> Result<String, CodegenError> {
+    let mut generator = Generator {
+        schema,
+        names: schema
+            .type_names
+            .iter()
+            .map(|(index, name)| (*index, name.to_upper_camel_case()))
+            .collect(),
+        out: String::new(),
+    };
+    generator.module()?;
+    Ok(generator.out)
+}
+
+impl<'a> Generator<'a> {
+    fn module(&mut self) -> Result<(), CodegenError> {
+        let schema = self.schema;
+        self.out.push_str(
+            "// Generated by bevy_spacetimedb_codegen from a SpacetimeDB module schema.\n\
+             // Do not edit: regenerate it when the module changes.\n",
+        );
+
+        // A row type backing several tables is generated once per table, named after it
+        let mut tables_by_row = BTreeMap::<u32, Vec<&TableDef>>::new();
+        for table in &schema.tables {
+            tables_by_row.entry(table.row).or_default().push(table);
+        }
+        let mut generated = HashSet::new();
+        let mut struct_names = self.names.values().cloned().collect::<HashSet<_>>();
+
+        for table in &schema.tables {
+            let shared = tables_by_row[&table.row].len() > 1;
+            let name = match self.names.get(&table.row) {
+                Some(name) if !shared => name.clone(),
+                _ => table.name.to_upper_camel_case(),
+            };
+            if !shared {
+                generated.insert(table.row);
+            }
+            self.table(&name, table)?;
+            struct_names.insert(name);
+        }
+
+        for (index, name) in self.names.clone() {
+            if !generated.contains(&index) {
+                self.named_type(index, &name)?;
+            }
+        }
+
+        for reducer in schema.reducers.iter().filter(|reducer| !reducer.lifecycle) {
+            let mut name = reducer.name.to_upper_camel_case();
+            if struct_names.contains(&name) {
+                name.push_str("Reducer");
+            }
+            self.reducer(&name, &reducer.name, &reducer.params)?;
+        }
+        Ok(())
+    }
+
+    /// The fields of the product type at `index`
+    fn product_fields(&self, index: u32, what: &str) -> Result<&'a [Element], CodegenError> {
+        let schema: &'a ModuleSchema = self.schema;
+        match schema.resolve(index)? {
+            AlgebraicType::Product(elements) => Ok(elements),
+            _ => Err(CodegenError::new(format!("{} is not a product type", what))),
+        }
+    }
+
+    fn table(&mut self, name: &str, table: &TableDef) -> Result<(), CodegenError> {
+        let fields =
+            self.product_fields(table.row, &format!("the row type of `{}`", table.name))?;
+
+        writeln!(self.out, "\n/// A row of the `{}` table", table.name).unwrap();
+        writeln!(
+            self.out,
+            "#[derive(Debug, Clone, PartialEq, ::serde::Serialize, ::serde::Deserialize, {}::TableRow)]",
+            CRATE
+        )
+        .unwrap();
+        writeln!(self.out, "#[stdb(table = {:?})]", table.name).unwrap();
+        writeln!(self.out, "pub struct {} {{", name).unwrap();
+        for (column, field) in fields.iter().enumerate() {
+            let attribute = if table.primary_key == Some(column) {
+                Some("primary_key")
+            } else if table.unique.contains(&column) {
+                Some("unique")
+            } else if table.indexed.contains(&column) {
+                Some("index")
+            } else {
+                None
+            };
+            if let Some(attribute) = attribute {
+                writeln!(self.out, "    #[stdb({})]", attribute).unwrap();
+            }
+            self.field(field, &table.name)?;
+        }
+        self.out.push_str("}\n");
+        Ok(())
+    }
+
+    fn field(&mut self, field: &Element, owner: &str) -> Result<(), CodegenError> {
+        let name = field
+            .name
+            .as_deref()
+            .ok_or_else(|| CodegenError::new(format!("`{}` has an unnamed field", owner)))?;
+        let ty = self.rust_type(&field.ty)?;
+        writeln!(self.out, "    pub {}: {},", ident(name)?, ty).unwrap();
+        Ok(())
+    }
+
+    fn named_type(&mut self, index: u32, name: &str) -> Result<(), CodegenError> {
+        match self.schema.resolve(index)?.clone() {
+            AlgebraicType::Product(fields) => {
+                writeln!(self.out, "\n/// The `{}` type", name).unwrap();
+                self.out.push_str(
+                    "#[derive(Debug, Clone, PartialEq, ::serde::Serialize, ::serde::Deserialize)]\n",
+                );
+                writeln!(self.out, "pub struct {} {{", name).unwrap();
+                for field in &fields {
+                    self.field(field, name)?;
+                }
+                self.out.push_str("}\n");
+            }
+            AlgebraicType::Sum(variants) => {
+                writeln!(self.out, "\n/// The `{}` type", name).unwrap();
+                writeln!(self.out, "#[{}::sum_type]", CRATE).unwrap();
+                self.out.push_str(
+                    "#[derive(Debug, Clone, PartialEq, ::serde::Serialize, ::serde::Deserialize)]\n",
+                );
+                writeln!(self.out, "pub enum {} {{", name).unwrap();
+                for (position, variant) in variants.iter().enumerate() {
+                    let variant_name = match &variant.name {
+                        Some(variant_name) => ident(variant_name)?,
+                        None => format!("Variant{}", position),
+                    };
+                    match &variant.ty {
+                        AlgebraicType::Product(fields) if fields.is_empty() => {
+                            writeln!(self.out, "    {},", variant_name).unwrap()
+                        }
+                        ty => {
+                            let ty = self.rust_type(ty)?;
+                            writeln!(self.out, "    {}({}),", variant_name, ty).unwrap()
+                        }
+                    }
+                }
+                self.out.push_str("}\n");
+            }
+            // Aliases of other types are inlined where they are used
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn reducer(
+        &mut self,
+        name: &str,
+        reducer: &str,
+        params: &[Element],
+    ) -> Result<(), CodegenError> {
+        let mut args = Vec::new();
+        let mut signature = Vec::new();
+        for param in params {
+            let ty = self.rust_type(&param.ty)?;
+            if let Some(param_name) = &param.name {
+                signature.push(format!("{}: {}", param_name, ty));
+            } else {
+                signature.push(ty.clone());
+            }
+            args.push(ty);
+        }
+        let args = match &args[..] {
+            [] => "()".to_string(),
+            [arg] => format!("({},)", arg),
+            args => format!("({})", args.join(", ")),
+        };
+
+        writeln!(
+            self.out,
+            "\n/// The `{}` reducer: `({})`",
+            reducer,
+            signature.join(", ")
+        )
+        .unwrap();
+        writeln!(self.out, "pub struct {};", name).unwrap();
+        writeln!(self.out, "\nimpl {}::Reducer for {} {{", CRATE, name).unwrap();
+        writeln!(self.out, "    const NAME: &'static str = {:?};", reducer).unwrap();
+        writeln!(self.out, "    type Args = {};", args).unwrap();
+        self.out.push_str("}\n");
+        Ok(())
+    }
+
+    fn rust_type(&self, ty: &AlgebraicType) -> Result<String, CodegenError> {
+        Ok(match ty {
+            AlgebraicType::Ref(index) => match self.names.get(index) {
+                Some(name) => name.clone(),
+                None => self.rust_type(self.schema.resolve(*index)?)?,
+            },
+            AlgebraicType::Primitive(primitive) => match primitive.as_str() {
+                "Bool" => "bool",
+                "I8" => "i8",
+                "U8" => "u8",
+                "I16" => "i16",
+                "U16" => "u16",
+                "I32" => "i32",
+                "U32" => "u32",
+                "I64" => "i64",
+                "U64" => "u64",
+                "I128" => "i128",
+                "U128" => "u128",
+                "F32" => "f32",
+                "F64" => "f64",
+                "String" => "String",
+                other => {
+                    return Err(CodegenError::new(format!(
+                        "{} values are not supported",
+                        other
+                    )));
+                }
+            }
+            .to_string(),
+            AlgebraicType::Array(element) => match &**element {
+                AlgebraicType::Primitive(primitive) if primitive == "U8" => {
+                    format!("{}::Bytes", CRATE)
+                }
+                element => format!("Vec<{}>", self.rust_type(element)?),
+            },
+            AlgebraicType::Product(fields) => match &fields[..] {
+                [] => "()".to_string(),
+                [field] => {
+                    let special = SPECIAL_PRODUCTS
+                        .iter()
+                        .find(|(name, _)| field.name.as_deref() == Some(*name))
+                        .ok_or_else(|| anonymous("product", ty))?;
+                    format!("{}::{}", CRATE, special.1)
+                }
+                _ => return Err(anonymous("product", ty)),
+            },
+            AlgebraicType::Sum(variants) => {
+                let names = variants
+                    .iter()
+                    .map(|variant| variant.name.as_deref().unwrap_or_default())
+                    .collect::<Vec<_>>();
+                match names[..] {
+                    ["some", "none"] => format!("Option<{}>", self.rust_type(&variants[0].ty)?),
+                    ["Interval", "Time"] => format!("{}::ScheduleAt", CRATE),
+                    _ => return Err(anonymous("sum", ty)),
+                }
+            }
+        })
+    }
+}
+
+fn anonymous(kind: &str, ty: &AlgebraicType) -> CodegenError {
+    CodegenError::new(format!(
+        "anonymous {} types are not supported: {:?}",
+        kind, ty
+    ))
+}
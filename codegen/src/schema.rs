@@ -0,0 +1,248 @@
+//! Module schemas, as printed by `spacetime describe --json`
+//!
+//! The schema is a `RawModuleDefV9` in SATS JSON: sum values are objects with one
+//! key naming the variant (`{ "some": x }`, `{ "User": [] }`) and unit values
+//! empty arrays. Only what the generator needs is read.
+
+use serde_json::Value;
+
+use crate::CodegenError;
+
+/// A type of the module's typespace
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum AlgebraicType {
+    /// A type defined elsewhere in the typespace
+    Ref(u32),
+    Sum(Vec<Element>),
+    Product(Vec<Element>),
+    Array(Box<AlgebraicType>),
+    /// `Bool`, `U32`, `String`...
+    Primitive(String),
+}
+
+/// A field of a product type or a variant of a sum type
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Element {
+    pub(crate) name: Option<String>,
+    pub(crate) ty: AlgebraicType,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct TableDef {
+    pub(crate) name: String,
+    /// The typespace entry of the row type
+    pub(crate) row: u32,
+    pub(crate) primary_key: Option<usize>,
+    pub(crate) unique: Vec<usize>,
+    pub(crate) indexed: Vec<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct ReducerDef {
+    pub(crate) name: String,
+    pub(crate) params: Vec<Element>,
+    /// Lifecycle reducers (`init`, `client_connected`...) can't be called by clients
+    pub(crate) lifecycle: bool,
+}
+
+/// The tables, reducers and types of a SpacetimeDB module
+#[derive(Debug, Clone)]
+pub struct ModuleSchema {
+    pub(crate) typespace: Vec<AlgebraicType>,
+    /// Type names by typespace entry, in declaration order
+    pub(crate) type_names: Vec<(u32, String)>,
+    pub(crate) tables: Vec<TableDef>,
+    pub(crate) reducers: Vec<ReducerDef>,
+}
+
+impl ModuleSchema {
+    /// Parse the output of `spacetime describe --json <module>`
+    pub fn from_json(json: &str) -> Result<Self, CodegenError> {
+        let value: Value = serde_json::from_str(json)?;
+        // `RawModuleDef` is a sum type; accept the V9 definition with or without it
+        let def = value.get("V9").unwrap_or(&value);
+
+        let typespace = field(def, "typespace")
+            .and_then(|typespace| array(field(typespace, "types")?))?
+            .iter()
+            .map(algebraic_type)
+            .collect::<Result<_, _>>()?;
+        let mut schema = Self {
+            typespace,
+            type_names: Vec::new(),
+            tables: Vec::new(),
+            reducers: Vec::new(),
+        };
+
+        for ty in array(field(def, "types")?)? {
+            let name = field(field(ty, "name")?, "name")?;
+            let name = name.as_str().ok_or_else(|| invalid("type name", name))?;
+            schema
+                .type_names
+                .push((type_ref(field(ty, "ty")?)?, name.to_string()));
+        }
+
+        for table in array(field(def, "tables")?)? {
+            schema.tables.push(table_def(table)?);
+        }
+
+        for reducer in array(field(def, "reducers")?)? {
+            let name = field(reducer, "name")?;
+            schema.reducers.push(ReducerDef {
+                name: name
+                    .as_str()
+                    .ok_or_else(|| invalid("reducer name", name))?
+                    .to_string(),
+                params: elements(field(field(reducer, "params")?, "elements")?)?,
+                lifecycle: reducer.get("lifecycle").and_then(option).is_some(),
+            });
+        }
+
+        Ok(schema)
+    }
+
+    pub(crate) fn resolve(&self, index: u32) -> Result<&AlgebraicType, CodegenError> {
+        self.typespace
+            .get(index as usize)
+            .ok_or_else(|| CodegenError::new(format!("type reference {} out of range", index)))
+    }
+}
+
+fn invalid(what: &str, value: &Value) -> CodegenError {
+    CodegenError::new(format!("invalid {}: {}", what, value))
+}
+
+fn field<'a>(value: &'a Value, name: &str) -> Result<&'a Value, CodegenError> {
+    value
+        .get(name)
+        .ok_or_else(|| CodegenError::new(format!("missing field `{}` in {}", name, value)))
+}
+
+fn array(value: &Value) -> Result<&Vec<Value>, CodegenError> {
+    value.as_array().ok_or_else(|| invalid("array", value))
+}
+
+/// An array field that may be missing
+fn optional_array<'a>(value: &'a Value, name: &str) -> &'a [Value] {
+    value
+        .get(name)
+        .and_then(Value::as_array)
+        .map_or(&[], Vec::as_slice)
+}
+
+/// The value of an `Option`: `{ "some": x }` or `{ "none": [] }` (plain values and
+/// null are accepted too)
+fn option(value: &Value) -> Option<&Value> {
+    match value {
+        Value::Null => None,
+        Value::Object(object) if object.len() == 1 && object.contains_key("none") => None,
+        Value::Object(object) if object.len() == 1 && object.contains_key("some") => {
+            object.get("some")
+        }
+        value => Some(value),
+    }
+}
+
+/// The variant of a sum value: `{ "Variant": value }`
+fn variant(value: &Value) -> Result<(&str, &Value), CodegenError> {
+    match value.as_object() {
+        Some(object) if object.len() == 1 => {
+            let (name, value) = object.iter().next().expect("one entry");
+            Ok((name.as_str(), value))
+        }
+        _ => Err(invalid("sum value", value)),
+    }
+}
+
+fn type_ref(value: &Value) -> Result<u32, CodegenError> {
+    value
+        .as_u64()
+        .and_then(|index| u32::try_from(index).ok())
+        .ok_or_else(|| invalid("type reference", value))
+}
+
+/// Column positions, e.g. a primary key or the columns of an index
+fn columns(value: &Value) -> Result<Vec<usize>, CodegenError> {
+    let value = value.get("columns").unwrap_or(value);
+    array(value)?
+        .iter()
+        .map(|column| {
+            column
+                .as_u64()
+                .map(|column| column as usize)
+                .ok_or_else(|| invalid("column", column))
+        })
+        .collect()
+}
+
+fn algebraic_type(value: &Value) -> Result<AlgebraicType, CodegenError> {
+    let (kind, value) = variant(value)?;
+    Ok(match kind {
+        "Ref" => AlgebraicType::Ref(type_ref(value)?),
+        "Sum" => AlgebraicType::Sum(elements(field(value, "variants")?)?),
+        "Product" => AlgebraicType::Product(elements(field(value, "elements")?)?),
+        "Array" => {
+            let element = value.get("elem_ty").unwrap_or(value);
+            AlgebraicType::Array(Box::new(algebraic_type(element)?))
+        }
+        primitive => AlgebraicType::Primitive(primitive.to_string()),
+    })
+}
+
+fn elements(value: &Value) -> Result<Vec<Element>, CodegenError> {
+    array(value)?
+        .iter()
+        .map(|element| {
+            let name = element.get("name").and_then(option);
+            Ok(Element {
+                name: name
+                    .map(|name| {
+                        name.as_str()
+                            .map(str::to_string)
+                            .ok_or_else(|| invalid("name", name))
+                    })
+                    .transpose()?,
+                ty: algebraic_type(field(element, "algebraic_type")?)?,
+            })
+        })
+        .collect()
+}
+
+fn table_def(table: &Value) -> Result<TableDef, CodegenError> {
+    let name = field(table, "name")?;
+    let name = name.as_str().ok_or_else(|| invalid("table name", name))?;
+
+    let primary_key = columns(field(table, "primary_key")?)?;
+    if primary_key.len() > 1 {
+        return Err(CodegenError::new(format!(
+            "table `{}` has a multi-column primary key, which is not supported",
+            name
+        )));
+    }
+
+    // Multi-column constraints and indexes have no column attribute to map to
+    let mut unique = Vec::new();
+    for constraint in optional_array(table, "constraints") {
+        if let Some(Ok(("Unique", data))) = constraint.get("data").map(variant)
+            && let Ok([column]) = columns(data).as_deref()
+        {
+            unique.push(*column);
+        }
+    }
+    let mut indexed = Vec::new();
+    for index in optional_array(table, "indexes") {
+        if let Some(Ok((_, algorithm))) = index.get("algorithm").map(variant)
+            && let Ok([column]) = columns(algorithm).as_deref()
+        {
+            indexed.push(*column);
+        }
+    }
+
+    Ok(TableDef {
+        name: name.to_string(),
+        row: type_ref(field(table, "product_type_ref")?)?,
+        primary_key: primary_key.first().copied(),
+        unique,
+        indexed,
+    })
+}
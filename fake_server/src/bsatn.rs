@@ -0,0 +1,595 @@
+//! The binary flavour of the client protocol, `v1.bsatn.spacetimedb`
+//!
+//! Values are little-endian without padding: strings and arrays are prefixed
+//! with their `u32` length, sums (options, message kinds) with their `u8` tag,
+//! and products are their fields in order. Rows are stored as JSON, so they are
+//! converted with the column types of their table; tables and reducers without
+//! types cannot be sent to binary clients.
+//!
+//! Server messages start with a compression byte, always 0 (none) here.
+
+use serde_json::{Value, json};
+
+use crate::protocol::{
+    CallReducer, ClientMessage, DatabaseUpdate, OneOffQuery, QueryId, ServerMessage,
+    SubscribeMulti, SubscriptionUpdate, UnsubscribeMulti, UpdateStatus,
+};
+use crate::{Column, ColumnType};
+
+/// Encode a message for a binary client
+pub(crate) fn encode_server_message(message: &ServerMessage) -> Result<Vec<u8>, String> {
+    let mut out = vec![0];
+    match message {
+        ServerMessage::IdentityToken {
+            identity,
+            token,
+            connection_id,
+        } => {
+            out.push(3);
+            write_value(&mut out, &ColumnType::Identity, identity)?;
+            write_str(&mut out, token);
+            write_value(&mut out, &ColumnType::ConnectionId, connection_id)?;
+        }
+        ServerMessage::SubscribeMultiApplied(update) => {
+            out.push(8);
+            write_subscription_update(&mut out, update)?;
+        }
+        ServerMessage::UnsubscribeMultiApplied(update) => {
+            out.push(9);
+            write_subscription_update(&mut out, update)?;
+        }
+        ServerMessage::SubscriptionError {
+            total_host_execution_duration_micros,
+            request_id,
+            query_id,
+            table_id,
+            error,
+        } => {
+            out.push(7);
+            out.extend(total_host_execution_duration_micros.to_le_bytes());
+            for id in [request_id, query_id, table_id] {
+                write_option(&mut out, id.as_ref(), |out, id| {
+                    out.extend(id.to_le_bytes());
+                    Ok(())
+                })?;
+            }
+            write_str(&mut out, error);
+        }
+        ServerMessage::TransactionUpdate {
+            status,
+            timestamp,
+            caller_identity,
+            caller_connection_id,
+            reducer_call,
+            energy_quanta_used,
+            total_host_execution_duration,
+        } => {
+            out.push(1);
+            match status {
+                UpdateStatus::Committed(update) => {
+                    out.push(0);
+                    write_database_update(&mut out, update)?;
+                }
+                UpdateStatus::Failed(error) => {
+                    out.push(1);
+                    write_str(&mut out, error);
+                }
+            }
+            write_value(&mut out, &ColumnType::Timestamp, timestamp)?;
+            write_value(&mut out, &ColumnType::Identity, caller_identity)?;
+            write_value(&mut out, &ColumnType::ConnectionId, caller_connection_id)?;
+
+            write_str(&mut out, &reducer_call.reducer_name);
+            out.extend(reducer_call.reducer_id.to_le_bytes());
+            let mut args = Vec::new();
+            if let Some(params) = &reducer_call.params {
+                write_args(&mut args, params, &reducer_call.args)
+                    .map_err(|e| format!("reducer {}: {}", reducer_call.reducer_name, e))?;
+            }
+            write_bytes(&mut out, &args);
+            out.extend(reducer_call.request_id.to_le_bytes());
+
+            let quanta = energy_quanta_used.get("quanta").unwrap_or(&Value::Null);
+            write_value(&mut out, &ColumnType::U128, quanta)?;
+            write_value(
+                &mut out,
+                &ColumnType::TimeDuration,
+                total_host_execution_duration,
+            )?;
+        }
+        ServerMessage::OneOffQueryResponse {
+            message_id,
+            error,
+            tables,
+            total_host_execution_duration,
+        } => {
+            out.push(4);
+            write_value(
+                &mut out,
+                &ColumnType::Array(Box::new(ColumnType::U8)),
+                message_id,
+            )?;
+            write_option(&mut out, error.as_ref(), |out, error| {
+                write_str(out, error);
+                Ok(())
+            })?;
+            write_len(&mut out, tables.len());
+            for table in tables {
+                write_str(&mut out, &table.table_name);
+                write_rows(&mut out, &table.table_name, &table.columns, &table.rows)?;
+            }
+            write_value(
+                &mut out,
+                &ColumnType::TimeDuration,
+                total_host_execution_duration,
+            )?;
+        }
+    }
+    Ok(out)
+}
+
+/// Decode a message from a binary client
+///
+/// `params` gives the parameter types of a reducer; the arguments of reducers
+/// without known types are passed on as `null`.
+pub(crate) fn decode_client_message<'a>(
+    bytes: &[u8],
+    params: impl Fn(&str) -> Option<&'a [Column]>,
+) -> Result<ClientMessage, String> {
+    let mut reader = Reader::new(bytes);
+    let message = match reader.u8()? {
+        0 => {
+            let reducer = reader.string()?;
+            let args = reader.bytes()?;
+            let request_id = reader.u32()?;
+            let _flags = reader.u8()?;
+            let args = match params(&reducer) {
+                Some(params) => read_args(args, params)
+                    .map_err(|e| format!("arguments of {}: {}", reducer, e))?,
+                None => Value::Null,
+            };
+            ClientMessage::CallReducer(CallReducer {
+                reducer,
+                args,
+                request_id,
+            })
+        }
+        2 => {
+            let message_id = reader.bytes()?;
+            ClientMessage::OneOffQuery(OneOffQuery {
+                message_id: json!(message_id),
+                query_string: reader.string()?,
+            })
+        }
+        4 => {
+            let count = reader.u32()?;
+            let query_strings = (0..count)
+                .map(|_| reader.string())
+                .collect::<Result<_, _>>()?;
+            ClientMessage::SubscribeMulti(SubscribeMulti {
+                query_strings,
+                request_id: reader.u32()?,
+                query_id: QueryId { id: reader.u32()? },
+            })
+        }
+        6 => ClientMessage::UnsubscribeMulti(UnsubscribeMulti {
+            request_id: reader.u32()?,
+            query_id: QueryId { id: reader.u32()? },
+        }),
+        tag => return Err(format!("unsupported client message (tag {})", tag)),
+    };
+    reader.finish()?;
+    Ok(message)
+}
+
+fn write_subscription_update(out: &mut Vec<u8>, update: &SubscriptionUpdate) -> Result<(), String> {
+    out.extend(update.request_id.to_le_bytes());
+    out.extend(update.total_host_execution_duration_micros.to_le_bytes());
+    out.extend(update.query_id.id.to_le_bytes());
+    write_database_update(out, &update.update)
+}
+
+fn write_database_update(out: &mut Vec<u8>, update: &DatabaseUpdate) -> Result<(), String> {
+    write_len(out, update.tables.len());
+    for table in &update.tables {
+        out.extend(table.table_id.to_le_bytes());
+        write_str(out, &table.table_name);
+        out.extend(table.num_rows.to_le_bytes());
+        write_len(out, table.updates.len());
+        for query in &table.updates {
+            // Uncompressed
+            out.push(0);
+            write_rows(out, &table.table_name, &table.columns, &query.deletes)?;
+            write_rows(out, &table.table_name, &table.columns, &query.inserts)?;
+        }
+    }
+    Ok(())
+}
+
+/// Write rows as a `BsatnRowList`: their offsets, then their bytes
+fn write_rows(
+    out: &mut Vec<u8>,
+    table: &str,
+    columns: &[Column],
+    rows: &[Value],
+) -> Result<(), String> {
+    if columns.is_empty() && !rows.is_empty() {
+        return Err(format!("table {} has no column types", table));
+    }
+    let mut data = Vec::new();
+    let mut offsets = Vec::new();
+    for row in rows {
+        offsets.push(data.len() as u64);
+        write_row(&mut data, columns, row).map_err(|e| format!("table {}: {}", table, e))?;
+    }
+    out.push(1);
+    write_len(out, offsets.len());
+    for offset in offsets {
+        out.extend(offset.to_le_bytes());
+    }
+    write_bytes(out, &data);
+    Ok(())
+}
+
+/// Write a row given as a JSON object
+pub(crate) fn write_row(out: &mut Vec<u8>, columns: &[Column], row: &Value) -> Result<(), String> {
+    let row = row
+        .as_object()
+        .ok_or_else(|| format!("expected a row object, got {}", row))?;
+    for column in columns {
+        let value = row.get(&column.name).unwrap_or(&Value::Null);
+        write_value(out, &column.ty, value)
+            .map_err(|e| format!("column {}: {}", column.name, e))?;
+    }
+    Ok(())
+}
+
+/// Write reducer arguments, given as a JSON array or object
+fn write_args(out: &mut Vec<u8>, params: &[Column], args: &Value) -> Result<(), String> {
+    match args {
+        Value::Array(values) if values.len() == params.len() => {
+            for (param, value) in params.iter().zip(values) {
+                write_value(out, &param.ty, value)
+                    .map_err(|e| format!("argument {}: {}", param.name, e))?;
+            }
+            Ok(())
+        }
+        Value::Object(_) => write_row(out, params, args),
+        Value::Null if params.is_empty() => Ok(()),
+        args => Err(format!("expected {} arguments, got {}", params.len(), args)),
+    }
+}
+
+/// Read reducer arguments as a JSON array
+pub(crate) fn read_args(bytes: &[u8], params: &[Column]) -> Result<Value, String> {
+    let mut reader = Reader::new(bytes);
+    let args = params
+        .iter()
+        .map(|param| read_value(&mut reader, &param.ty))
+        .collect::<Result<Vec<_>, _>>()?;
+    reader.finish()?;
+    Ok(Value::Array(args))
+}
+
+fn write_len(out: &mut Vec<u8>, len: usize) {
+    out.extend((len as u32).to_le_bytes());
+}
+
+fn write_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    write_len(out, bytes.len());
+    out.extend_from_slice(bytes);
+}
+
+fn write_str(out: &mut Vec<u8>, s: &str) {
+    write_bytes(out, s.as_bytes());
+}
+
+fn write_option<T>(
+    out: &mut Vec<u8>,
+    value: Option<T>,
+    write: impl FnOnce(&mut Vec<u8>, T) -> Result<(), String>,
+) -> Result<(), String> {
+    match value {
+        Some(value) => {
+            out.push(0);
+            write(out, value)
+        }
+        None => {
+            out.push(1);
+            Ok(())
+        }
+    }
+}
+
+/// Write a JSON value as a value of type `ty`
+pub(crate) fn write_value(out: &mut Vec<u8>, ty: &ColumnType, value: &Value) -> Result<(), String> {
+    match ty {
+        ColumnType::Bool => {
+            out.push(value.as_bool().ok_or_else(|| expected("a bool", value))? as u8)
+        }
+        ColumnType::U8 => out.extend(unsigned::<u8>(value)?.to_le_bytes()),
+        ColumnType::U16 => out.extend(unsigned::<u16>(value)?.to_le_bytes()),
+        ColumnType::U32 => out.extend(unsigned::<u32>(value)?.to_le_bytes()),
+        ColumnType::U64 => out.extend(unsigned::<u64>(value)?.to_le_bytes()),
+        ColumnType::U128 => out.extend(unsigned::<u128>(value)?.to_le_bytes()),
+        ColumnType::I8 => out.extend(signed::<i8>(value)?.to_le_bytes()),
+        ColumnType::I16 => out.extend(signed::<i16>(value)?.to_le_bytes()),
+        ColumnType::I32 => out.extend(signed::<i32>(value)?.to_le_bytes()),
+        ColumnType::I64 => out.extend(signed::<i64>(value)?.to_le_bytes()),
+        ColumnType::I128 => out.extend(signed::<i128>(value)?.to_le_bytes()),
+        ColumnType::F32 => out.extend((float(value)? as f32).to_le_bytes()),
+        ColumnType::F64 => out.extend(float(value)?.to_le_bytes()),
+        ColumnType::String => write_str(
+            out,
+            value.as_str().ok_or_else(|| expected("a string", value))?,
+        ),
+        ColumnType::Identity => {
+            let hex = special(value, "__identity__")
+                .as_str()
+                .ok_or_else(|| expected("an identity", value))?;
+            let mut bytes = parse_hex(hex.trim_start_matches("0x"))
+                .filter(|bytes| bytes.len() == 32)
+                .ok_or_else(|| expected("an identity of 64 hex digits", value))?;
+            // The hex form is big-endian
+            bytes.reverse();
+            out.extend(bytes);
+        }
+        ColumnType::ConnectionId => {
+            let id = unsigned::<u128>(special(value, "__connection_id__"))?;
+            out.extend(id.to_le_bytes());
+        }
+        ColumnType::Timestamp => {
+            let micros = signed::<i64>(special(value, "__timestamp_micros_since_unix_epoch__"))?;
+            out.extend(micros.to_le_bytes());
+        }
+        ColumnType::TimeDuration => {
+            let micros = signed::<i64>(special(value, "__time_duration_micros__"))?;
+            out.extend(micros.to_le_bytes());
+        }
+        ColumnType::Array(element) => {
+            let values = value
+                .as_array()
+                .ok_or_else(|| expected("an array", value))?;
+            write_len(out, values.len());
+            for value in values {
+                write_value(out, element, value)?;
+            }
+        }
+        ColumnType::Option(inner) => {
+            let value = Some(value).filter(|value| !value.is_null());
+            write_option(out, value, |out, value| write_value(out, inner, value))?;
+        }
+    }
+    Ok(())
+}
+
+/// Read a value of type `ty` as JSON, in the form rows are stored in
+pub(crate) fn read_value(reader: &mut Reader<'_>, ty: &ColumnType) -> Result<Value, String> {
+    Ok(match ty {
+        ColumnType::Bool => json!(reader.u8()? != 0),
+        ColumnType::U8 => json!(reader.u8()?),
+        ColumnType::U16 => json!(u16::from_le_bytes(reader.array()?)),
+        ColumnType::U32 => json!(reader.u32()?),
+        ColumnType::U64 => json!(reader.u64()?),
+        ColumnType::U128 => wide_unsigned(u128::from_le_bytes(reader.array()?)),
+        ColumnType::I8 => json!(i8::from_le_bytes(reader.array()?)),
+        ColumnType::I16 => json!(i16::from_le_bytes(reader.array()?)),
+        ColumnType::I32 => json!(i32::from_le_bytes(reader.array()?)),
+        ColumnType::I64 => json!(i64::from_le_bytes(reader.array()?)),
+        ColumnType::I128 => {
+            let value = i128::from_le_bytes(reader.array()?);
+            i64::try_from(value).map_or_else(|_| json!(value.to_string()), |value| json!(value))
+        }
+        ColumnType::F32 => json!(f32::from_le_bytes(reader.array()?)),
+        ColumnType::F64 => json!(f64::from_le_bytes(reader.array()?)),
+        ColumnType::String => json!(reader.string()?),
+        ColumnType::Identity => {
+            let bytes: [u8; 32] = reader.array()?;
+            let hex = bytes.iter().rev().map(|byte| format!("{:02x}", byte));
+            json!({ "__identity__": hex.collect::<String>() })
+        }
+        ColumnType::ConnectionId => {
+            json!({ "__connection_id__": wide_unsigned(u128::from_le_bytes(reader.array()?)) })
+        }
+        ColumnType::Timestamp => {
+            json!({ "__timestamp_micros_since_unix_epoch__": i64::from_le_bytes(reader.array()?) })
+        }
+        ColumnType::TimeDuration => {
+            json!({ "__time_duration_micros__": i64::from_le_bytes(reader.array()?) })
+        }
+        ColumnType::Array(element) => {
+            let len = reader.u32()?;
+            let values = (0..len)
+                .map(|_| read_value(reader, element))
+                .collect::<Result<Vec<_>, _>>()?;
+            Value::Array(values)
+        }
+        ColumnType::Option(inner) => match reader.u8()? {
+            0 => read_value(reader, inner)?,
+            1 => Value::Null,
+            tag => return Err(format!("invalid option tag {}", tag)),
+        },
+    })
+}
+
+/// Read a row as a JSON object
+#[cfg(test)]
+pub(crate) fn read_row(reader: &mut Reader<'_>, columns: &[Column]) -> Result<Value, String> {
+    let row = columns
+        .iter()
+        .map(|column| Ok((column.name.clone(), read_value(reader, &column.ty)?)))
+        .collect::<Result<serde_json::Map<_, _>, String>>()?;
+    Ok(Value::Object(row))
+}
+
+/// A number, or a decimal string if it does not fit in a JSON number
+fn wide_unsigned(value: u128) -> Value {
+    u64::try_from(value).map_or_else(|_| json!(value.to_string()), |value| json!(value))
+}
+
+/// The field of a special type's object, or the value itself
+fn special<'a>(value: &'a Value, field: &str) -> &'a Value {
+    value.get(field).unwrap_or(value)
+}
+
+fn unsigned<T: TryFrom<u128>>(value: &Value) -> Result<T, String> {
+    let wide = match value {
+        Value::Number(number) => number.as_u64().map(u128::from),
+        Value::String(digits) => digits.parse().ok(),
+        _ => None,
+    };
+    wide.and_then(|wide| T::try_from(wide).ok())
+        .ok_or_else(|| expected(std::any::type_name::<T>(), value))
+}
+
+fn signed<T: TryFrom<i128>>(value: &Value) -> Result<T, String> {
+    let wide = match value {
+        Value::Number(number) => number
+            .as_i64()
+            .map(i128::from)
+            .or_else(|| number.as_u64().map(i128::from)),
+        Value::String(digits) => digits.parse().ok(),
+        _ => None,
+    };
+    wide.and_then(|wide| T::try_from(wide).ok())
+        .ok_or_else(|| expected(std::any::type_name::<T>(), value))
+}
+
+fn float(value: &Value) -> Result<f64, String> {
+    value.as_f64().ok_or_else(|| expected("a number", value))
+}
+
+fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn expected(what: &str, value: &Value) -> String {
+    format!("expected {}, got {}", what, value)
+}
+
+/// Reads values from the front of a message
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.bytes.len() < len {
+            return Err("message ends early".to_string());
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    pub(crate) fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    pub(crate) fn bytes(&mut self) -> Result<&'a [u8], String> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+
+    pub(crate) fn string(&mut self) -> Result<String, String> {
+        let bytes = self.bytes()?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| "invalid UTF-8 in a string".to_string())
+    }
+
+    /// Fail if anything is left
+    pub(crate) fn finish(&self) -> Result<(), String> {
+        if self.bytes.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("{} unexpected trailing bytes", self.bytes.len()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::columns;
+
+    #[test]
+    fn test_values_roundtrip() {
+        let columns = columns(&[
+            ("id", "u32"),
+            ("name", "string"),
+            ("owner", "identity"),
+            ("connection", "connection_id"),
+            ("score", "option<i64>"),
+            ("tags", "array<string>"),
+            ("big", "u128"),
+        ]);
+        let row = json!({
+            "id": 7,
+            "name": "Alice",
+            "owner": { "__identity__": format!("{:064x}", 0x0102) },
+            "connection": { "__connection_id__": u128::MAX.to_string() },
+            "score": null,
+            "tags": ["a", "b"],
+            "big": 5,
+        });
+
+        let mut bytes = Vec::new();
+        write_row(&mut bytes, &columns, &row).unwrap();
+        assert_eq!(&bytes[..4], &7u32.to_le_bytes());
+        // Identities are little-endian
+        assert_eq!(&bytes[13..15], &[0x02, 0x01]);
+
+        let mut reader = Reader::new(&bytes);
+        assert_eq!(read_row(&mut reader, &columns).unwrap(), row);
+        reader.finish().unwrap();
+
+        let mut bytes = Vec::new();
+        assert!(write_row(&mut bytes, &columns, &json!({ "id": -1 })).is_err());
+        assert!(write_value(&mut bytes, &ColumnType::U8, &json!(256)).is_err());
+    }
+
+    #[test]
+    fn test_client_messages() {
+        let params = columns(&[("id", "u32"), ("name", "string")]);
+        let mut args = Vec::new();
+        write_args(&mut args, &params, &json!([1, "Bob"])).unwrap();
+        let mut bytes = vec![0];
+        write_str(&mut bytes, "create_player");
+        write_bytes(&mut bytes, &args);
+        bytes.extend(9u32.to_le_bytes());
+        bytes.push(0);
+
+        let lookup = |name: &str| (name == "create_player").then_some(params.as_slice());
+        let ClientMessage::CallReducer(call) = decode_client_message(&bytes, lookup).unwrap()
+        else {
+            panic!("expected a reducer call");
+        };
+        assert_eq!(call.args, json!([1, "Bob"]));
+        assert_eq!(call.request_id, 9);
+
+        bytes.push(0);
+        assert!(decode_client_message(&bytes, lookup).is_err());
+        assert!(decode_client_message(&[1], lookup).is_err());
+    }
+}
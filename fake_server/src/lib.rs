@@ -0,0 +1,55 @@
+//! # bevy_spacetimedb_fake_server
+//!
+//! A stand-in for a SpacetimeDB server, so the bridge and `StdbPlugin` can be
+//! tested end to end without `spacetime start`.
+//!
+//! The server keeps its tables in memory as JSON rows and runs reducers as Rust
+//! closures (or the row-level reducers of a [`Schema`] file). It speaks enough
+//! of the `v1.bsatn.spacetimedb` and `v1.json.spacetimedb` WebSocket protocols
+//! for a client to:
+//!
+//! - connect and receive an identity token (the same token always maps to the
+//!   same identity),
+//! - subscribe to `SELECT * FROM table [WHERE ...]` queries and unsubscribe,
+//! - receive transaction updates for the rows it subscribed to,
+//! - call reducers and be told whether they committed or failed,
+//! - run one-off queries.
+//!
+//! It also serves the module definition at `/v1/database/<module>/schema`, so
+//! the bridge can build the TypeScript SDK's bindings without generated code.
+//! The binary protocol needs the column types of tables and the parameter
+//! types of reducers, see [`FakeServer::columns`] and [`FakeServer::params`].
+//!
+//! It is not a database: there is no persistence, no access control, and no
+//! scheduled or lifecycle reducers.
+//!
+//! Run `fake-spacetimedb --help` for the binary, or embed the server in tests:
+//!
+//! ```no_run
+//! use bevy_spacetimedb_fake_server::FakeServer;
+//!
+//! let server = FakeServer::new()
+//!     .table_with_primary_key("test_player", "id")
+//!     .columns("test_player", &[("id", "u32"), ("name", "string")])
+//!     .reducer("create_player", |ctx, (id, name): (u32, String)| {
+//!         ctx.db.insert("test_player", serde_json::json!({ "id": id, "name": name }))
+//!     })
+//!     .params("create_player", &[("id", "u32"), ("name", "string")])
+//!     .listen("127.0.0.1:0")
+//!     .unwrap();
+//! // Connect to server.url(); the server stops when dropped
+//! ```
+
+mod bsatn;
+mod protocol;
+mod schema;
+mod server;
+mod sql;
+mod store;
+mod types;
+
+pub use protocol::{BSATN_SUBPROTOCOL, SUBPROTOCOL};
+pub use schema::{ReducerSchema, RowAction, Schema};
+pub use server::{FakeServer, ReducerContext, RunningServer};
+pub use store::{TableSchema, Transaction};
+pub use types::{Column, ColumnType};
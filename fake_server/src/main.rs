@@ -0,0 +1,63 @@
+//! `fake-spacetimedb`: serve a schema file on a local port
+
+use std::process::ExitCode;
+
+use bevy_spacetimedb_fake_server::{FakeServer, Schema};
+
+const USAGE: &str = "\
+Usage: fake-spacetimedb [--listen <addr>] [--schema <file>]
+
+Options:
+  --listen <addr>   Address to listen on [default: 127.0.0.1:3000]
+  --schema <file>   JSON file with the tables and reducers to serve
+  -h, --help        Print this help";
+
+fn main() -> ExitCode {
+    let mut listen = "127.0.0.1:3000".to_string();
+    let mut schema = Schema::default();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let result = match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return ExitCode::SUCCESS;
+            }
+            "--listen" => args
+                .next()
+                .map(|addr| listen = addr)
+                .ok_or_else(|| "--listen needs an address".to_string()),
+            "--schema" => args
+                .next()
+                .ok_or_else(|| "--schema needs a file".to_string())
+                .and_then(|path| read_schema(&path))
+                .map(|read| schema = read),
+            other => Err(format!("unknown argument: {}", other)),
+        };
+        if let Err(e) = result {
+            eprintln!("{}\n\n{}", e, USAGE);
+            return ExitCode::FAILURE;
+        }
+    }
+
+    let tables = schema.tables.keys().cloned().collect::<Vec<_>>();
+    let reducers = schema.reducers.keys().cloned().collect::<Vec<_>>();
+    match FakeServer::from_schema(schema).listen(&listen) {
+        Ok(server) => {
+            println!("fake-spacetimedb listening on {}", server.url());
+            println!("  tables: {}", tables.join(", "));
+            println!("  reducers: {}", reducers.join(", "));
+            server.wait();
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("failed to listen on {}: {}", listen, e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn read_schema(path: &str) -> Result<Schema, String> {
+    let json = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    serde_json::from_str(&json).map_err(|e| format!("{}: {}", path, e))
+}
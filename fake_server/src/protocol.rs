@@ -0,0 +1,195 @@
+//! Messages of the SpacetimeDB client protocol
+//!
+//! Field names follow the `v1.json.spacetimedb` protocol. Rows are sent as JSON
+//! objects, options as the value or `null`, and each table update carries the
+//! table's primary key column so clients without generated bindings can pair
+//! deletes and inserts into updates. The same messages are encoded for
+//! `v1.bsatn.spacetimedb` clients by [`crate::bsatn`], using the column types
+//! they carry.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::Column;
+
+/// The WebSocket subprotocol of the JSON protocol
+pub const SUBPROTOCOL: &str = "v1.json.spacetimedb";
+
+/// The WebSocket subprotocol of the binary protocol, which the SDKs speak
+pub const BSATN_SUBPROTOCOL: &str = "v1.bsatn.spacetimedb";
+
+/// The protocol a client connected with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Protocol {
+    Json,
+    Bsatn,
+}
+
+impl Protocol {
+    pub(crate) fn from_subprotocol(name: &str) -> Option<Self> {
+        match name {
+            SUBPROTOCOL => Some(Protocol::Json),
+            BSATN_SUBPROTOCOL => Some(Protocol::Bsatn),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn subprotocol(self) -> &'static str {
+        match self {
+            Protocol::Json => SUBPROTOCOL,
+            Protocol::Bsatn => BSATN_SUBPROTOCOL,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) enum ClientMessage {
+    CallReducer(CallReducer),
+    SubscribeMulti(SubscribeMulti),
+    UnsubscribeMulti(UnsubscribeMulti),
+    OneOffQuery(OneOffQuery),
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct CallReducer {
+    pub(crate) reducer: String,
+    /// The arguments, or a string holding them as JSON
+    pub(crate) args: Value,
+    pub(crate) request_id: u32,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct SubscribeMulti {
+    pub(crate) query_strings: Vec<String>,
+    pub(crate) request_id: u32,
+    pub(crate) query_id: QueryId,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct UnsubscribeMulti {
+    pub(crate) request_id: u32,
+    pub(crate) query_id: QueryId,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct OneOffQuery {
+    pub(crate) message_id: Value,
+    pub(crate) query_string: String,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub(crate) struct QueryId {
+    pub(crate) id: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) enum ServerMessage {
+    IdentityToken {
+        identity: Value,
+        token: String,
+        connection_id: Value,
+    },
+    SubscribeMultiApplied(SubscriptionUpdate),
+    UnsubscribeMultiApplied(SubscriptionUpdate),
+    SubscriptionError {
+        total_host_execution_duration_micros: u64,
+        request_id: Option<u32>,
+        query_id: Option<u32>,
+        table_id: Option<u32>,
+        error: String,
+    },
+    TransactionUpdate {
+        status: UpdateStatus,
+        timestamp: Value,
+        caller_identity: Value,
+        caller_connection_id: Value,
+        reducer_call: ReducerCallInfo,
+        energy_quanta_used: Value,
+        total_host_execution_duration: Value,
+    },
+    OneOffQueryResponse {
+        message_id: Value,
+        error: Option<String>,
+        tables: Vec<OneOffTable>,
+        total_host_execution_duration: Value,
+    },
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct SubscriptionUpdate {
+    pub(crate) request_id: u32,
+    pub(crate) total_host_execution_duration_micros: u64,
+    pub(crate) query_id: QueryId,
+    pub(crate) update: DatabaseUpdate,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) enum UpdateStatus {
+    Committed(DatabaseUpdate),
+    Failed(String),
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct ReducerCallInfo {
+    pub(crate) reducer_name: String,
+    pub(crate) reducer_id: u32,
+    pub(crate) args: Value,
+    pub(crate) request_id: u32,
+    /// The parameter types, if known
+    #[serde(skip)]
+    pub(crate) params: Option<Vec<Column>>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub(crate) struct DatabaseUpdate {
+    pub(crate) tables: Vec<TableUpdate>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct TableUpdate {
+    pub(crate) table_id: u32,
+    pub(crate) table_name: String,
+    pub(crate) primary_key: Option<String>,
+    pub(crate) num_rows: u64,
+    pub(crate) updates: Vec<QueryUpdate>,
+    #[serde(skip)]
+    pub(crate) columns: Vec<Column>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct QueryUpdate {
+    pub(crate) deletes: Vec<Value>,
+    pub(crate) inserts: Vec<Value>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct OneOffTable {
+    pub(crate) table_name: String,
+    pub(crate) rows: Vec<Value>,
+    #[serde(skip)]
+    pub(crate) columns: Vec<Column>,
+}
+
+/// An identity, given as 64 hex digits
+pub(crate) fn identity(hex: &str) -> Value {
+    json!({ "__identity__": hex })
+}
+
+pub(crate) fn connection_id(id: u64) -> Value {
+    json!({ "__connection_id__": id })
+}
+
+pub(crate) fn timestamp_now() -> Value {
+    let micros = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_micros() as i64);
+    json!({ "__timestamp_micros_since_unix_epoch__": micros })
+}
+
+pub(crate) fn zero_duration() -> Value {
+    json!({ "__time_duration_micros__": 0 })
+}
+
+pub(crate) fn energy() -> Value {
+    json!({ "quanta": 0 })
+}
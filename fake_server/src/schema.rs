@@ -0,0 +1,155 @@
+//! Servers described by a JSON file, for the `fake-spacetimedb` binary
+//!
+//! ```json
+//! {
+//!   "tables": {
+//!     "test_player": {
+//!       "primary_key": "id",
+//!       "columns": [["id", "u32"], ["name", "string"]],
+//!       "rows": [{ "id": 1, "name": "Alice" }]
+//!     }
+//!   },
+//!   "reducers": {
+//!     "create_player": { "insert": "test_player", "params": ["id", "name"] },
+//!     "rename_player": { "update": "test_player", "params": ["id", "name"] },
+//!     "delete_player": { "delete": "test_player", "params": ["id"] }
+//!   }
+//! }
+//! ```
+//!
+//! Binary clients, like the SDKs, need the column types of the tables they use.
+//! Reducer parameters get the types of the columns they are stored in.
+
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::{Column, ReducerContext, TableSchema};
+
+/// The tables and reducers of a server
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Schema {
+    /// Tables by name
+    #[serde(default)]
+    pub tables: BTreeMap<String, TableSchema>,
+    /// Reducers by name
+    #[serde(default)]
+    pub reducers: BTreeMap<String, ReducerSchema>,
+}
+
+/// A reducer changing one row, built from its arguments
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReducerSchema {
+    /// What the reducer does with the row
+    #[serde(flatten)]
+    pub action: RowAction,
+    /// The column each positional argument is stored in
+    #[serde(default)]
+    pub params: Vec<String>,
+}
+
+/// What a [`ReducerSchema`] does with the row built from its arguments
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RowAction {
+    /// Insert the row into the table
+    Insert(String),
+    /// Replace the row with the same primary key
+    Update(String),
+    /// Delete the row whose primary key is the first argument, if any
+    Delete(String),
+}
+
+impl ReducerSchema {
+    /// The parameter types, from the columns of the table the reducer changes
+    pub(crate) fn param_types(
+        &self,
+        tables: &BTreeMap<String, TableSchema>,
+    ) -> Option<Vec<Column>> {
+        let (RowAction::Insert(table) | RowAction::Update(table) | RowAction::Delete(table)) =
+            &self.action;
+        let columns = &tables.get(table)?.columns;
+        self.params
+            .iter()
+            .map(|param| columns.iter().find(|column| column.name == *param).cloned())
+            .collect()
+    }
+
+    pub(crate) fn run(&self, ctx: &mut ReducerContext, args: Value) -> Result<(), String> {
+        let row = match args {
+            Value::Object(row) => row,
+            Value::Array(values) if values.len() == self.params.len() => self
+                .params
+                .iter()
+                .cloned()
+                .zip(values)
+                .collect::<Map<_, _>>(),
+            args => {
+                return Err(format!(
+                    "expected {} arguments ({}), got {}",
+                    self.params.len(),
+                    self.params.join(", "),
+                    args
+                ));
+            }
+        };
+
+        match &self.action {
+            RowAction::Insert(table) => ctx.db.insert(table, row),
+            RowAction::Update(table) => ctx.db.update(table, row),
+            RowAction::Delete(table) => {
+                let key = self
+                    .params
+                    .first()
+                    .and_then(|param| row.get(param))
+                    .ok_or("delete reducers take the primary key as first parameter")?;
+                ctx.db.delete_by_key(table, key).map(|_| ())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Transaction;
+    use crate::store::Database;
+    use serde_json::json;
+
+    #[test]
+    fn test_row_reducers() {
+        let schema: Schema = serde_json::from_value(json!({
+            "tables": {
+                "player": { "primary_key": "id", "columns": [["id", "u32"], ["name", "string"]] },
+            },
+            "reducers": {
+                "create_player": { "insert": "player", "params": ["id", "name"] },
+                "delete_player": { "delete": "player", "params": ["id"] },
+            },
+        }))
+        .unwrap();
+        let mut ctx = ReducerContext {
+            sender: format!("{:064x}", 1),
+            connection_id: 1,
+            db: Transaction::new(Database {
+                tables: schema.tables.clone(),
+            }),
+        };
+        let create = &schema.reducers["create_player"];
+        let delete = &schema.reducers["delete_player"];
+        let params = create.param_types(&schema.tables).unwrap();
+        assert_eq!(params[1].ty, crate::ColumnType::String);
+
+        create.run(&mut ctx, json!([1, "Alice"])).unwrap();
+        create
+            .run(&mut ctx, json!({ "id": 2, "name": "Bob" }))
+            .unwrap();
+        assert!(create.run(&mut ctx, json!([3])).is_err());
+        delete.run(&mut ctx, json!([1])).unwrap();
+        assert_eq!(
+            ctx.db.rows("player").unwrap(),
+            [json!({ "id": 2, "name": "Bob" })]
+        );
+    }
+}
@@ -0,0 +1,1373 @@
+//! The WebSocket server and the state shared by its connections
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::Value;
+use tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tungstenite::http::{HeaderValue, StatusCode};
+use tungstenite::{Message, WebSocket};
+
+use crate::protocol::{
+    self, CallReducer, ClientMessage, DatabaseUpdate, OneOffQuery, OneOffTable, Protocol,
+    QueryUpdate, ReducerCallInfo, ServerMessage, SubscribeMulti, SubscriptionUpdate, TableUpdate,
+    UnsubscribeMulti, UpdateStatus,
+};
+use crate::sql::Query;
+use crate::store::{Database, TableChanges, TableSchema, Transaction};
+use crate::{Column, Schema, bsatn, types};
+
+/// How often connections check for outgoing messages and shutdown
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How long a closing connection waits for the client's close frame
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(1);
+
+type ReducerFn = Arc<dyn Fn(&mut ReducerContext, Value) -> Result<(), String> + Send + Sync>;
+
+/// What a reducer handler gets to work with
+pub struct ReducerContext {
+    /// The identity of the caller, as 64 hex digits
+    pub sender: String,
+    /// The connection of the caller
+    pub connection_id: u64,
+    /// The database, as seen by this call
+    pub db: Transaction,
+}
+
+impl ReducerContext {
+    /// The identity of the caller, encoded as in rows
+    pub fn sender_identity(&self) -> Value {
+        protocol::identity(&self.sender)
+    }
+}
+
+/// A stand-in SpacetimeDB server, to configure before listening
+///
+/// # Example
+/// ```no_run
+/// use bevy_spacetimedb_fake_server::FakeServer;
+/// use serde_json::json;
+///
+/// let server = FakeServer::new()
+///     .table_with_primary_key("player", "id")
+///     .reducer("create_player", |ctx, (id, name): (u32, String)| {
+///         ctx.db.insert("player", json!({ "id": id, "name": name }))
+///     })
+///     .listen("127.0.0.1:3000")
+///     .unwrap();
+/// println!("Listening on {}", server.url());
+/// server.wait();
+/// ```
+#[derive(Default)]
+pub struct FakeServer {
+    db: Database,
+    reducers: HashMap<String, ReducerFn>,
+    reducer_params: BTreeMap<String, Vec<Column>>,
+}
+
+impl FakeServer {
+    /// A server without tables or reducers
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A server with the tables and reducers of a schema
+    pub fn from_schema(schema: Schema) -> Self {
+        let mut server = Self::default();
+        for (name, reducer) in schema.reducers {
+            if let Some(params) = reducer.param_types(&schema.tables) {
+                server.reducer_params.insert(name.clone(), params);
+            }
+            server
+                .reducers
+                .insert(name, Arc::new(move |ctx, args| reducer.run(ctx, args)));
+        }
+        server.db.tables = schema.tables;
+        server
+    }
+
+    /// Add a table without a primary key
+    pub fn table(mut self, name: impl Into<String>) -> Self {
+        self.db.tables.insert(name.into(), TableSchema::default());
+        self
+    }
+
+    /// Add a table whose rows are identified by the `primary_key` column
+    pub fn table_with_primary_key(
+        mut self,
+        name: impl Into<String>,
+        primary_key: impl Into<String>,
+    ) -> Self {
+        self.db.tables.insert(
+            name.into(),
+            TableSchema {
+                primary_key: Some(primary_key.into()),
+                ..TableSchema::default()
+            },
+        );
+        self
+    }
+
+    /// Give the columns of a table their types, so binary clients can receive its rows
+    ///
+    /// Columns are `(name, type)` pairs in the order of the module's table,
+    /// e.g. `[("id", "u32"), ("name", "string")]`; see [`ColumnType`](crate::ColumnType).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the table does not exist or a type does not parse.
+    pub fn columns(mut self, table: &str, columns: &[(&str, &str)]) -> Self {
+        self.db
+            .tables
+            .get_mut(table)
+            .unwrap_or_else(|| panic!("no such table: {}", table))
+            .columns = types::columns(columns);
+        self
+    }
+
+    /// Give the parameters of a reducer names and types, so binary clients can call it
+    ///
+    /// Parameters are `(name, type)` pairs, as for [`FakeServer::columns`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if a type does not parse.
+    pub fn params(mut self, reducer: impl Into<String>, params: &[(&str, &str)]) -> Self {
+        self.reducer_params
+            .insert(reducer.into(), types::columns(params));
+        self
+    }
+
+    /// Add an initial row
+    ///
+    /// # Panics
+    ///
+    /// Panics if the table does not exist or the row does not serialize.
+    pub fn row(mut self, table: &str, row: impl Serialize) -> Self {
+        let row = serde_json::to_value(row).expect("row does not serialize to JSON");
+        self.db
+            .tables
+            .get_mut(table)
+            .unwrap_or_else(|| panic!("no such table: {}", table))
+            .rows
+            .push(row);
+        self
+    }
+
+    /// Handle calls to reducer `name`
+    ///
+    /// Arguments are deserialized from the JSON array the client sends, so a
+    /// tuple matches the reducer's parameters. Changes are only committed if
+    /// the handler returns `Ok`; an `Err` or a panic fails the call.
+    pub fn reducer<A: DeserializeOwned>(
+        mut self,
+        name: impl Into<String>,
+        handler: impl Fn(&mut ReducerContext, A) -> Result<(), String> + Send + Sync + 'static,
+    ) -> Self {
+        let name = name.into();
+        let reducer = name.clone();
+        self.reducers.insert(
+            name,
+            Arc::new(move |ctx, args| {
+                let args = serde_json::from_value(args)
+                    .map_err(|e| format!("invalid arguments for {}: {}", reducer, e))?;
+                handler(ctx, args)
+            }),
+        );
+        self
+    }
+
+    /// Start accepting connections on `addr`
+    ///
+    /// Use port 0 to pick a free port, see [`RunningServer::addr`].
+    pub fn listen(self, addr: impl ToSocketAddrs) -> io::Result<RunningServer> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        let addr = listener.local_addr()?;
+
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                db: self.db,
+                reducers: self.reducers,
+                reducer_params: self.reducer_params,
+                clients: BTreeMap::new(),
+                identities: HashMap::new(),
+                next_connection_id: 1,
+            }),
+            stop: AtomicBool::new(false),
+        });
+
+        let accepting = shared.clone();
+        let thread = thread::spawn(move || accept_loop(accepting, listener));
+
+        Ok(RunningServer {
+            addr,
+            shared,
+            thread: Some(thread),
+        })
+    }
+}
+
+/// A server accepting connections
+///
+/// Stops when dropped.
+pub struct RunningServer {
+    addr: SocketAddr,
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl RunningServer {
+    /// The address the server listens on
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// The URI to give `StdbPlugin::with_uri`
+    pub fn url(&self) -> String {
+        format!("ws://{}", self.addr)
+    }
+
+    /// The current rows of a table, or nothing if there is no such table
+    pub fn rows(&self, table: &str) -> Vec<Value> {
+        self.shared
+            .lock()
+            .db
+            .table(table)
+            .map(|table| table.rows.clone())
+            .unwrap_or_default()
+    }
+
+    /// Change the database outside of any reducer call
+    ///
+    /// Subscribed clients receive the changes as a transaction without a
+    /// reducer name.
+    pub fn transaction(
+        &self,
+        change: impl FnOnce(&mut Transaction) -> Result<(), String>,
+    ) -> Result<(), String> {
+        let mut state = self.shared.lock();
+        let mut tx = Transaction::new(state.db.clone());
+        change(&mut tx)?;
+        let call = ReducerCallInfo {
+            reducer_name: String::new(),
+            reducer_id: 0,
+            args: Value::Null,
+            request_id: 0,
+            params: None,
+        };
+        state.commit(tx, None, call);
+        Ok(())
+    }
+
+    /// The number of connected clients
+    pub fn client_count(&self) -> usize {
+        self.shared.lock().clients.len()
+    }
+
+    /// Close every connection, as if the server restarted
+    pub fn disconnect_clients(&self) {
+        for client in self.shared.lock().clients.values() {
+            let _ = client.outbox.send(Outgoing::Close);
+        }
+    }
+
+    /// Block until the server is stopped
+    pub fn wait(mut self) {
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for RunningServer {
+    fn drop(&mut self) {
+        self.shared.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+struct Shared {
+    state: Mutex<State>,
+    stop: AtomicBool,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+enum Outgoing {
+    Message(Message),
+    Close,
+}
+
+struct Client {
+    identity: String,
+    protocol: Protocol,
+    outbox: Sender<Outgoing>,
+    /// Queries by query id
+    subscriptions: BTreeMap<u32, Vec<Query>>,
+}
+
+impl Client {
+    fn covers(&self, table: &str, row: &Value) -> bool {
+        self.subscriptions
+            .values()
+            .flatten()
+            .any(|query| query.table == table && query.matches(row))
+    }
+
+    fn send(&self, message: &ServerMessage) {
+        let message = match self.protocol {
+            Protocol::Json => {
+                Message::text(serde_json::to_string(message).expect("server messages serialize"))
+            }
+            Protocol::Bsatn => match bsatn::encode_server_message(message) {
+                Ok(bytes) => Message::binary(bytes),
+                Err(e) => return eprintln!("fake-spacetimedb: can't send a binary message: {}", e),
+            },
+        };
+        let _ = self.outbox.send(Outgoing::Message(message));
+    }
+}
+
+struct State {
+    db: Database,
+    reducers: HashMap<String, ReducerFn>,
+    reducer_params: BTreeMap<String, Vec<Column>>,
+    clients: BTreeMap<u64, Client>,
+    /// Identities by token
+    identities: HashMap<String, String>,
+    next_connection_id: u64,
+}
+
+impl State {
+    /// Register a client, returning its connection id and outbox
+    fn connect(&mut self, token: Option<String>, protocol: Protocol) -> (u64, Receiver<Outgoing>) {
+        let connection_id = self.next_connection_id;
+        self.next_connection_id += 1;
+
+        // Any token is accepted; the same token always gets the same identity
+        let token = token.unwrap_or_else(|| format!("fake-token-{}", connection_id));
+        let next_identity = self.identities.len() + 1;
+        let identity = self
+            .identities
+            .entry(token.clone())
+            .or_insert_with(|| format!("{:064x}", next_identity))
+            .clone();
+
+        let (outbox, receiver) = mpsc::channel();
+        let client = Client {
+            identity: identity.clone(),
+            protocol,
+            outbox,
+            subscriptions: BTreeMap::new(),
+        };
+        client.send(&ServerMessage::IdentityToken {
+            identity: protocol::identity(&identity),
+            token,
+            connection_id: protocol::connection_id(connection_id),
+        });
+        self.clients.insert(connection_id, client);
+
+        (connection_id, receiver)
+    }
+
+    fn handle(&mut self, connection_id: u64, message: ClientMessage) {
+        match message {
+            ClientMessage::CallReducer(call) => self.call_reducer(connection_id, call),
+            ClientMessage::SubscribeMulti(subscribe) => self.subscribe(connection_id, subscribe),
+            ClientMessage::UnsubscribeMulti(unsubscribe) => {
+                self.unsubscribe(connection_id, unsubscribe)
+            }
+            ClientMessage::OneOffQuery(query) => self.one_off_query(connection_id, query),
+        }
+    }
+
+    fn call_reducer(&mut self, connection_id: u64, call: CallReducer) {
+        let Some(caller) = self.clients.get(&connection_id) else {
+            return;
+        };
+        let args = match call.args {
+            Value::String(json) => serde_json::from_str(&json).unwrap_or(Value::String(json)),
+            args => args,
+        };
+
+        let result = match self.reducers.get(&call.reducer).cloned() {
+            None => Err(format!("no such reducer: {}", call.reducer)),
+            Some(reducer) => {
+                let mut ctx = ReducerContext {
+                    sender: caller.identity.clone(),
+                    connection_id,
+                    db: Transaction::new(self.db.clone()),
+                };
+                let args = args.clone();
+                match panic::catch_unwind(AssertUnwindSafe(|| reducer(&mut ctx, args))) {
+                    Ok(result) => result.map(|()| ctx.db),
+                    Err(_) => Err(format!("reducer {} panicked", call.reducer)),
+                }
+            }
+        };
+
+        let info = ReducerCallInfo {
+            params: self.reducer_params.get(&call.reducer).cloned(),
+            reducer_name: call.reducer,
+            reducer_id: 0,
+            args,
+            request_id: call.request_id,
+        };
+        match result {
+            Ok(tx) => self.commit(tx, Some(connection_id), info),
+            Err(error) => {
+                let identity = caller.identity.clone();
+                self.clients[&connection_id].send(&transaction_update(
+                    UpdateStatus::Failed(error),
+                    &identity,
+                    connection_id,
+                    info,
+                ));
+            }
+        }
+    }
+
+    /// Apply a transaction and send each client the changes it subscribed to
+    ///
+    /// The caller is always told the outcome of its call, even if it sees no rows.
+    fn commit(&mut self, tx: Transaction, caller: Option<u64>, call: ReducerCallInfo) {
+        let (db, changes) = tx.finish();
+        self.db = db;
+
+        let caller_identity = caller
+            .and_then(|id| self.clients.get(&id))
+            .map_or_else(|| format!("{:064x}", 0), |client| client.identity.clone());
+        let caller_connection_id = caller.unwrap_or(0);
+
+        for (&connection_id, client) in &self.clients {
+            let update = self.database_update(&changes, |table, row| client.covers(table, row));
+            if update.tables.is_empty() && caller != Some(connection_id) {
+                continue;
+            }
+            let info = ReducerCallInfo {
+                reducer_name: call.reducer_name.clone(),
+                reducer_id: call.reducer_id,
+                args: call.args.clone(),
+                // Request ids are only meaningful to the caller
+                request_id: if caller == Some(connection_id) {
+                    call.request_id
+                } else {
+                    0
+                },
+                params: call.params.clone(),
+            };
+            client.send(&transaction_update(
+                UpdateStatus::Committed(update),
+                &caller_identity,
+                caller_connection_id,
+                info,
+            ));
+        }
+    }
+
+    fn subscribe(&mut self, connection_id: u64, subscribe: SubscribeMulti) {
+        let Some(client) = self.clients.get(&connection_id) else {
+            return;
+        };
+        let query_id = subscribe.query_id;
+
+        let queries = subscribe
+            .query_strings
+            .iter()
+            .map(|sql| {
+                let query = Query::parse(sql)?;
+                let table = self.db.table(&query.table)?;
+                if client.protocol == Protocol::Bsatn && table.columns.is_empty() {
+                    return Err(format!(
+                        "table {} has no column types, which binary clients need",
+                        query.table
+                    ));
+                }
+                Ok(query)
+            })
+            .collect::<Result<Vec<_>, String>>()
+            .and_then(|queries| {
+                if client.subscriptions.contains_key(&query_id.id) {
+                    Err(format!("query id {} is already in use", query_id.id))
+                } else {
+                    Ok(queries)
+                }
+            });
+        let queries = match queries {
+            Ok(queries) => queries,
+            Err(error) => {
+                return client.send(&subscription_error(
+                    subscribe.request_id,
+                    query_id.id,
+                    error,
+                ));
+            }
+        };
+
+        // Only rows the client does not have yet
+        let rows = self.matching_rows(&queries, |table, row| !client.covers(table, row));
+        let update = self.database_update(&rows, |_, _| true);
+        client.send(&ServerMessage::SubscribeMultiApplied(SubscriptionUpdate {
+            request_id: subscribe.request_id,
+            total_host_execution_duration_micros: 0,
+            query_id,
+            update,
+        }));
+
+        self.clients
+            .get_mut(&connection_id)
+            .unwrap()
+            .subscriptions
+            .insert(query_id.id, queries);
+    }
+
+    fn unsubscribe(&mut self, connection_id: u64, unsubscribe: UnsubscribeMulti) {
+        let Some(client) = self.clients.get_mut(&connection_id) else {
+            return;
+        };
+        let query_id = unsubscribe.query_id;
+        let Some(queries) = client.subscriptions.remove(&query_id.id) else {
+            let error = format!("no subscription with query id {}", query_id.id);
+            return self.clients[&connection_id].send(&subscription_error(
+                unsubscribe.request_id,
+                query_id.id,
+                error,
+            ));
+        };
+
+        // Only rows no other subscription covers
+        let client = &self.clients[&connection_id];
+        let rows = self.matching_rows(&queries, |table, row| !client.covers(table, row));
+        let update = self.database_update(
+            &rows
+                .into_iter()
+                .map(|(table, changes)| {
+                    let deletes = changes.inserts;
+                    (
+                        table,
+                        TableChanges {
+                            inserts: Vec::new(),
+                            deletes,
+                        },
+                    )
+                })
+                .collect(),
+            |_, _| true,
+        );
+        client.send(&ServerMessage::UnsubscribeMultiApplied(
+            SubscriptionUpdate {
+                request_id: unsubscribe.request_id,
+                total_host_execution_duration_micros: 0,
+                query_id,
+                update,
+            },
+        ));
+    }
+
+    fn one_off_query(&self, connection_id: u64, query: OneOffQuery) {
+        let Some(client) = self.clients.get(&connection_id) else {
+            return;
+        };
+        let result = Query::parse(&query.query_string).and_then(|parsed| {
+            let table = self.db.table(&parsed.table)?;
+            let rows = table
+                .rows
+                .iter()
+                .filter(|row| parsed.matches(row))
+                .cloned()
+                .collect();
+            Ok(OneOffTable {
+                table_name: parsed.table,
+                rows,
+                columns: table.columns.clone(),
+            })
+        });
+        let (error, tables) = match result {
+            Ok(table) => (None, vec![table]),
+            Err(error) => (Some(error), Vec::new()),
+        };
+        client.send(&ServerMessage::OneOffQueryResponse {
+            message_id: query.message_id,
+            error,
+            tables,
+            total_host_execution_duration: protocol::zero_duration(),
+        });
+    }
+
+    /// The rows matching any of `queries` and `filter`, as inserts
+    fn matching_rows(
+        &self,
+        queries: &[Query],
+        filter: impl Fn(&str, &Value) -> bool,
+    ) -> BTreeMap<String, TableChanges> {
+        let mut rows = BTreeMap::<String, TableChanges>::new();
+        for (name, table) in &self.db.tables {
+            for row in &table.rows {
+                let selected = queries
+                    .iter()
+                    .any(|query| query.table == *name && query.matches(row));
+                if selected && filter(name, row) {
+                    rows.entry(name.clone())
+                        .or_default()
+                        .inserts
+                        .push(row.clone());
+                }
+            }
+        }
+        rows
+    }
+
+    /// The changes to the rows selected by `visible`
+    fn database_update(
+        &self,
+        changes: &BTreeMap<String, TableChanges>,
+        visible: impl Fn(&str, &Value) -> bool,
+    ) -> DatabaseUpdate {
+        let mut update = DatabaseUpdate::default();
+        for (table_id, (name, table)) in self.db.tables.iter().enumerate() {
+            let Some(changes) = changes.get(name) else {
+                continue;
+            };
+            let select = |rows: &[Value]| {
+                rows.iter()
+                    .filter(|row| visible(name, row))
+                    .cloned()
+                    .collect::<Vec<_>>()
+            };
+            let inserts = select(&changes.inserts);
+            let deletes = select(&changes.deletes);
+            if inserts.is_empty() && deletes.is_empty() {
+                continue;
+            }
+            update.tables.push(TableUpdate {
+                table_id: table_id as u32,
+                table_name: name.clone(),
+                primary_key: table.primary_key.clone(),
+                num_rows: table.rows.len() as u64,
+                updates: vec![QueryUpdate { deletes, inserts }],
+                columns: table.columns.clone(),
+            });
+        }
+        update
+    }
+}
+
+fn transaction_update(
+    status: UpdateStatus,
+    caller_identity: &str,
+    caller_connection_id: u64,
+    reducer_call: ReducerCallInfo,
+) -> ServerMessage {
+    ServerMessage::TransactionUpdate {
+        status,
+        timestamp: protocol::timestamp_now(),
+        caller_identity: protocol::identity(caller_identity),
+        caller_connection_id: protocol::connection_id(caller_connection_id),
+        reducer_call,
+        energy_quanta_used: protocol::energy(),
+        total_host_execution_duration: protocol::zero_duration(),
+    }
+}
+
+fn subscription_error(request_id: u32, query_id: u32, error: String) -> ServerMessage {
+    ServerMessage::SubscriptionError {
+        total_host_execution_duration_micros: 0,
+        request_id: Some(request_id),
+        query_id: Some(query_id),
+        table_id: None,
+        error,
+    }
+}
+
+fn accept_loop(shared: Arc<Shared>, listener: TcpListener) {
+    while !shared.stop.load(Ordering::Relaxed) {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                let shared = shared.clone();
+                thread::spawn(move || serve_client(shared, stream));
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(POLL_INTERVAL),
+            Err(e) => eprintln!("fake-spacetimedb: failed to accept a connection: {}", e),
+        }
+    }
+}
+
+/// Check the request of a WebSocket handshake, returning the client's token and protocol
+fn check_request(
+    request: &Request,
+    response: &mut Response,
+) -> Result<(Option<String>, Protocol), String> {
+    let path = request.uri().path();
+    let segments = path.trim_matches('/').split('/').collect::<Vec<_>>();
+    let is_subscribe = matches!(
+        segments.as_slice(),
+        ["v1", "database", _, "subscribe"] | ["database", "subscribe", _]
+    );
+    if !is_subscribe {
+        return Err(format!(
+            "not a subscribe endpoint: {} (expected /v1/database/<module>/subscribe)",
+            path
+        ));
+    }
+
+    let protocols = request
+        .headers()
+        .get_all("Sec-WebSocket-Protocol")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .collect::<Vec<_>>();
+    let chosen = protocols
+        .iter()
+        .find_map(|name| Protocol::from_subprotocol(name));
+    if let Some(chosen) = chosen {
+        response.headers_mut().insert(
+            "Sec-WebSocket-Protocol",
+            HeaderValue::from_static(chosen.subprotocol()),
+        );
+    } else if !protocols.is_empty() {
+        return Err(format!(
+            "unsupported protocols {:?}, expected {} or {}",
+            protocols,
+            protocol::SUBPROTOCOL,
+            protocol::BSATN_SUBPROTOCOL
+        ));
+    }
+
+    let bearer = request
+        .headers()
+        .get("Authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::to_string);
+    let query_token = request.uri().query().and_then(|query| {
+        query
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("token="))
+            .map(str::to_string)
+    });
+    Ok((bearer.or(query_token), chosen.unwrap_or(Protocol::Json)))
+}
+
+// The handshake callback's error type is set by tungstenite
+#[allow(clippy::result_large_err)]
+fn serve_client(shared: Arc<Shared>, stream: TcpStream) {
+    match peek_request(&stream) {
+        Ok(head) if !is_upgrade(&head) => return serve_http(&shared, stream, &head),
+        Ok(_) => {}
+        Err(e) => return eprintln!("fake-spacetimedb: failed to read a request: {}", e),
+    }
+
+    let mut token = None;
+    let mut protocol = Protocol::Json;
+    let handshake = stream
+        .set_read_timeout(None)
+        .map_err(|e| e.to_string())
+        .and_then(|()| {
+            tungstenite::accept_hdr(stream, |request: &Request, mut response: Response| {
+                match check_request(request, &mut response) {
+                    Ok(found) => {
+                        (token, protocol) = found;
+                        Ok(response)
+                    }
+                    Err(error) => {
+                        let mut response = ErrorResponse::new(Some(error));
+                        *response.status_mut() = StatusCode::BAD_REQUEST;
+                        Err(response)
+                    }
+                }
+            })
+            .map_err(|e| e.to_string())
+        });
+    let mut socket = match handshake {
+        Ok(socket) => socket,
+        Err(e) => return eprintln!("fake-spacetimedb: rejected a connection: {}", e),
+    };
+    if let Err(e) = socket.get_ref().set_read_timeout(Some(POLL_INTERVAL)) {
+        return eprintln!("fake-spacetimedb: {}", e);
+    }
+
+    let (connection_id, outbox) = shared.lock().connect(token, protocol);
+    run_connection(&shared, connection_id, &mut socket, &outbox);
+    shared.lock().clients.remove(&connection_id);
+}
+
+/// The head of the request waiting on `stream`, without consuming it
+fn peek_request(stream: &TcpStream) -> io::Result<String> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(CLOSE_TIMEOUT))?;
+    let mut buffer = [0; 8192];
+    let started = Instant::now();
+    loop {
+        let read = stream.peek(&mut buffer)?;
+        let head = &buffer[..read];
+        if let Some(end) = head.windows(4).position(|window| window == b"\r\n\r\n") {
+            return Ok(String::from_utf8_lossy(&head[..end + 4]).into_owned());
+        }
+        if read == 0 || read == buffer.len() || started.elapsed() > CLOSE_TIMEOUT {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "incomplete request head",
+            ));
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+fn header<'a>(head: &'a str, name: &str) -> Option<&'a str> {
+    head.lines().skip(1).find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        key.trim().eq_ignore_ascii_case(name).then(|| value.trim())
+    })
+}
+
+fn is_upgrade(head: &str) -> bool {
+    header(head, "Upgrade").is_some_and(|value| value.eq_ignore_ascii_case("websocket"))
+}
+
+/// Answer the plain HTTP endpoints the SDKs use before connecting
+///
+/// - `GET /v1/database/<module>/schema`: the module definition, built from the
+///   column and parameter types
+/// - `POST /v1/identity/websocket-token`: the bearer token itself, which the
+///   SDKs then connect with
+fn serve_http(shared: &Shared, mut stream: TcpStream, head: &str) {
+    // Consume the request
+    let body = header(head, "Content-Length").and_then(|len| len.parse::<usize>().ok());
+    let mut consumed = vec![0; head.len() + body.unwrap_or(0)];
+    let _ = stream.read_exact(&mut consumed);
+
+    let mut request_line = head.lines().next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().unwrap_or_default();
+    let path = request_line
+        .next()
+        .unwrap_or_default()
+        .split('?')
+        .next()
+        .unwrap_or_default();
+    let segments = path.trim_matches('/').split('/').collect::<Vec<_>>();
+
+    let (status, body) = match (method, segments.as_slice()) {
+        ("OPTIONS", _) => ("204 No Content", String::new()),
+        ("GET", ["v1", "database", _, "schema"]) => {
+            let state = shared.lock();
+            let def = types::module_def(&state.db.tables, &state.reducer_params);
+            ("200 OK", def.to_string())
+        }
+        ("POST", ["v1", "identity", "websocket-token"]) => {
+            match header(head, "Authorization").and_then(|value| value.strip_prefix("Bearer ")) {
+                Some(token) => ("200 OK", serde_json::json!({ "token": token }).to_string()),
+                None => ("401 Unauthorized", "a bearer token is required".to_string()),
+            }
+        }
+        _ => (
+            "404 Not Found",
+            format!("no such endpoint: {} {}", method, path),
+        ),
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\n\
+         Content-Type: application/json\r\n\
+         Content-Length: {}\r\n\
+         Access-Control-Allow-Origin: *\r\n\
+         Access-Control-Allow-Headers: Authorization, Content-Type\r\n\
+         Access-Control-Allow-Methods: GET, POST\r\n\
+         Connection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    let _ = stream.write_all(response.as_bytes());
+}
+
+fn run_connection(
+    shared: &Shared,
+    connection_id: u64,
+    socket: &mut WebSocket<TcpStream>,
+    outbox: &Receiver<Outgoing>,
+) {
+    let mut closing_since = None;
+    loop {
+        if closing_since.is_none() && shared.stop.load(Ordering::Relaxed) {
+            let _ = socket.close(None);
+            closing_since = Some(Instant::now());
+        }
+        if closing_since.is_some_and(|since| since.elapsed() > CLOSE_TIMEOUT) {
+            return;
+        }
+
+        while let Ok(outgoing) = outbox.try_recv() {
+            let result = match outgoing {
+                Outgoing::Message(message) if closing_since.is_none() => socket.send(message),
+                Outgoing::Message(_) => Ok(()),
+                Outgoing::Close => {
+                    closing_since.get_or_insert_with(Instant::now);
+                    socket.close(None)
+                }
+            };
+            if result.is_err() {
+                return;
+            }
+        }
+
+        match socket.read() {
+            Ok(Message::Text(text)) => match serde_json::from_str::<ClientMessage>(&text) {
+                Ok(message) => shared.lock().handle(connection_id, message),
+                Err(e) => eprintln!(
+                    "fake-spacetimedb: ignoring invalid message from connection {}: {}",
+                    connection_id, e
+                ),
+            },
+            Ok(Message::Binary(bytes)) => {
+                let mut state = shared.lock();
+                let params = |reducer: &str| state.reducer_params.get(reducer).map(Vec::as_slice);
+                match bsatn::decode_client_message(&bytes, params) {
+                    Ok(message) => state.handle(connection_id, message),
+                    Err(e) => eprintln!(
+                        "fake-spacetimedb: ignoring invalid message from connection {}: {}",
+                        connection_id, e
+                    ),
+                }
+            }
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(e))
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) => {}
+            Err(_) => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tungstenite::client::IntoClientRequest;
+    use tungstenite::stream::MaybeTlsStream;
+
+    type Client = WebSocket<MaybeTlsStream<TcpStream>>;
+
+    fn server() -> RunningServer {
+        FakeServer::new()
+            .table_with_primary_key("player", "id")
+            .columns("player", &[("id", "u32"), ("name", "string")])
+            .row("player", json!({ "id": 1, "name": "Alice" }))
+            .reducer("create_player", |ctx, (id, name): (u32, String)| {
+                ctx.db.insert("player", json!({ "id": id, "name": name }))
+            })
+            .params("create_player", &[("id", "u32"), ("name", "string")])
+            .reducer("rename_all", |ctx, (name,): (String,)| {
+                for row in ctx.db.rows("player")?.to_vec() {
+                    ctx.db
+                        .update("player", json!({ "id": row["id"], "name": name }))?;
+                }
+                Err("renaming is not allowed".to_string())
+            })
+            .reducer("crash", |_, _: Vec<Value>| panic!("crash"))
+            .listen("127.0.0.1:0")
+            .unwrap()
+    }
+
+    fn connect(server: &RunningServer, query: &str) -> (Client, Value) {
+        let mut request = format!("ws://{}/v1/database/test/subscribe{}", server.addr(), query)
+            .into_client_request()
+            .unwrap();
+        request.headers_mut().insert(
+            "Sec-WebSocket-Protocol",
+            HeaderValue::from_static(protocol::SUBPROTOCOL),
+        );
+        let (mut client, _) = tungstenite::connect(request).unwrap();
+        if let MaybeTlsStream::Plain(stream) = client.get_ref() {
+            stream
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+        }
+        let identity = receive(&mut client)["IdentityToken"].clone();
+        (client, identity)
+    }
+
+    fn send(client: &mut Client, message: Value) {
+        client.send(Message::text(message.to_string())).unwrap();
+    }
+
+    fn receive(client: &mut Client) -> Value {
+        loop {
+            if let Message::Text(text) = client.read().unwrap() {
+                return serde_json::from_str(&text).unwrap();
+            }
+        }
+    }
+
+    fn subscribe(client: &mut Client, id: u32, queries: &[&str]) -> Value {
+        send(
+            client,
+            json!({ "SubscribeMulti": {
+                "query_strings": queries,
+                "request_id": id,
+                "query_id": { "id": id },
+            }}),
+        );
+        receive(client)
+    }
+
+    fn call(client: &mut Client, reducer: &str, args: Value, request_id: u32) -> Value {
+        send(
+            client,
+            json!({ "CallReducer": {
+                "reducer": reducer,
+                "args": args,
+                "request_id": request_id,
+                "flags": 0,
+            }}),
+        );
+        receive(client)["TransactionUpdate"].clone()
+    }
+
+    /// The rows inserted and deleted in `table` by a committed transaction update
+    fn changes(update: &Value, table: &str) -> (Value, Value) {
+        let tables = update["status"]["Committed"]["tables"].as_array().unwrap();
+        tables
+            .iter()
+            .find(|update| update["table_name"] == table)
+            .map_or((json!([]), json!([])), |update| {
+                let rows = &update["updates"][0];
+                (rows["inserts"].clone(), rows["deletes"].clone())
+            })
+    }
+
+    #[test]
+    fn test_subscriptions_receive_reducer_changes() {
+        let server = server();
+        let (mut alice, identity) = connect(&server, "");
+        let (mut bob, _) = connect(&server, "");
+        assert_eq!(
+            identity["identity"]["__identity__"].as_str().unwrap().len(),
+            64
+        );
+
+        let applied = subscribe(&mut alice, 1, &["SELECT * FROM player WHERE id < 10"]);
+        let applied = &applied["SubscribeMultiApplied"];
+        assert_eq!(applied["query_id"]["id"], 1);
+        let table = &applied["update"]["tables"][0];
+        assert_eq!(table["primary_key"], "id");
+        assert_eq!(
+            table["updates"][0]["inserts"],
+            json!([{ "id": 1, "name": "Alice" }])
+        );
+        subscribe(&mut bob, 1, &["SELECT * FROM player"]);
+
+        let update = call(&mut alice, "create_player", json!([2, "Bob"]), 7);
+        assert_eq!(update["reducer_call"]["request_id"], 7);
+        assert_eq!(update["caller_identity"], identity["identity"]);
+        assert_eq!(
+            changes(&update, "player").0,
+            json!([{ "id": 2, "name": "Bob" }])
+        );
+        assert_eq!(
+            receive(&mut bob)["TransactionUpdate"]["reducer_call"]["request_id"],
+            0
+        );
+
+        // Outside Alice's subscription: she only learns that the call committed
+        let update = call(&mut alice, "create_player", "[20, \"Zed\"]".into(), 8);
+        assert_eq!(changes(&update, "player"), (json!([]), json!([])));
+        let update = receive(&mut bob)["TransactionUpdate"].clone();
+        assert_eq!(
+            changes(&update, "player").0,
+            json!([{ "id": 20, "name": "Zed" }])
+        );
+
+        server
+            .transaction(|tx| tx.update("player", json!({ "id": 2, "name": "Robert" })))
+            .unwrap();
+        let update = receive(&mut alice)["TransactionUpdate"].clone();
+        assert_eq!(update["reducer_call"]["reducer_name"], "");
+        assert_eq!(
+            changes(&update, "player"),
+            (
+                json!([{ "id": 2, "name": "Robert" }]),
+                json!([{ "id": 2, "name": "Bob" }])
+            )
+        );
+        assert_eq!(server.rows("player").len(), 3);
+    }
+
+    #[test]
+    fn test_failed_reducers_change_nothing() {
+        let server = server();
+        let (mut client, identity) = connect(&server, "");
+        subscribe(&mut client, 1, &["SELECT * FROM player"]);
+
+        let update = call(&mut client, "rename_all", json!(["Eve"]), 1);
+        assert_eq!(update["status"]["Failed"], "renaming is not allowed");
+        assert_eq!(update["caller_identity"], identity["identity"]);
+        let update = call(&mut client, "create_player", json!([1, "Alice"]), 2);
+        assert!(
+            update["status"]["Failed"]
+                .as_str()
+                .unwrap()
+                .contains("duplicate")
+        );
+        let update = call(&mut client, "create_player", json!(["Eve"]), 3);
+        assert!(
+            update["status"]["Failed"]
+                .as_str()
+                .unwrap()
+                .contains("invalid arguments")
+        );
+        let update = call(&mut client, "crash", json!([]), 4);
+        assert_eq!(update["status"]["Failed"], "reducer crash panicked");
+        let update = call(&mut client, "missing", json!([]), 5);
+        assert_eq!(update["status"]["Failed"], "no such reducer: missing");
+
+        assert_eq!(
+            server.rows("player"),
+            vec![json!({ "id": 1, "name": "Alice" })]
+        );
+    }
+
+    #[test]
+    fn test_unsubscribe_and_queries() {
+        let server = server();
+        let (mut client, _) = connect(&server, "");
+        call(&mut client, "create_player", json!([2, "Bob"]), 1);
+
+        subscribe(&mut client, 1, &["SELECT * FROM player WHERE id = 1"]);
+        let applied = subscribe(&mut client, 2, &["SELECT * FROM player"]);
+        // Alice is already covered by the first subscription
+        let inserts =
+            &applied["SubscribeMultiApplied"]["update"]["tables"][0]["updates"][0]["inserts"];
+        assert_eq!(*inserts, json!([{ "id": 2, "name": "Bob" }]));
+
+        send(
+            &mut client,
+            json!({ "UnsubscribeMulti": { "request_id": 3, "query_id": { "id": 2 } } }),
+        );
+        let ended = receive(&mut client);
+        let deletes =
+            &ended["UnsubscribeMultiApplied"]["update"]["tables"][0]["updates"][0]["deletes"];
+        assert_eq!(*deletes, json!([{ "id": 2, "name": "Bob" }]));
+
+        let error = subscribe(&mut client, 4, &["SELECT * FROM nope"]);
+        assert_eq!(error["SubscriptionError"]["error"], "no such table: nope");
+        assert_eq!(error["SubscriptionError"]["query_id"], 4);
+
+        send(
+            &mut client,
+            json!({ "OneOffQuery": { "message_id": [1, 2], "query_string": "SELECT * FROM player WHERE name = 'Bob'" } }),
+        );
+        let response = receive(&mut client);
+        let response = &response["OneOffQueryResponse"];
+        assert_eq!(response["message_id"], json!([1, 2]));
+        assert_eq!(response["error"], Value::Null);
+        assert_eq!(
+            response["tables"][0]["rows"],
+            json!([{ "id": 2, "name": "Bob" }])
+        );
+    }
+
+    #[test]
+    fn test_connections() {
+        let server = server();
+        let (_, first) = connect(&server, "?token=secret");
+        let (_, second) = connect(&server, "?token=secret");
+        let (mut other, third) = connect(&server, "");
+        assert_eq!(first["token"], "secret");
+        assert_eq!(first["identity"], second["identity"]);
+        assert_ne!(first["identity"], third["identity"]);
+        assert_ne!(first["connection_id"], second["connection_id"]);
+
+        let mut request = format!("ws://{}/v1/database/test/subscribe", server.addr())
+            .into_client_request()
+            .unwrap();
+        request.headers_mut().insert(
+            "Sec-WebSocket-Protocol",
+            HeaderValue::from_static("v2.bsatn.spacetimedb"),
+        );
+        assert!(tungstenite::connect(request).is_err());
+
+        server.disconnect_clients();
+        assert!(matches!(other.read(), Ok(Message::Close(_))));
+    }
+
+    fn connect_binary(server: &RunningServer) -> Client {
+        let mut request = format!("ws://{}/v1/database/test/subscribe", server.addr())
+            .into_client_request()
+            .unwrap();
+        request.headers_mut().insert(
+            "Sec-WebSocket-Protocol",
+            HeaderValue::from_static(protocol::BSATN_SUBPROTOCOL),
+        );
+        let (client, response) = tungstenite::connect(request).unwrap();
+        assert_eq!(
+            response.headers()["Sec-WebSocket-Protocol"],
+            protocol::BSATN_SUBPROTOCOL
+        );
+        if let MaybeTlsStream::Plain(stream) = client.get_ref() {
+            stream
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+        }
+        client
+    }
+
+    /// The next binary message, after its compression byte
+    fn receive_binary(client: &mut Client) -> Vec<u8> {
+        loop {
+            if let Message::Binary(bytes) = client.read().unwrap() {
+                assert_eq!(bytes[0], 0);
+                return bytes[1..].to_vec();
+            }
+        }
+    }
+
+    /// Read a `BsatnRowList`
+    fn read_rows(reader: &mut bsatn::Reader<'_>, columns: &[Column]) -> Vec<Value> {
+        assert_eq!(reader.u8().unwrap(), 1);
+        let count = reader.u32().unwrap();
+        for _ in 0..count {
+            reader.u64().unwrap();
+        }
+        let mut data = bsatn::Reader::new(reader.bytes().unwrap());
+        let rows = (0..count)
+            .map(|_| bsatn::read_row(&mut data, columns).unwrap())
+            .collect();
+        data.finish().unwrap();
+        rows
+    }
+
+    /// Read a `DatabaseUpdate` of one table, as its deletes and inserts
+    fn read_update(reader: &mut bsatn::Reader<'_>, columns: &[Column]) -> (Vec<Value>, Vec<Value>) {
+        assert_eq!(reader.u32().unwrap(), 1);
+        reader.u32().unwrap();
+        assert_eq!(reader.string().unwrap(), "player");
+        reader.u64().unwrap();
+        assert_eq!(reader.u32().unwrap(), 1);
+        assert_eq!(reader.u8().unwrap(), 0);
+        (read_rows(reader, columns), read_rows(reader, columns))
+    }
+
+    #[test]
+    fn test_binary_clients() {
+        let server = server();
+        let columns = types::columns(&[("id", "u32"), ("name", "string")]);
+        let mut client = connect_binary(&server);
+
+        let token = receive_binary(&mut client);
+        let mut reader = bsatn::Reader::new(&token);
+        assert_eq!(reader.u8().unwrap(), 3);
+        reader.array::<32>().unwrap();
+        assert!(reader.string().unwrap().starts_with("fake-token-"));
+        reader.array::<16>().unwrap();
+        reader.finish().unwrap();
+
+        let mut subscribe = vec![4];
+        subscribe.extend(1u32.to_le_bytes());
+        let query = b"SELECT * FROM player";
+        subscribe.extend((query.len() as u32).to_le_bytes());
+        subscribe.extend(query);
+        subscribe.extend(1u32.to_le_bytes());
+        subscribe.extend(2u32.to_le_bytes());
+        client.send(Message::binary(subscribe)).unwrap();
+        let applied = receive_binary(&mut client);
+        let mut reader = bsatn::Reader::new(&applied);
+        assert_eq!(reader.u8().unwrap(), 8);
+        assert_eq!(reader.u32().unwrap(), 1);
+        reader.u64().unwrap();
+        assert_eq!(reader.u32().unwrap(), 2);
+        let (_, inserts) = read_update(&mut reader, &columns);
+        assert_eq!(inserts, [json!({ "id": 1, "name": "Alice" })]);
+        reader.finish().unwrap();
+
+        let call = |reducer: &str, args: &[u8], request_id: u32| {
+            let mut message = vec![0];
+            message.extend((reducer.len() as u32).to_le_bytes());
+            message.extend(reducer.as_bytes());
+            message.extend((args.len() as u32).to_le_bytes());
+            message.extend(args);
+            message.extend(request_id.to_le_bytes());
+            message.push(0);
+            Message::binary(message)
+        };
+        let mut args = 2u32.to_le_bytes().to_vec();
+        args.extend(3u32.to_le_bytes());
+        args.extend(b"Bob");
+        client.send(call("create_player", &args, 7)).unwrap();
+        let update = receive_binary(&mut client);
+        let mut reader = bsatn::Reader::new(&update);
+        assert_eq!(reader.u8().unwrap(), 1);
+        assert_eq!(reader.u8().unwrap(), 0);
+        let (deletes, inserts) = read_update(&mut reader, &columns);
+        assert!(deletes.is_empty());
+        assert_eq!(inserts, [json!({ "id": 2, "name": "Bob" })]);
+        reader.array::<{ 8 + 32 + 16 }>().unwrap();
+        assert_eq!(reader.string().unwrap(), "create_player");
+        reader.u32().unwrap();
+        assert_eq!(reader.bytes().unwrap(), args);
+        assert_eq!(reader.u32().unwrap(), 7);
+        reader.array::<{ 16 + 8 }>().unwrap();
+        reader.finish().unwrap();
+
+        // Without parameter types the arguments can't be read
+        client.send(call("rename_all", &[], 8)).unwrap();
+        let update = receive_binary(&mut client);
+        let mut reader = bsatn::Reader::new(&update);
+        assert_eq!(reader.u8().unwrap(), 1);
+        assert_eq!(reader.u8().unwrap(), 1);
+        assert!(reader.string().unwrap().contains("invalid arguments"));
+        assert_eq!(server.rows("player").len(), 2);
+    }
+
+    fn http(server: &RunningServer, request: &str) -> (String, String) {
+        let mut stream = TcpStream::connect(server.addr()).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        (head.lines().next().unwrap().to_string(), body.to_string())
+    }
+
+    #[test]
+    fn test_http_endpoints() {
+        let server = server();
+        let (status, body) = http(
+            &server,
+            "GET /v1/database/test/schema?version=9 HTTP/1.1\r\nHost: localhost\r\n\r\n",
+        );
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        let def: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(def["tables"][0]["name"], "player");
+        assert_eq!(def["reducers"][0]["name"], "create_player");
+        assert_eq!(def["reducers"].as_array().unwrap().len(), 1);
+
+        let (status, body) = http(
+            &server,
+            "POST /v1/identity/websocket-token HTTP/1.1\r\n\
+             Authorization: Bearer secret\r\nContent-Length: 0\r\n\r\n",
+        );
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        assert_eq!(body, r#"{"token":"secret"}"#);
+
+        let (status, _) = http(&server, "GET /nope HTTP/1.1\r\n\r\n");
+        assert_eq!(status, "HTTP/1.1 404 Not Found");
+    }
+}
@@ -0,0 +1,489 @@
+//! The subset of SQL the server understands
+//!
+//! `SELECT * FROM table [WHERE condition]`, where conditions compare columns
+//! and literals with `=`, `!=`, `<>`, `<`, `<=`, `>`, `>=` and combine them
+//! with `AND`, `OR`, `NOT` and parentheses. This covers the queries built by
+//! `StdbQuery` in `bevy_spacetimedb_wasm`.
+
+use std::cmp::Ordering;
+
+use serde_json::Value;
+
+/// A parsed `SELECT` query
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Query {
+    pub(crate) table: String,
+    filter: Option<Expr>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare(Operand, CompareOp, Operand),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    Column(String),
+    Literal(Scalar),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// A value as compared by conditions
+#[derive(Debug, Clone, PartialEq)]
+enum Scalar {
+    Int(i128),
+    Float(f64),
+    Str(String),
+    Bool(bool),
+    /// A hex literal, or an identity column, as lowercase digits without `0x`
+    Hex(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    /// A double-quoted identifier, never a keyword
+    QuotedIdent(String),
+    Int(i128),
+    Float(f64),
+    Str(String),
+    Hex(String),
+    Op(CompareOp),
+    Star,
+    Dot,
+    LParen,
+    RParen,
+}
+
+impl Query {
+    /// Parse a query
+    pub(crate) fn parse(sql: &str) -> Result<Self, String> {
+        let mut parser = Parser {
+            tokens: tokenize(sql)?,
+            pos: 0,
+        };
+        let query = parser.query()?;
+        match parser.peek() {
+            None => Ok(query),
+            Some(token) => Err(format!("unexpected {:?} in `{}`", token, sql)),
+        }
+    }
+
+    /// Whether `row` is selected by this query
+    pub(crate) fn matches(&self, row: &Value) -> bool {
+        self.filter.as_ref().is_none_or(|filter| filter.eval(row))
+    }
+}
+
+impl Expr {
+    fn eval(&self, row: &Value) -> bool {
+        match self {
+            Expr::And(a, b) => a.eval(row) && b.eval(row),
+            Expr::Or(a, b) => a.eval(row) || b.eval(row),
+            Expr::Not(a) => !a.eval(row),
+            Expr::Compare(a, op, b) => {
+                let (Some(a), Some(b)) = (a.eval(row), b.eval(row)) else {
+                    return false;
+                };
+                let Some(ordering) = a.compare(&b) else {
+                    return false;
+                };
+                match op {
+                    CompareOp::Eq => ordering == Ordering::Equal,
+                    CompareOp::Ne => ordering != Ordering::Equal,
+                    CompareOp::Lt => ordering == Ordering::Less,
+                    CompareOp::Le => ordering != Ordering::Greater,
+                    CompareOp::Gt => ordering == Ordering::Greater,
+                    CompareOp::Ge => ordering != Ordering::Less,
+                }
+            }
+        }
+    }
+}
+
+impl Operand {
+    fn eval(&self, row: &Value) -> Option<Scalar> {
+        match self {
+            Operand::Column(name) => Scalar::from_json(row.get(name)?),
+            Operand::Literal(value) => Some(value.clone()),
+        }
+    }
+}
+
+impl Scalar {
+    /// Convert a column value, as encoded by the bridge
+    fn from_json(value: &Value) -> Option<Self> {
+        match value {
+            Value::Bool(b) => Some(Scalar::Bool(*b)),
+            Value::Number(n) => match n.as_i64() {
+                Some(i) => Some(Scalar::Int(i.into())),
+                None => match n.as_u64() {
+                    Some(u) => Some(Scalar::Int(u.into())),
+                    None => n.as_f64().map(Scalar::Float),
+                },
+            },
+            Value::String(s) => Some(Scalar::Str(s.clone())),
+            Value::Object(fields) if fields.len() == 1 => match fields.iter().next()? {
+                (key, Value::String(hex)) if key == "__identity__" => {
+                    Some(Scalar::Hex(normalize_hex(hex)))
+                }
+                (key, value)
+                    if key == "__connection_id__"
+                        || key == "__timestamp_micros_since_unix_epoch__"
+                        || key == "__time_duration_micros__" =>
+                {
+                    Scalar::from_json(value)
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn compare(&self, other: &Scalar) -> Option<Ordering> {
+        match (self, other) {
+            (Scalar::Int(a), Scalar::Int(b)) => Some(a.cmp(b)),
+            (Scalar::Int(a), Scalar::Float(b)) => (*a as f64).partial_cmp(b),
+            (Scalar::Float(a), Scalar::Int(b)) => a.partial_cmp(&(*b as f64)),
+            (Scalar::Float(a), Scalar::Float(b)) => a.partial_cmp(b),
+            (Scalar::Str(a), Scalar::Str(b)) => Some(a.cmp(b)),
+            (Scalar::Bool(a), Scalar::Bool(b)) => Some(a.cmp(b)),
+            (Scalar::Hex(a), Scalar::Hex(b)) => Some(compare_hex(a, b)),
+            (Scalar::Hex(hex), Scalar::Int(n)) => Some(compare_hex(hex, &format!("{:x}", n))),
+            (Scalar::Int(n), Scalar::Hex(hex)) => Some(compare_hex(&format!("{:x}", n), hex)),
+            _ => None,
+        }
+    }
+}
+
+fn normalize_hex(hex: &str) -> String {
+    hex.trim_start_matches("0x").to_ascii_lowercase()
+}
+
+/// Compare hex numbers of any width
+fn compare_hex(a: &str, b: &str) -> Ordering {
+    let a = a.trim_start_matches('0');
+    let b = b.trim_start_matches('0');
+    a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+}
+
+fn tokenize(sql: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = sql.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        i += 1;
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '*' => Token::Star,
+            '.' => Token::Dot,
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            '=' => Token::Op(CompareOp::Eq),
+            '!' if chars.get(i) == Some(&'=') => {
+                i += 1;
+                Token::Op(CompareOp::Ne)
+            }
+            '<' => match chars.get(i) {
+                Some('=') => {
+                    i += 1;
+                    Token::Op(CompareOp::Le)
+                }
+                Some('>') => {
+                    i += 1;
+                    Token::Op(CompareOp::Ne)
+                }
+                _ => Token::Op(CompareOp::Lt),
+            },
+            '>' => match chars.get(i) {
+                Some('=') => {
+                    i += 1;
+                    Token::Op(CompareOp::Ge)
+                }
+                _ => Token::Op(CompareOp::Gt),
+            },
+            '\'' => {
+                let mut value = String::new();
+                loop {
+                    match chars.get(i) {
+                        None => return Err(format!("unterminated string in `{}`", sql)),
+                        Some('\'') if chars.get(i + 1) == Some(&'\'') => {
+                            value.push('\'');
+                            i += 2;
+                        }
+                        Some('\'') => {
+                            i += 1;
+                            break;
+                        }
+                        Some(&c) => {
+                            value.push(c);
+                            i += 1;
+                        }
+                    }
+                }
+                Token::Str(value)
+            }
+            '"' => {
+                let mut name = String::new();
+                loop {
+                    match chars.get(i) {
+                        None => return Err(format!("unterminated identifier in `{}`", sql)),
+                        Some('"') if chars.get(i + 1) == Some(&'"') => {
+                            name.push('"');
+                            i += 2;
+                        }
+                        Some('"') => {
+                            i += 1;
+                            break;
+                        }
+                        Some(&c) => {
+                            name.push(c);
+                            i += 1;
+                        }
+                    }
+                }
+                Token::QuotedIdent(name)
+            }
+            '0' if matches!(chars.get(i), Some('x' | 'X')) => {
+                i += 1;
+                while chars.get(i).is_some_and(char::is_ascii_hexdigit) {
+                    i += 1;
+                }
+                let digits: String = chars[start + 2..i].iter().collect();
+                if digits.is_empty() {
+                    return Err(format!("empty hex literal in `{}`", sql));
+                }
+                Token::Hex(digits.to_ascii_lowercase())
+            }
+            c if c.is_ascii_digit()
+                || (c == '-' && chars.get(i).is_some_and(char::is_ascii_digit)) =>
+            {
+                while chars
+                    .get(i)
+                    .is_some_and(|c| c.is_ascii_digit() || matches!(c, '.' | 'e' | 'E'))
+                {
+                    i += 1;
+                }
+                let text: String = chars[start..i].iter().collect();
+                match text.parse::<i128>() {
+                    Ok(value) => Token::Int(value),
+                    Err(_) => Token::Float(
+                        text.parse()
+                            .map_err(|_| format!("invalid number `{}` in `{}`", text, sql))?,
+                    ),
+                }
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                while chars
+                    .get(i)
+                    .is_some_and(|c| c.is_alphanumeric() || *c == '_')
+                {
+                    i += 1;
+                }
+                Token::Ident(chars[start..i].iter().collect())
+            }
+            c => return Err(format!("unexpected `{}` in `{}`", c, sql)),
+        };
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Ident(word)) if word.eq_ignore_ascii_case(keyword))
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), String> {
+        if self.is_keyword(keyword) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(format!("expected {}, found {:?}", keyword, self.peek()))
+        }
+    }
+
+    fn ident(&mut self) -> Result<String, String> {
+        match self.next() {
+            Some(Token::Ident(name) | Token::QuotedIdent(name)) => Ok(name),
+            other => Err(format!("expected a name, found {:?}", other)),
+        }
+    }
+
+    fn query(&mut self) -> Result<Query, String> {
+        self.expect_keyword("SELECT")?;
+        // `*` or `table.*`
+        if let Some(Token::Ident(_)) = self.peek() {
+            self.pos += 1;
+            if self.next() != Some(Token::Dot) {
+                return Err("only `SELECT *` is supported".to_string());
+            }
+        }
+        if self.next() != Some(Token::Star) {
+            return Err("only `SELECT *` is supported".to_string());
+        }
+        self.expect_keyword("FROM")?;
+        let table = self.ident()?;
+
+        let filter = if self.is_keyword("WHERE") {
+            self.pos += 1;
+            Some(self.or()?)
+        } else {
+            None
+        };
+
+        Ok(Query { table, filter })
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.is_keyword("OR") {
+            self.pos += 1;
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.not()?;
+        while self.is_keyword("AND") {
+            self.pos += 1;
+            expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<Expr, String> {
+        if self.is_keyword("NOT") {
+            self.pos += 1;
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        if self.peek() == Some(&Token::LParen) {
+            self.pos += 1;
+            let expr = self.or()?;
+            if self.next() != Some(Token::RParen) {
+                return Err("expected `)`".to_string());
+            }
+            return Ok(expr);
+        }
+
+        let left = self.operand()?;
+        let op = match self.next() {
+            Some(Token::Op(op)) => op,
+            other => return Err(format!("expected a comparison, found {:?}", other)),
+        };
+        let right = self.operand()?;
+        Ok(Expr::Compare(left, op, right))
+    }
+
+    fn operand(&mut self) -> Result<Operand, String> {
+        Ok(match self.next() {
+            Some(Token::Int(value)) => Operand::Literal(Scalar::Int(value)),
+            Some(Token::Float(value)) => Operand::Literal(Scalar::Float(value)),
+            Some(Token::Str(value)) => Operand::Literal(Scalar::Str(value)),
+            Some(Token::Hex(value)) => Operand::Literal(Scalar::Hex(value)),
+            Some(Token::Ident(word)) if word.eq_ignore_ascii_case("true") => {
+                Operand::Literal(Scalar::Bool(true))
+            }
+            Some(Token::Ident(word)) if word.eq_ignore_ascii_case("false") => {
+                Operand::Literal(Scalar::Bool(false))
+            }
+            Some(Token::Ident(name) | Token::QuotedIdent(name)) => {
+                // `table.column`
+                if self.peek() == Some(&Token::Dot) {
+                    self.pos += 1;
+                    Operand::Column(self.ident()?)
+                } else {
+                    Operand::Column(name)
+                }
+            }
+            other => return Err(format!("expected a column or literal, found {:?}", other)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_builder_queries() {
+        let query = Query::parse(
+            r#"SELECT * FROM players WHERE ("name" = 'O''Brien' OR "id" < 3) AND "online" <> false"#,
+        )
+        .unwrap();
+        assert_eq!(query.table, "players");
+
+        assert!(query.matches(&json!({ "id": 9, "name": "O'Brien", "online": true })));
+        assert!(query.matches(&json!({ "id": 2, "name": "Ann", "online": true })));
+        assert!(!query.matches(&json!({ "id": 2, "name": "Ann", "online": false })));
+        assert!(!query.matches(&json!({ "id": 5, "name": "Ann", "online": true })));
+        assert!(
+            Query::parse("SELECT * FROM players")
+                .unwrap()
+                .matches(&json!({}))
+        );
+    }
+
+    #[test]
+    fn test_special_types() {
+        let owner = format!("{:064x}", 0xabc);
+        let query = Query::parse(&format!(
+            "SELECT players.* FROM players WHERE owner = 0x{}",
+            owner
+        ))
+        .unwrap();
+        assert!(query.matches(&json!({ "owner": { "__identity__": owner } })));
+        assert!(!query.matches(&json!({ "owner": { "__identity__": format!("{:064x}", 1) } })));
+
+        let query = Query::parse("SELECT * FROM t WHERE NOT score >= 1.5 AND t.ts > 10").unwrap();
+        assert!(query.matches(&json!({
+            "score": 1,
+            "ts": { "__timestamp_micros_since_unix_epoch__": 11 },
+        })));
+
+        let query = Query::parse(r#"SELECT * FROM t WHERE "type" = 'main'"#).unwrap();
+        assert!(query.matches(&json!({ "type": "main" })));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Query::parse("SELECT id FROM players").is_err());
+        assert!(Query::parse("SELECT * FROM players WHERE").is_err());
+        assert!(Query::parse("SELECT * FROM players WHERE name = 'x").is_err());
+        assert!(Query::parse(r#"SELECT * FROM players WHERE "name = 'x'"#).is_err());
+        assert!(Query::parse("DELETE FROM players").is_err());
+    }
+}
@@ -0,0 +1,268 @@
+//! In-memory tables and the transactions reducers run in
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::Column;
+
+/// The rows of one table
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TableSchema {
+    /// The column rows are identified by, so changes to it show up as updates
+    #[serde(default)]
+    pub primary_key: Option<String>,
+    /// The column types, needed to send rows to binary clients
+    #[serde(default)]
+    pub columns: Vec<Column>,
+    /// The initial rows, as JSON objects
+    #[serde(default)]
+    pub rows: Vec<Value>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Database {
+    pub(crate) tables: BTreeMap<String, TableSchema>,
+}
+
+/// The rows inserted and deleted in one table by a transaction
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct TableChanges {
+    pub(crate) inserts: Vec<Value>,
+    pub(crate) deletes: Vec<Value>,
+}
+
+impl Database {
+    pub(crate) fn table(&self, name: &str) -> Result<&TableSchema, String> {
+        self.tables
+            .get(name)
+            .ok_or_else(|| format!("no such table: {}", name))
+    }
+}
+
+/// Changes to the database made by a reducer
+///
+/// Nothing is visible to clients unless the reducer succeeds.
+pub struct Transaction {
+    db: Database,
+    changes: BTreeMap<String, TableChanges>,
+}
+
+impl Transaction {
+    pub(crate) fn new(db: Database) -> Self {
+        Self {
+            db,
+            changes: BTreeMap::new(),
+        }
+    }
+
+    pub(crate) fn finish(self) -> (Database, BTreeMap<String, TableChanges>) {
+        let changes = self
+            .changes
+            .into_iter()
+            .filter(|(_, changes)| !changes.inserts.is_empty() || !changes.deletes.is_empty())
+            .collect();
+        (self.db, changes)
+    }
+
+    fn table_mut(&mut self, name: &str) -> Result<&mut TableSchema, String> {
+        self.db
+            .tables
+            .get_mut(name)
+            .ok_or_else(|| format!("no such table: {}", name))
+    }
+
+    /// The rows of a table
+    pub fn rows(&self, table: &str) -> Result<&[Value], String> {
+        Ok(&self.db.table(table)?.rows)
+    }
+
+    /// Find a row by primary key
+    pub fn find(&self, table: &str, key: impl Serialize) -> Result<Option<&Value>, String> {
+        let key = to_json(key)?;
+        let table = self.db.table(table)?;
+        let column = primary_key(table)?;
+        Ok(table.rows.iter().find(|row| row.get(column) == Some(&key)))
+    }
+
+    /// Insert a row
+    ///
+    /// Fails if the table has a primary key and a row with the same key exists.
+    pub fn insert(&mut self, table: &str, row: impl Serialize) -> Result<(), String> {
+        let row = to_json(row)?;
+        let rows = self.table_mut(table)?;
+        if let Some(column) = &rows.primary_key {
+            let key = row.get(column);
+            if rows.rows.iter().any(|existing| existing.get(column) == key) {
+                return Err(format!(
+                    "duplicate primary key in {}: {}",
+                    table,
+                    display(key)
+                ));
+            }
+        }
+        rows.rows.push(row.clone());
+        self.record(table, |changes| {
+            changes.inserts.push(row);
+        });
+        Ok(())
+    }
+
+    /// Replace the row with the same primary key
+    pub fn update(&mut self, table: &str, row: impl Serialize) -> Result<(), String> {
+        let row = to_json(row)?;
+        let rows = self.table_mut(table)?;
+        let column = primary_key(rows)?.to_string();
+        let key = row.get(&column);
+        let Some(index) = rows
+            .rows
+            .iter()
+            .position(|existing| existing.get(&column) == key)
+        else {
+            return Err(format!(
+                "no row in {} with {} = {}",
+                table,
+                column,
+                display(key)
+            ));
+        };
+        let old = std::mem::replace(&mut rows.rows[index], row.clone());
+        self.record(table, |changes| {
+            remove_or_push(&mut changes.inserts, &mut changes.deletes, old);
+            changes.inserts.push(row);
+        });
+        Ok(())
+    }
+
+    /// Delete the row with the given primary key, returning it
+    pub fn delete_by_key(
+        &mut self,
+        table: &str,
+        key: impl Serialize,
+    ) -> Result<Option<Value>, String> {
+        let key = to_json(key)?;
+        let rows = self.table_mut(table)?;
+        let column = primary_key(rows)?.to_string();
+        let index = rows
+            .rows
+            .iter()
+            .position(|row| row.get(&column) == Some(&key));
+        Ok(index.map(|index| self.remove(table, index)))
+    }
+
+    /// Delete a row equal to `row`, returning whether there was one
+    pub fn delete(&mut self, table: &str, row: impl Serialize) -> Result<bool, String> {
+        let row = to_json(row)?;
+        let index = self
+            .table_mut(table)?
+            .rows
+            .iter()
+            .position(|existing| *existing == row);
+        Ok(index.map(|index| self.remove(table, index)).is_some())
+    }
+
+    fn remove(&mut self, table: &str, index: usize) -> Value {
+        let row = self.db.tables.get_mut(table).unwrap().rows.remove(index);
+        let deleted = row.clone();
+        self.record(table, |changes| {
+            remove_or_push(&mut changes.inserts, &mut changes.deletes, deleted);
+        });
+        row
+    }
+
+    fn record(&mut self, table: &str, change: impl FnOnce(&mut TableChanges)) {
+        change(self.changes.entry(table.to_string()).or_default());
+    }
+}
+
+/// Delete `row`: undo its insertion in this transaction, or record the delete
+fn remove_or_push(inserts: &mut Vec<Value>, deletes: &mut Vec<Value>, row: Value) {
+    match inserts.iter().position(|inserted| *inserted == row) {
+        Some(index) => {
+            inserts.remove(index);
+        }
+        None => deletes.push(row),
+    }
+}
+
+fn primary_key(table: &TableSchema) -> Result<&str, String> {
+    table
+        .primary_key
+        .as_deref()
+        .ok_or_else(|| "table has no primary key".to_string())
+}
+
+fn display(value: Option<&Value>) -> String {
+    value.map_or_else(|| "null".to_string(), Value::to_string)
+}
+
+fn to_json(value: impl Serialize) -> Result<Value, String> {
+    serde_json::to_value(value).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn database() -> Database {
+        let mut db = Database::default();
+        db.tables.insert(
+            "player".to_string(),
+            TableSchema {
+                primary_key: Some("id".to_string()),
+                columns: Vec::new(),
+                rows: vec![json!({ "id": 1, "name": "Alice" })],
+            },
+        );
+        db
+    }
+
+    #[test]
+    fn test_changes() {
+        let mut tx = Transaction::new(database());
+        tx.insert("player", json!({ "id": 2, "name": "Bob" }))
+            .unwrap();
+        tx.update("player", json!({ "id": 2, "name": "Robert" }))
+            .unwrap();
+        tx.update("player", json!({ "id": 1, "name": "Alicia" }))
+            .unwrap();
+        assert!(
+            tx.insert("player", json!({ "id": 1, "name": "Eve" }))
+                .is_err()
+        );
+        assert!(tx.insert("nope", json!({})).is_err());
+
+        let (db, changes) = tx.finish();
+        assert_eq!(db.tables["player"].rows.len(), 2);
+        assert_eq!(
+            changes["player"],
+            TableChanges {
+                inserts: vec![
+                    json!({ "id": 2, "name": "Robert" }),
+                    json!({ "id": 1, "name": "Alicia" }),
+                ],
+                deletes: vec![json!({ "id": 1, "name": "Alice" })],
+            }
+        );
+    }
+
+    #[test]
+    fn test_delete() {
+        let mut tx = Transaction::new(database());
+        assert_eq!(tx.find("player", 1).unwrap().unwrap()["name"], "Alice");
+        assert_eq!(tx.delete_by_key("player", 3).unwrap(), None);
+        assert!(tx.delete_by_key("player", 1).unwrap().is_some());
+        tx.insert("player", json!({ "id": 4 })).unwrap();
+        assert!(tx.delete("player", json!({ "id": 4 })).unwrap());
+
+        let (db, changes) = tx.finish();
+        assert!(db.tables["player"].rows.is_empty());
+        assert_eq!(
+            changes["player"].deletes,
+            vec![json!({ "id": 1, "name": "Alice" })]
+        );
+        assert!(changes["player"].inserts.is_empty());
+    }
+}
@@ -0,0 +1,314 @@
+//! Column types, and the module definition clients build their bindings from
+//!
+//! Rows are kept as JSON, so types are only needed to speak the binary protocol
+//! and to describe the module. They are written in schema files as strings:
+//! `"u32"`, `"string"`, `"identity"`, `"bytes"`, `"array<u64>"`,
+//! `"option<string>"`, ...
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::TableSchema;
+
+/// The type of a column or reducer parameter
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum ColumnType {
+    Bool,
+    U8,
+    U16,
+    U32,
+    U64,
+    U128,
+    I8,
+    I16,
+    I32,
+    I64,
+    I128,
+    F32,
+    F64,
+    String,
+    /// `{ "__identity__": "<64 hex digits>" }`
+    Identity,
+    /// `{ "__connection_id__": <number or decimal string> }`
+    ConnectionId,
+    /// `{ "__timestamp_micros_since_unix_epoch__": <number> }`
+    Timestamp,
+    /// `{ "__time_duration_micros__": <number> }`
+    TimeDuration,
+    /// An array, e.g. `array<u8>` (also written `bytes`)
+    Array(Box<ColumnType>),
+    /// A value or `null`
+    Option(Box<ColumnType>),
+}
+
+impl FromStr for ColumnType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let generic = |name: &str| {
+            s.strip_prefix(name)
+                .and_then(|rest| rest.strip_prefix('<'))
+                .and_then(|rest| rest.strip_suffix('>'))
+        };
+        if let Some(element) = generic("array") {
+            return Ok(ColumnType::Array(Box::new(element.parse()?)));
+        }
+        if let Some(inner) = generic("option") {
+            return Ok(ColumnType::Option(Box::new(inner.parse()?)));
+        }
+        Ok(match s {
+            "bool" => ColumnType::Bool,
+            "u8" => ColumnType::U8,
+            "u16" => ColumnType::U16,
+            "u32" => ColumnType::U32,
+            "u64" => ColumnType::U64,
+            "u128" => ColumnType::U128,
+            "i8" => ColumnType::I8,
+            "i16" => ColumnType::I16,
+            "i32" => ColumnType::I32,
+            "i64" => ColumnType::I64,
+            "i128" => ColumnType::I128,
+            "f32" => ColumnType::F32,
+            "f64" => ColumnType::F64,
+            "string" => ColumnType::String,
+            "bytes" => ColumnType::Array(Box::new(ColumnType::U8)),
+            "identity" => ColumnType::Identity,
+            "connection_id" => ColumnType::ConnectionId,
+            "timestamp" => ColumnType::Timestamp,
+            "time_duration" => ColumnType::TimeDuration,
+            _ => return Err(format!("unknown column type: {}", s)),
+        })
+    }
+}
+
+impl fmt::Display for ColumnType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ColumnType::Bool => "bool",
+            ColumnType::U8 => "u8",
+            ColumnType::U16 => "u16",
+            ColumnType::U32 => "u32",
+            ColumnType::U64 => "u64",
+            ColumnType::U128 => "u128",
+            ColumnType::I8 => "i8",
+            ColumnType::I16 => "i16",
+            ColumnType::I32 => "i32",
+            ColumnType::I64 => "i64",
+            ColumnType::I128 => "i128",
+            ColumnType::F32 => "f32",
+            ColumnType::F64 => "f64",
+            ColumnType::String => "string",
+            ColumnType::Identity => "identity",
+            ColumnType::ConnectionId => "connection_id",
+            ColumnType::Timestamp => "timestamp",
+            ColumnType::TimeDuration => "time_duration",
+            ColumnType::Array(element) => return write!(f, "array<{}>", element),
+            ColumnType::Option(inner) => return write!(f, "option<{}>", inner),
+        };
+        f.write_str(name)
+    }
+}
+
+impl TryFrom<String> for ColumnType {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<ColumnType> for String {
+    fn from(ty: ColumnType) -> Self {
+        ty.to_string()
+    }
+}
+
+impl ColumnType {
+    /// The type as a SATS `AlgebraicType`, in the JSON form of module definitions
+    pub(crate) fn algebraic_type(&self) -> Value {
+        let primitive = |name: &str| json!({ name: [] });
+        let special = |field: &str, ty: &str| {
+            json!({ "Product": { "elements": [
+                { "name": { "some": field }, "algebraic_type": primitive(ty) }
+            ] } })
+        };
+        match self {
+            ColumnType::Bool => primitive("Bool"),
+            ColumnType::U8 => primitive("U8"),
+            ColumnType::U16 => primitive("U16"),
+            ColumnType::U32 => primitive("U32"),
+            ColumnType::U64 => primitive("U64"),
+            ColumnType::U128 => primitive("U128"),
+            ColumnType::I8 => primitive("I8"),
+            ColumnType::I16 => primitive("I16"),
+            ColumnType::I32 => primitive("I32"),
+            ColumnType::I64 => primitive("I64"),
+            ColumnType::I128 => primitive("I128"),
+            ColumnType::F32 => primitive("F32"),
+            ColumnType::F64 => primitive("F64"),
+            ColumnType::String => primitive("String"),
+            ColumnType::Identity => special("__identity__", "U256"),
+            ColumnType::ConnectionId => special("__connection_id__", "U128"),
+            ColumnType::Timestamp => special("__timestamp_micros_since_unix_epoch__", "I64"),
+            ColumnType::TimeDuration => special("__time_duration_micros__", "I64"),
+            ColumnType::Array(element) => json!({ "Array": element.algebraic_type() }),
+            ColumnType::Option(inner) => json!({ "Sum": { "variants": [
+                { "name": { "some": "some" }, "algebraic_type": inner.algebraic_type() },
+                { "name": { "some": "none" }, "algebraic_type": { "Product": { "elements": [] } } },
+            ] } }),
+        }
+    }
+}
+
+/// A named column or reducer parameter, written `["name", "type"]`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "(String, ColumnType)", into = "(String, ColumnType)")]
+pub struct Column {
+    pub name: String,
+    pub ty: ColumnType,
+}
+
+impl From<(String, ColumnType)> for Column {
+    fn from((name, ty): (String, ColumnType)) -> Self {
+        Self { name, ty }
+    }
+}
+
+impl From<Column> for (String, ColumnType) {
+    fn from(column: Column) -> Self {
+        (column.name, column.ty)
+    }
+}
+
+/// Parse `(name, type)` pairs, as given to `FakeServer::columns`
+///
+/// # Panics
+///
+/// Panics if a type does not parse.
+pub(crate) fn columns(pairs: &[(&str, &str)]) -> Vec<Column> {
+    pairs
+        .iter()
+        .map(|(name, ty)| Column {
+            name: name.to_string(),
+            ty: ty.parse().unwrap_or_else(|e| panic!("{}", e)),
+        })
+        .collect()
+}
+
+fn product(columns: &[Column]) -> Value {
+    let elements = columns
+        .iter()
+        .map(|column| {
+            json!({ "name": { "some": column.name }, "algebraic_type": column.ty.algebraic_type() })
+        })
+        .collect::<Vec<_>>();
+    json!({ "elements": elements })
+}
+
+/// The module definition (`RawModuleDefV9`), as served by `/v1/database/<module>/schema`
+///
+/// Only tables with column types and reducers with parameter types are listed.
+pub(crate) fn module_def(
+    tables: &BTreeMap<String, TableSchema>,
+    reducers: &BTreeMap<String, Vec<Column>>,
+) -> Value {
+    let mut types = Vec::new();
+    let mut table_defs = Vec::new();
+    for (name, table) in tables {
+        if table.columns.is_empty() {
+            continue;
+        }
+        let primary_key = table
+            .primary_key
+            .as_ref()
+            .and_then(|key| table.columns.iter().position(|column| column.name == *key))
+            .into_iter()
+            .collect::<Vec<_>>();
+        table_defs.push(json!({
+            "name": name,
+            "product_type_ref": types.len(),
+            "primary_key": primary_key,
+            "indexes": [],
+            "constraints": [],
+            "sequences": [],
+            "schedule": { "none": [] },
+            "table_type": { "User": [] },
+            "table_access": { "Public": [] },
+        }));
+        types.push(json!({ "Product": product(&table.columns) }));
+    }
+
+    let reducer_defs = reducers
+        .iter()
+        .map(|(name, params)| {
+            json!({ "name": name, "params": product(params), "lifecycle": { "none": [] } })
+        })
+        .collect::<Vec<_>>();
+
+    json!({
+        "typespace": { "types": types },
+        "tables": table_defs,
+        "reducers": reducer_defs,
+        "types": [],
+        "misc_exports": [],
+        "row_level_security": [],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_column_types_parse() {
+        for name in [
+            "u32",
+            "string",
+            "identity",
+            "array<option<i64>>",
+            "time_duration",
+        ] {
+            assert_eq!(name.parse::<ColumnType>().unwrap().to_string(), name);
+        }
+        assert_eq!(
+            "bytes".parse::<ColumnType>().unwrap(),
+            ColumnType::Array(Box::new(ColumnType::U8))
+        );
+        assert!("u31".parse::<ColumnType>().is_err());
+        assert!("array<u8".parse::<ColumnType>().is_err());
+
+        let column: Column = serde_json::from_value(json!(["id", "u64"])).unwrap();
+        assert_eq!(column.ty, ColumnType::U64);
+    }
+
+    #[test]
+    fn test_module_def() {
+        let mut tables = BTreeMap::new();
+        tables.insert(
+            "player".to_string(),
+            TableSchema {
+                primary_key: Some("id".to_string()),
+                columns: columns(&[("name", "string"), ("id", "u32")]),
+                rows: Vec::new(),
+            },
+        );
+        tables.insert("untyped".to_string(), TableSchema::default());
+        let mut reducers = BTreeMap::new();
+        reducers.insert("kick".to_string(), columns(&[("id", "u32")]));
+
+        let def = module_def(&tables, &reducers);
+        assert_eq!(def["tables"].as_array().unwrap().len(), 1);
+        assert_eq!(def["tables"][0]["primary_key"], json!([1]));
+        assert_eq!(
+            def["typespace"]["types"][0]["Product"]["elements"][1],
+            json!({ "name": { "some": "id" }, "algebraic_type": { "U32": [] } })
+        );
+        assert_eq!(def["reducers"][0]["name"], "kick");
+    }
+}
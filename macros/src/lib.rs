@@ -1,84 +1,466 @@
 use heck::ToSnakeCase;
 use proc_macro::TokenStream;
-use quote::quote;
-use syn::{parse_macro_input, Data, DeriveInput, Fields, Ident};
+use quote::{format_ident, quote, quote_spanned};
+use syn::{
+    ext::IdentExt, parse_macro_input, punctuated::Punctuated, spanned::Spanned, Attribute, Data,
+    DeriveInput, Expr, ExprLit, Fields, Lit, LitStr, Meta, Token,
+};
 
-/// This macro automatically generates the boilerplate code needed to register a reducer
-/// with the `StdbPlugin`.
+/// Derives `RegisterableReducerEvent` for a struct describing the runs of a reducer,
+/// to register it with `StdbPlugin::add_reducer`.
 ///
 /// ## Requirements
 ///
-/// - The struct must have exactly one field named `event` of type `ReducerEvent<Reducer>`
+/// - The struct must have exactly one field named `event` of type `ReducerEvent`
 /// - All other fields must match the reducer's parameter types and order
 /// - Struct fields must be named (no tuple structs)
 ///
+/// ## Attributes
+///
+/// - `#[stdb(reducer = "name")]` on the struct sets the reducer name. Defaults to
+///   the snake_case struct name.
+///
 /// ## Example
 ///
-///```no-run
+///```ignore
 /// #[derive(RegisterReducerEvent)]
 /// pub struct SetName {
-///     pub event: ReducerEvent<Reducer>,
+///     pub event: ReducerEvent,
 ///     pub name: String,
 /// }
 /// ```
-#[proc_macro_derive(RegisterReducerEvent)]
+#[proc_macro_derive(RegisterReducerEvent, attributes(stdb))]
 pub fn register_reducer_event_derive(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
+    expand_register_reducer_event(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand_register_reducer_event(input: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
     let struct_name = &input.ident;
-    let struct_name_str = struct_name.to_string();
-
-    // Derive callback name directly from struct name (no suffix stripping)
-    let function_name = Ident::new(
-        &format!("on_{}", struct_name_str.to_snake_case()),
-        struct_name.span(),
-    );
-
-    // Extract named fields
-    let fields = match input.data {
-        Data::Struct(data_struct) => match data_struct.fields {
-            Fields::Named(fields_named) => fields_named.named,
-            _ => panic!("Struct must have named fields"),
+
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &input.generics,
+            "RegisterReducerEvent cannot be derived for generic structs",
+        ));
+    }
+
+    let mut reducer_name = None;
+    for attr in input.attrs.iter().filter(|attr| attr.path().is_ident("stdb")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("reducer") {
+                let name: LitStr = meta.value()?.parse()?;
+                reducer_name = Some(name.value());
+                Ok(())
+            } else {
+                Err(meta.error("unknown reducer attribute, expected `reducer = \"...\"`"))
+            }
+        })?;
+    }
+    let reducer_name = reducer_name.unwrap_or_else(|| struct_name.to_string().to_snake_case());
+
+    let fields = match &input.data {
+        Data::Struct(data_struct) => match &data_struct.fields {
+            Fields::Named(fields_named) => &fields_named.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    struct_name,
+                    "RegisterReducerEvent can only be derived for structs with named fields",
+                ))
+            }
         },
-        _ => panic!("Only structs are supported"),
+        _ => {
+            return Err(syn::Error::new_spanned(
+                struct_name,
+                "RegisterReducerEvent can only be derived for structs",
+            ))
+        }
     };
 
     // Separate 'event' field from reducer parameters
-    let mut event_field = None;
-    let mut param_fields = Vec::new();
+    let mut has_event = false;
     let mut param_idents = Vec::new();
+    let mut param_types = Vec::new();
 
     for field in fields {
         let field_ident = field.ident.as_ref().expect("Field must have identifier");
         if field_ident == "event" {
-            if event_field.is_some() {
-                panic!("Duplicate 'event' field");
+            if has_event {
+                return Err(syn::Error::new_spanned(
+                    field_ident,
+                    "duplicate `event` field",
+                ));
             }
-            event_field = Some(field);
+            has_event = true;
         } else {
-            param_idents.push(field_ident.clone());
-            param_fields.push(field);
+            param_idents.push(field_ident);
+            param_types.push(&field.ty);
         }
     }
 
-    if event_field.is_none() {
-        panic!("Struct must have an 'event' field");
+    if !has_event {
+        return Err(syn::Error::new_spanned(
+            struct_name,
+            "RegisterReducerEvent requires an `event: ReducerEvent` field",
+        ));
+    }
+
+    // The bridge passes the arguments as an array, which `()` can't be decoded from
+    let decode_args = if param_idents.is_empty() {
+        quote! { let _ = args; }
+    } else {
+        quote! {
+            let (#(#param_idents,)*): (#(#param_types,)*) = args.decode()?;
+        }
+    };
+
+    Ok(quote! {
+        impl ::bevy_spacetimedb_wasm::RegisterableReducerEvent for #struct_name {
+            const REDUCER_NAME: &'static str = #reducer_name;
+
+            fn from_reducer_event(
+                event: ::bevy_spacetimedb_wasm::ReducerEvent,
+                args: ::bevy_spacetimedb_wasm::BackendValue,
+            ) -> ::core::result::Result<Self, ::bevy_spacetimedb_wasm::CodecError> {
+                #decode_args
+                ::core::result::Result::Ok(Self {
+                    event,
+                    #(#param_idents),*
+                })
+            }
+        }
+    })
+}
+
+/// Derives `TableRow` (and `HasPrimaryKey` when a primary key is marked) for a struct
+/// mirroring a SpacetimeDB table.
+///
+/// ## Attributes
+///
+/// - `#[stdb(table = "name")]` on the struct sets the table name. Defaults to the
+///   snake_case struct name.
+/// - `#[stdb(primary_key)]` marks the primary key column (at most one).
+/// - `#[stdb(unique)]` marks a column with a unique constraint.
+/// - `#[stdb(index)]` marks an indexed column.
+///
+/// The struct must implement `Deserialize` and `Clone`, which is checked at compile time.
+///
+/// Column names follow `#[serde(rename = "...")]` on fields and
+/// `#[serde(rename_all = "...")]` on the struct, as rows are decoded with them.
+///
+/// Also derives `HasColumns`, so columns can be referenced in typed queries with
+/// `col!(Player::lobby_id)`.
+///
+/// ## Example
+///
+///```ignore
+/// #[derive(Clone, Deserialize, TableRow)]
+/// #[stdb(table = "players")]
+/// pub struct Player {
+///     #[stdb(primary_key)]
+///     pub id: u64,
+///     #[stdb(unique)]
+///     pub name: String,
+///     #[stdb(index)]
+///     pub lobby_id: u64,
+/// }
+/// ```
+#[proc_macro_derive(TableRow, attributes(stdb))]
+pub fn table_row_derive(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_table_row(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand_table_row(input: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let struct_name = &input.ident;
+
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &input.generics,
+            "TableRow cannot be derived for generic structs",
+        ));
+    }
+
+    let mut table_name = None;
+    for attr in input.attrs.iter().filter(|attr| attr.path().is_ident("stdb")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("table") {
+                let name: LitStr = meta.value()?.parse()?;
+                table_name = Some(name.value());
+                Ok(())
+            } else {
+                Err(meta.error("unknown table attribute, expected `table = \"...\"`"))
+            }
+        })?;
+    }
+    let table_name = table_name.unwrap_or_else(|| struct_name.to_string().to_snake_case());
+    let rename_all = match serde_rename(&input.attrs, "rename_all")? {
+        Some(rule) => Some(RenameRule::parse(&rule)?),
+        None => None,
+    };
+
+    let fields = match &input.data {
+        Data::Struct(data_struct) => match &data_struct.fields {
+            Fields::Named(fields_named) => &fields_named.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    struct_name,
+                    "TableRow can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                struct_name,
+                "TableRow can only be derived for structs",
+            ))
+        }
+    };
+
+    let mut primary_key = None;
+    let mut unique_columns = Vec::new();
+    let mut indexed_columns = Vec::new();
+    let mut column_accessors = Vec::new();
+
+    for field in fields {
+        let field_ident = field.ident.as_ref().expect("Field must have identifier");
+        let column_name = match (serde_rename(&field.attrs, "rename")?, &rename_all) {
+            (Some(name), _) => name.value(),
+            (None, Some(rule)) => rule.apply(&field_ident.unraw().to_string()),
+            (None, None) => field_ident.unraw().to_string(),
+        };
+
+        let field_ty = &field.ty;
+        column_accessors.push(quote! {
+            pub fn #field_ident(&self) -> ::bevy_spacetimedb_wasm::Column<#struct_name, #field_ty> {
+                ::bevy_spacetimedb_wasm::Column::new(#column_name)
+            }
+        });
+
+        for attr in field.attrs.iter().filter(|attr| attr.path().is_ident("stdb")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("primary_key") {
+                    if primary_key.is_some() {
+                        return Err(meta.error("only one column can be the primary key"));
+                    }
+                    primary_key = Some((field_ident, &field.ty, column_name.clone()));
+                    Ok(())
+                } else if meta.path.is_ident("unique") {
+                    unique_columns.push(column_name.clone());
+                    Ok(())
+                } else if meta.path.is_ident("index") {
+                    indexed_columns.push(column_name.clone());
+                    Ok(())
+                } else {
+                    Err(meta.error(
+                        "unknown column attribute, expected `primary_key`, `unique` or `index`",
+                    ))
+                }
+            })?;
+        }
     }
 
-    // Generate the implementation
-    let expanded = quote! {
-        impl bevy_spacetimedb::RegisterableReducerEvent<DbConnection, RemoteModule> for #struct_name {
-            fn set_stdb_callback(reducers: &RemoteReducers, sender: std::sync::mpsc::Sender<ReducerResultEvent<Self>>) {
-                reducers.#function_name(move |ctx, #(#param_idents),*| {
-                    sender
-                        .send(ReducerResultEvent::new(#struct_name {
-                            event: ctx.event.clone(),
-                            #(#param_idents: #param_idents.clone()),*
-                        }))
-                        .unwrap();
-                });
+    let primary_key_name = match &primary_key {
+        Some((_, _, name)) => quote! { ::core::option::Option::Some(#name) },
+        None => quote! { ::core::option::Option::None },
+    };
+
+    let primary_key_impl = primary_key.as_ref().map(|(field_ident, field_ty, _)| {
+        quote! {
+            impl ::bevy_spacetimedb_wasm::HasPrimaryKey for #struct_name {
+                type PrimaryKey = #field_ty;
+
+                fn primary_key(&self) -> Self::PrimaryKey {
+                    ::core::clone::Clone::clone(&self.#field_ident)
+                }
+            }
+        }
+    });
+
+    let assert_bounds = quote_spanned! {struct_name.span()=>
+        const _: () = {
+            fn assert_table_row_bounds<
+                T: ::bevy_spacetimedb_wasm::__private::serde::de::DeserializeOwned
+                    + ::core::clone::Clone,
+            >() {
+            }
+            #[allow(dead_code)]
+            fn check() {
+                assert_table_row_bounds::<#struct_name>();
             }
+        };
+    };
+
+    let vis = &input.vis;
+    let columns_name = format_ident!("__{}Columns", struct_name);
+    let columns_impl = quote! {
+        #[doc(hidden)]
+        #vis struct #columns_name;
+
+        impl #columns_name {
+            #(#column_accessors)*
+        }
+
+        impl ::bevy_spacetimedb_wasm::HasColumns for #struct_name {
+            type Columns = #columns_name;
+            const COLUMNS: Self::Columns = #columns_name;
         }
     };
 
-    TokenStream::from(expanded)
+    Ok(quote! {
+        #assert_bounds
+
+        impl ::bevy_spacetimedb_wasm::TableRow for #struct_name {
+            const TABLE_NAME: &'static str = #table_name;
+            const PRIMARY_KEY: ::core::option::Option<&'static str> = #primary_key_name;
+            const UNIQUE_COLUMNS: &'static [&'static str] = &[#(#unique_columns),*];
+            const INDEXED_COLUMNS: &'static [&'static str] = &[#(#indexed_columns),*];
+        }
+
+        #primary_key_impl
+
+        #columns_impl
+    })
+}
+
+/// Find the name serde deserializes with from `#[serde(<key> = "...")]` or
+/// `#[serde(<key>(deserialize = "..."))]`
+fn serde_rename(attrs: &[Attribute], key: &str) -> syn::Result<Option<LitStr>> {
+    let mut name = None;
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("serde")) {
+        let metas = attr.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)?;
+        for meta in metas.iter().filter(|meta| meta.path().is_ident(key)) {
+            match meta {
+                Meta::NameValue(value) => name = Some(lit_str(&value.value)?),
+                Meta::List(list) => {
+                    let nested =
+                        list.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)?;
+                    for meta in nested.iter().filter(|meta| meta.path().is_ident("deserialize")) {
+                        name = Some(lit_str(&meta.require_name_value()?.value)?);
+                    }
+                }
+                Meta::Path(_) => {
+                    return Err(syn::Error::new_spanned(
+                        meta,
+                        format!("expected `{key} = \"...\"`"),
+                    ))
+                }
+            }
+        }
+    }
+    Ok(name)
+}
+
+fn lit_str(expr: &Expr) -> syn::Result<LitStr> {
+    match expr {
+        Expr::Lit(ExprLit {
+            lit: Lit::Str(lit), ..
+        }) => Ok(lit.clone()),
+        _ => Err(syn::Error::new_spanned(expr, "expected a string literal")),
+    }
+}
+
+/// A serde `rename_all` rule, applied to snake_case field names
+enum RenameRule {
+    Lower,
+    Upper,
+    Pascal,
+    Camel,
+    Snake,
+    ScreamingSnake,
+    Kebab,
+    ScreamingKebab,
+}
+
+impl RenameRule {
+    fn parse(rule: &LitStr) -> syn::Result<Self> {
+        Ok(match rule.value().as_str() {
+            "lowercase" => Self::Lower,
+            "UPPERCASE" => Self::Upper,
+            "PascalCase" => Self::Pascal,
+            "camelCase" => Self::Camel,
+            "snake_case" => Self::Snake,
+            "SCREAMING_SNAKE_CASE" => Self::ScreamingSnake,
+            "kebab-case" => Self::Kebab,
+            "SCREAMING-KEBAB-CASE" => Self::ScreamingKebab,
+            _ => return Err(syn::Error::new_spanned(rule, "unknown rename_all rule")),
+        })
+    }
+
+    /// Rename a field the way serde does
+    fn apply(&self, field: &str) -> String {
+        match self {
+            Self::Lower | Self::Snake => field.to_string(),
+            Self::Upper | Self::ScreamingSnake => field.to_ascii_uppercase(),
+            Self::Pascal | Self::Camel => {
+                let mut pascal = String::new();
+                let mut capitalize = true;
+                for ch in field.chars() {
+                    if ch == '_' {
+                        capitalize = true;
+                    } else if capitalize {
+                        pascal.push(ch.to_ascii_uppercase());
+                        capitalize = false;
+                    } else {
+                        pascal.push(ch);
+                    }
+                }
+                if matches!(self, Self::Camel) {
+                    pascal[..1].to_ascii_lowercase() + &pascal[1..]
+                } else {
+                    pascal
+                }
+            }
+            Self::Kebab => field.replace('_', "-"),
+            Self::ScreamingKebab => field.to_ascii_uppercase().replace('_', "-"),
+        }
+    }
+}
+
+/// Makes an enum (de)serialize like a SpacetimeDB sum type in the TypeScript SDK.
+///
+/// The SDK represents sum type values as `{ tag: "Variant", value: ... }` objects,
+/// with `value` omitted for unit variants. This attribute adds
+/// `#[serde(tag = "tag", content = "value")]` to the enum so rows and reducer
+/// arguments use that encoding instead of serde's externally tagged default.
+///
+/// Place it above the `#[derive(Serialize, Deserialize)]` attribute.
+///
+/// ## Example
+///
+///```ignore
+/// #[sum_type]
+/// #[derive(Debug, Clone, Serialize, Deserialize)]
+/// pub enum BodyTypes {
+///     Star,
+///     Planet(u32),
+///     Moon { parent: u64 },
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn sum_type(attr: TokenStream, item: TokenStream) -> TokenStream {
+    if !attr.is_empty() {
+        return syn::Error::new(
+            proc_macro2::TokenStream::from(attr).span(),
+            "sum_type does not take arguments",
+        )
+        .into_compile_error()
+        .into();
+    }
+
+    let mut input = parse_macro_input!(item as DeriveInput);
+    if !matches!(input.data, Data::Enum(_)) {
+        return syn::Error::new_spanned(&input.ident, "sum_type can only be applied to enums")
+            .into_compile_error()
+            .into();
+    }
+
+    input
+        .attrs
+        .push(syn::parse_quote!(#[serde(tag = "tag", content = "value")]));
+    quote!(#input).into()
 }